@@ -0,0 +1,188 @@
+use crate::ipc::HopResponse;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::{self, File, Metadata};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub enum WriteError {
+    /// The file changed since the caller last saw it.
+    Conflict(String),
+    /// The file does not exist and the caller did not ask to create it.
+    NotFound(String),
+    Io(String),
+}
+
+impl WriteError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            WriteError::Conflict(_) => "conflict",
+            WriteError::NotFound(_) => "not_found",
+            WriteError::Io(_) => "io",
+        }
+    }
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteError::Conflict(reason) => write!(f, "Write conflict: {reason}"),
+            WriteError::NotFound(path) => write!(f, "File not found: {path}"),
+            WriteError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for WriteError {
+    fn from(e: std::io::Error) -> Self {
+        WriteError::Io(e.to_string())
+    }
+}
+
+impl From<WriteError> for HopResponse {
+    fn from(e: WriteError) -> Self {
+        HopResponse::Error { ok: false, code: Some(e.code().into()), error: e.to_string() }
+    }
+}
+
+/// Hex SHA-256 of `bytes`; this is the `hash` reported by `fs.read` and
+/// expected back in `expected_hash`.
+pub fn content_hash(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+pub fn modified_ms(meta: &Metadata) -> Option<i64> {
+    meta.modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|dur| dur.as_millis() as i64)
+}
+
+/// What the caller believes the file currently looks like. A missing file
+/// never matches an expectation.
+#[derive(Debug, Default)]
+pub struct Precondition {
+    pub expected_hash: Option<String>,
+    pub expected_mtime: Option<i64>,
+}
+
+impl Precondition {
+    fn check(&self, path: &Path, meta: Option<&Metadata>) -> Result<(), WriteError> {
+        if self.expected_hash.is_none() && self.expected_mtime.is_none() {
+            return Ok(());
+        }
+        let meta = meta.ok_or_else(|| WriteError::Conflict(format!("{} no longer exists", path.display())))?;
+        if let Some(expected) = self.expected_mtime {
+            let actual = modified_ms(meta);
+            if actual != Some(expected) {
+                return Err(WriteError::Conflict(format!("{} was modified at {} (expected {expected})", path.display(), actual.unwrap_or(0))));
+            }
+        }
+        if let Some(expected) = &self.expected_hash {
+            if !content_hash(&fs::read(path)?).eq_ignore_ascii_case(expected) {
+                return Err(WriteError::Conflict(format!("{} has different content than expected", path.display())));
+            }
+        }
+        Ok(())
+    }
+}
+
+const TEMP_SUFFIX: &str = ".hopcoder-tmp";
+
+/// Sibling temp path in the same directory, so the final rename never
+/// crosses a file system.
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!(".{name}.{}{TEMP_SUFFIX}", Uuid::new_v4().simple()))
+}
+
+/// True for the temp files `write` creates, so watchers can hide them.
+pub fn is_temp_path(path: &Path) -> bool {
+    path.file_name().map(|n| n.to_string_lossy().ends_with(TEMP_SUFFIX)).unwrap_or(false)
+}
+
+/// Replaces `path` with `bytes` so readers see either the old or the new
+/// content, never a torn write. The data goes to a temp file that is synced
+/// and renamed over the target; an existing file's permissions carry over.
+/// Blocking; call it from `spawn_blocking`.
+pub fn write(path: &Path, bytes: &[u8], create_if_missing: bool, precondition: &Precondition) -> Result<Metadata, WriteError> {
+    let existing = fs::metadata(path).ok();
+    if existing.is_none() && !create_if_missing {
+        return Err(WriteError::NotFound(path.to_string_lossy().to_string()));
+    }
+    if existing.as_ref().map(|m| m.is_dir()).unwrap_or(false) {
+        return Err(WriteError::Io(format!("{} is a directory", path.display())));
+    }
+    precondition.check(path, existing.as_ref())?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let tmp = temp_path(path);
+    let result = (|| -> Result<(), WriteError> {
+        let mut file = File::create(&tmp)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        drop(file);
+        if let Some(meta) = &existing {
+            fs::set_permissions(&tmp, meta.permissions())?;
+        }
+        // Re-check right before the swap to keep the race window small.
+        precondition.check(path, fs::metadata(path).ok().as_ref())?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result?;
+
+    // Persist the rename itself; not supported on every platform.
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        let _ = File::open(parent).and_then(|dir| dir.sync_all());
+    }
+
+    Ok(fs::metadata(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_writes_and_detects_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+
+        let missing = write(&path, b"x", false, &Precondition::default()).unwrap_err();
+        assert_eq!(missing.code(), "not_found");
+
+        write(&path, b"one", true, &Precondition::default()).unwrap();
+        let stale = Precondition { expected_hash: Some(content_hash(b"zero")), expected_mtime: None };
+        assert_eq!(write(&path, b"two", true, &stale).unwrap_err().code(), "conflict");
+        assert_eq!(fs::read(&path).unwrap(), b"one");
+
+        let fresh = Precondition { expected_hash: Some(content_hash(b"one")), expected_mtime: None };
+        write(&path, b"two", true, &fresh).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"two");
+        // No temp files left behind.
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_preserves_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.sh");
+        fs::write(&path, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        write(&path, b"#!/bin/sh\necho hi\n", true, &Precondition::default()).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o755);
+    }
+}
@@ -0,0 +1,209 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+
+/// Number of leading bytes inspected when guessing a file's encoding.
+pub const SNIFF_BYTES: usize = 8192;
+
+const UTF16LE_BOM: [u8; 2] = [0xFF, 0xFE];
+const UTF16BE_BOM: [u8; 2] = [0xFE, 0xFF];
+
+/// Encodings file content can travel in over IPC. Text encodings are turned
+/// into a JS string; anything else is sent as base64 so no byte is lost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// Every byte maps to the code point of the same value, so any byte
+    /// sequence survives a read/write round trip unchanged.
+    Latin1,
+    Base64,
+}
+
+impl Encoding {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "utf-16le" | "utf16le" => Ok(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Ok(Encoding::Utf16Be),
+            "latin1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            "base64" | "binary" => Ok(Encoding::Base64),
+            _ => Err(format!("Unsupported encoding: {name}")),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin1",
+            Encoding::Base64 => "base64",
+        }
+    }
+
+    /// Guesses the encoding from the first bytes of a file: a UTF-16 BOM
+    /// wins, a NUL means binary, valid UTF-8 means UTF-8 and any other text
+    /// is treated as Latin-1. `complete` says whether `head` is the whole
+    /// file, so a multi-byte character cut off at the end is not held
+    /// against UTF-8.
+    pub fn detect(head: &[u8], complete: bool) -> Self {
+        if head.starts_with(&UTF16LE_BOM) {
+            return Encoding::Utf16Le;
+        }
+        if head.starts_with(&UTF16BE_BOM) {
+            return Encoding::Utf16Be;
+        }
+        if head.contains(&0) {
+            return Encoding::Base64;
+        }
+        match std::str::from_utf8(head) {
+            Ok(_) => Encoding::Utf8,
+            Err(e) if e.error_len().is_none() && !complete => Encoding::Utf8,
+            Err(_) => Encoding::Latin1,
+        }
+    }
+
+    /// Number of leading bytes of `bytes` to skip so decoding starts on a
+    /// character boundary, e.g. when a ranged read begins mid-character.
+    pub fn leading_partial(self, bytes: &[u8], at_start: bool) -> usize {
+        match self {
+            Encoding::Utf8 => bytes.iter().take(3).take_while(|b| (**b & 0xC0) == 0x80).count(),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let first = bytes.get(..2).map(|u| self.unit([u[0], u[1]]));
+                if at_start && bytes.starts_with(&self.bom()) {
+                    2
+                } else if let Some(0xDC00..=0xDFFF) = first {
+                    // Low surrogate whose high half came before the range.
+                    2
+                } else {
+                    0
+                }
+            }
+            Encoding::Latin1 | Encoding::Base64 => 0,
+        }
+    }
+
+    /// Number of trailing bytes of `bytes` that only begin a character and
+    /// should be left for the next ranged read.
+    pub fn trailing_partial(self, bytes: &[u8]) -> usize {
+        match self {
+            Encoding::Utf8 => match std::str::from_utf8(bytes) {
+                Err(e) if e.error_len().is_none() => bytes.len() - e.valid_up_to(),
+                _ => 0,
+            },
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let odd = bytes.len() % 2;
+                let units = &bytes[..bytes.len() - odd];
+                let last = units.len().checked_sub(2).map(|i| self.unit([units[i], units[i + 1]]));
+                // A high surrogate needs the unit after it.
+                match last {
+                    Some(0xD800..=0xDBFF) => odd + 2,
+                    _ => odd,
+                }
+            }
+            Encoding::Latin1 | Encoding::Base64 => 0,
+        }
+    }
+
+    pub fn decode(self, bytes: &[u8]) -> Result<String, String> {
+        match self {
+            Encoding::Utf8 => String::from_utf8(bytes.to_vec()).map_err(|e| e.to_string()),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let pairs = bytes.chunks_exact(2);
+                if !pairs.remainder().is_empty() {
+                    return Err("odd number of bytes in UTF-16 content".into());
+                }
+                let units: Vec<u16> = pairs.map(|c| self.unit([c[0], c[1]])).collect();
+                String::from_utf16(&units).map_err(|e| e.to_string())
+            }
+            Encoding::Latin1 => Ok(bytes.iter().map(|b| *b as char).collect()),
+            Encoding::Base64 => Ok(BASE64.encode(bytes)),
+        }
+    }
+
+    /// Turns IPC content back into bytes. UTF-16 output gets a BOM, since
+    /// that is how `detect` recognizes it on the next read.
+    pub fn encode(self, content: &str) -> Result<Vec<u8>, String> {
+        match self {
+            Encoding::Utf8 => Ok(content.as_bytes().to_vec()),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let mut bytes = self.bom().to_vec();
+                for unit in content.encode_utf16() {
+                    let pair = if self == Encoding::Utf16Le { unit.to_le_bytes() } else { unit.to_be_bytes() };
+                    bytes.extend_from_slice(&pair);
+                }
+                Ok(bytes)
+            }
+            Encoding::Latin1 => content
+                .chars()
+                .map(|c| u8::try_from(c as u32).map_err(|_| format!("{c:?} cannot be encoded as latin1")))
+                .collect(),
+            Encoding::Base64 => BASE64.decode(content).map_err(|e| e.to_string()),
+        }
+    }
+
+    fn bom(self) -> [u8; 2] {
+        if self == Encoding::Utf16Be {
+            UTF16BE_BOM
+        } else {
+            UTF16LE_BOM
+        }
+    }
+
+    fn unit(self, pair: [u8; 2]) -> u16 {
+        if self == Encoding::Utf16Be {
+            u16::from_be_bytes(pair)
+        } else {
+            u16::from_le_bytes(pair)
+        }
+    }
+}
+
+/// Decodes a whole file the way `fs.read` would, without a leading BOM.
+/// `None` for content `fs.read` would hand over as base64, i.e. binary.
+pub fn decode_text(bytes: &[u8]) -> Option<String> {
+    let head = &bytes[..bytes.len().min(SNIFF_BYTES)];
+    let encoding = Encoding::detect(head, head.len() == bytes.len());
+    if encoding == Encoding::Base64 {
+        return None;
+    }
+    encoding.decode(&bytes[encoding.leading_partial(bytes, true)..]).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_encodings() {
+        assert_eq!(Encoding::detect(b"fn main() {}", true), Encoding::Utf8);
+        assert_eq!(Encoding::detect(b"\x89PNG\r\n\x1a\n\0\0", true), Encoding::Base64);
+        assert_eq!(Encoding::detect(b"caf\xe9", true), Encoding::Latin1);
+        // A character cut off by the sniff window is still UTF-8.
+        assert_eq!(Encoding::detect("café".as_bytes().split_last().unwrap().1, false), Encoding::Utf8);
+        assert_eq!(Encoding::detect(&[0xFF, 0xFE, b'h', 0], true), Encoding::Utf16Le);
+    }
+
+    #[test]
+    fn test_round_trips() {
+        for (encoding, text) in [(Encoding::Utf16Le, "héllo 🌍"), (Encoding::Utf16Be, "héllo 🌍"), (Encoding::Latin1, "café")] {
+            let bytes = encoding.encode(text).unwrap();
+            let skip = encoding.leading_partial(&bytes, true);
+            assert_eq!(encoding.decode(&bytes[skip..]).unwrap(), text);
+        }
+        assert_eq!(Encoding::Base64.encode(&Encoding::Base64.decode(&[0, 159, 146]).unwrap()).unwrap(), vec![0, 159, 146]);
+        assert_eq!(decode_text(&Encoding::Utf16Le.encode("hi").unwrap()).as_deref(), Some("hi"));
+        assert_eq!(decode_text(b"caf\xe9").as_deref(), Some("café"));
+        assert_eq!(decode_text(b"\x89PNG\r\n\x1a\n\0\0"), None);
+    }
+
+    #[test]
+    fn test_trims_partial_characters() {
+        let bytes = "aé🌍".as_bytes();
+        assert_eq!(Encoding::Utf8.trailing_partial(&bytes[..bytes.len() - 1]), 3);
+        assert_eq!(Encoding::Utf8.leading_partial(&bytes[2..], false), 1);
+        let utf16 = Encoding::Utf16Le.encode("🌍").unwrap();
+        assert_eq!(Encoding::Utf16Le.trailing_partial(&utf16[..5]), 3);
+    }
+}
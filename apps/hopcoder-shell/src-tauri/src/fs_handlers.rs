@@ -1,58 +1,213 @@
+use crate::atomic_write::{self, Precondition, WriteError};
+use crate::encoding::{Encoding, SNIFF_BYTES};
 use crate::ipc::HopResponse;
+use crate::sandbox::{self, Sandbox};
+use crate::walker::{self, CancelToken, WalkOptions};
+use std::io::SeekFrom;
+use std::ops::ControlFlow;
+use std::path::Path;
+use tauri::async_runtime;
 use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 
-fn validate_path(path: &str, root: Option<&str>) -> Result<(), String> {
-    if let Some(root) = root {
-        // Basic security check: prevent path traversal and ensure path is within root
-        if path.contains("..") {
-             return Err("Path traversal detected".to_string());
+/// Upper bound on entries visited by a name search so huge trees can't hang it.
+const MAX_FILES: usize = 10000;
+/// Hard cap on a single read so a stray multi-gigabyte log can't exhaust memory.
+const MAX_READ_BYTES: u64 = 64 * 1024 * 1024;
+
+pub struct ReadOptions {
+    pub offset: Option<u64>,
+    pub length: Option<u64>,
+    /// Soft cap from the `fs.read` tool schema; combined with `length` the
+    /// smaller one wins.
+    pub max_bytes: Option<u64>,
+    /// Force an encoding instead of detecting one.
+    pub encoding: Option<String>,
+}
+
+struct Chunk {
+    content: String,
+    encoding: Encoding,
+    offset: u64,
+    length: u64,
+    size: u64,
+    /// Only set when the chunk covers the whole file.
+    hash: Option<String>,
+    modified_ms: Option<i64>,
+}
+
+async fn read_chunk(path: &Path, options: &ReadOptions, forced: Option<Encoding>) -> Result<Chunk, String> {
+    let mut file = fs::File::open(path).await.map_err(|e| e.to_string())?;
+    let meta = file.metadata().await.map_err(|e| e.to_string())?;
+    let size = meta.len();
+    let modified_ms = atomic_write::modified_ms(&meta);
+
+    let encoding = match forced {
+        Some(e) => e,
+        None => {
+            let mut head = Vec::with_capacity(SNIFF_BYTES);
+            (&mut file).take(SNIFF_BYTES as u64).read_to_end(&mut head).await.map_err(|e| e.to_string())?;
+            Encoding::detect(&head, head.len() as u64 == size)
         }
-        
-        // Normalize separators for comparison if needed, but assuming consistent usage from frontend
-        if !path.starts_with(root) {
-             return Err("Access denied: Path is outside workspace root".to_string());
+    };
+
+    let mut offset = options.offset.unwrap_or(0).min(size);
+    if matches!(encoding, Encoding::Utf16Le | Encoding::Utf16Be) {
+        offset -= offset % 2;
+    }
+    let want = [options.length, options.max_bytes].into_iter().flatten().fold(MAX_READ_BYTES, u64::min);
+    file.seek(SeekFrom::Start(offset)).await.map_err(|e| e.to_string())?;
+    let mut bytes = Vec::new();
+    (&mut file).take(want).read_to_end(&mut bytes).await.map_err(|e| e.to_string())?;
+
+    // Keep ranged reads on character boundaries; the caller continues from
+    // `offset + length`.
+    let skip = encoding.leading_partial(&bytes, offset == 0);
+    let keep = if offset + bytes.len() as u64 >= size { bytes.len() } else { bytes.len() - encoding.trailing_partial(&bytes) };
+    let keep = keep.max(skip);
+    let hash = (offset == 0 && bytes.len() as u64 == size).then(|| atomic_write::content_hash(&bytes));
+
+    match encoding.decode(&bytes[skip..keep]) {
+        Ok(content) => Ok(Chunk { content, encoding, offset: offset + skip as u64, length: (keep - skip) as u64, size, hash, modified_ms }),
+        // Detection only saw the head of the file; if this range disagrees,
+        // hand it over as raw bytes rather than failing or mangling it.
+        Err(_) if forced.is_none() => {
+            let content = Encoding::Base64.decode(&bytes)?;
+            Ok(Chunk { content, encoding: Encoding::Base64, offset, length: bytes.len() as u64, size, hash, modified_ms })
         }
+        Err(e) => Err(e),
     }
-    Ok(())
 }
 
-pub async fn read(path: &str, root: Option<&str>) -> HopResponse {
-    if let Err(e) = validate_path(path, root) {
-        return HopResponse::FsRead { ok: false, content: None, error: Some(e) };
-    }
+/// Reads a file, or a byte range of it, in whatever encoding it appears to
+/// use. Binary files come back base64-encoded; `truncated` is set when the
+/// content stops short of the end of the file.
+pub async fn read(path: &str, root: Option<&str>, options: ReadOptions) -> HopResponse {
+    let path = match sandbox::resolve(path, root) {
+        Ok(p) => p,
+        Err(e) => return e.into(),
+    };
+    let forced = match options.encoding.as_deref().map(Encoding::parse).transpose() {
+        Ok(e) => e,
+        Err(e) => return read_error(e),
+    };
 
-    match fs::read_to_string(path).await {
-        Ok(content) => HopResponse::FsRead { ok: true, content: Some(content), error: None },
-        Err(e) => HopResponse::FsRead { ok: false, content: None, error: Some(e.to_string()) },
+    match read_chunk(&path, &options, forced).await {
+        Ok(chunk) => HopResponse::FsRead {
+            ok: true,
+            content: Some(chunk.content),
+            encoding: Some(chunk.encoding.name().to_string()),
+            offset: Some(chunk.offset),
+            length: Some(chunk.length),
+            size: Some(chunk.size),
+            truncated: Some(chunk.offset + chunk.length < chunk.size),
+            hash: chunk.hash,
+            modified_ms: chunk.modified_ms,
+            error: None,
+        },
+        Err(e) => read_error(e),
     }
 }
 
-pub async fn write(path: &str, content: String, root: Option<&str>) -> HopResponse {
-    if let Err(e) = validate_path(path, root) {
-        return HopResponse::FsWrite { ok: false, error: Some(e) };
+fn read_error(error: String) -> HopResponse {
+    HopResponse::FsRead {
+        ok: false,
+        content: None,
+        encoding: None,
+        offset: None,
+        length: None,
+        size: None,
+        truncated: None,
+        hash: None,
+        modified_ms: None,
+        error: Some(error),
     }
+}
 
-    match fs::write(path, content).await {
-        Ok(_) => HopResponse::FsWrite { ok: true, error: None },
-        Err(e) => HopResponse::FsWrite { ok: false, error: Some(e.to_string()) },
-    }
+pub struct WriteOptions {
+    /// Encoding of `content`; UTF-8 by default, or base64 for binary data.
+    pub encoding: Option<String>,
+    pub create_if_missing: bool,
+    pub append: bool,
+    pub precondition: Precondition,
 }
 
-pub async fn delete(path: &str, root: Option<&str>) -> HopResponse {
-    if let Err(e) = validate_path(path, root) {
-        return HopResponse::FsDelete { ok: false, error: Some(e) };
+/// The file's content with `content` added at the end, and how many bytes
+/// that added. The text is encoded the way the file already is; binary files
+/// only take base64 content, and an `encoding` that disagrees with the file's
+/// is refused. Without a precondition from the caller, the write is pinned to
+/// the content read here, so an edit that lands in between is a conflict
+/// rather than lost.
+async fn appended(path: &Path, content: &str, requested: Option<Encoding>, precondition: &mut Precondition) -> Result<(Vec<u8>, u64), String> {
+    let mut bytes = match fs::read(path).await {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.to_string()),
+    };
+    if bytes.is_empty() {
+        let tail = requested.unwrap_or(Encoding::Utf8).encode(content)?;
+        let len = tail.len() as u64;
+        return Ok((tail, len));
+    }
+
+    let encoding = Encoding::detect(&bytes, true);
+    match requested {
+        Some(requested) if requested != encoding => {
+            return Err(format!("Cannot append {} content to a {} file", requested.name(), encoding.name()));
+        }
+        None if encoding == Encoding::Base64 => {
+            return Err("Cannot append text to a binary file; send base64 content with encoding 'base64'".into());
+        }
+        _ => {}
+    }
+    let mut tail = encoding.encode(content)?;
+    if matches!(encoding, Encoding::Utf16Le | Encoding::Utf16Be) {
+        // The file already starts with a BOM.
+        tail.drain(..2);
     }
+    if precondition.expected_hash.is_none() && precondition.expected_mtime.is_none() {
+        precondition.expected_hash = Some(atomic_write::content_hash(&bytes));
+    }
+    let len = tail.len() as u64;
+    bytes.extend_from_slice(&tail);
+    Ok((bytes, len))
+}
 
-    let p = std::path::Path::new(path);
-    let res = if p.is_dir() {
-        fs::remove_dir_all(path).await
-    } else {
-        fs::remove_file(path).await
+/// Atomically replaces the file with `content`, or with `append` adds it to
+/// the end. With `expected_hash` or `expected_mtime` set, the write fails
+/// with a `conflict` error if the file changed since the caller read it.
+/// `bytes_written` counts only the bytes added by an append.
+pub async fn write(path: &str, content: String, root: Option<&str>, options: WriteOptions) -> HopResponse {
+    let path = match sandbox::resolve(path, root) {
+        Ok(p) => p,
+        Err(e) => return e.into(),
+    };
+    let mut precondition = options.precondition;
+    let encoded = match options.encoding.as_deref().map(Encoding::parse).transpose() {
+        Ok(requested) if options.append => appended(&path, &content, requested, &mut precondition).await,
+        Ok(requested) => requested.unwrap_or(Encoding::Utf8).encode(&content).map(|b| {
+            let len = b.len() as u64;
+            (b, len)
+        }),
+        Err(e) => Err(e),
     };
+    let (bytes, added) = match encoded {
+        Ok(encoded) => encoded,
+        Err(e) => return HopResponse::FsWrite { ok: false, bytes_written: None, hash: None, modified_ms: None, error: Some(e) },
+    };
+
+    let written = async_runtime::spawn_blocking(move || {
+        let meta = atomic_write::write(&path, &bytes, options.create_if_missing, &precondition)?;
+        Ok::<_, WriteError>((added, atomic_write::content_hash(&bytes), atomic_write::modified_ms(&meta)))
+    })
+    .await;
 
-    match res {
-        Ok(_) => HopResponse::FsDelete { ok: true, error: None },
-        Err(e) => HopResponse::FsDelete { ok: false, error: Some(e.to_string()) },
+    match written {
+        Ok(Ok((bytes_written, hash, modified_ms))) => {
+            HopResponse::FsWrite { ok: true, bytes_written: Some(bytes_written), hash: Some(hash), modified_ms, error: None }
+        }
+        Ok(Err(e)) => e.into(),
+        Err(e) => HopResponse::FsWrite { ok: false, bytes_written: None, hash: None, modified_ms: None, error: Some(e.to_string()) },
     }
 }
 
@@ -62,51 +217,67 @@ pub async fn search(query: &str, root: Option<&str>) -> HopResponse {
         None => return HopResponse::FsSearch { ok: false, matches: None, error: Some("Root required".to_string()) },
     };
 
-    if let Err(e) = validate_path(root_path, Some(root_path)) {
-        return HopResponse::FsSearch { ok: false, matches: None, error: Some(e) };
-    }
+    let sandbox = match Sandbox::new(root_path) {
+        Ok(s) => s,
+        Err(e) => return e.into(),
+    };
 
-    let mut matches = Vec::new();
-    let mut dirs = vec![std::path::PathBuf::from(root_path)];
     let query_lower = query.to_lowercase();
+    let options = WalkOptions { max_entries: Some(MAX_FILES), ..WalkOptions::default() };
 
-    // Limit search depth/count to prevent hanging
-    let mut count = 0;
-    const MAX_FILES: usize = 10000;
-
-    while let Some(dir) = dirs.pop() {
-        let mut entries = match fs::read_dir(&dir).await {
-            Ok(e) => e,
-            Err(_) => continue,
-        };
-
-        while let Ok(Some(entry)) = entries.next_entry().await {
-            count += 1;
-            if count > MAX_FILES {
-                break;
+    let walk = async_runtime::spawn_blocking(move || {
+        let mut matches = Vec::new();
+        walker::walk(sandbox.root(), &options, &CancelToken::default(), |entry| {
+            if entry.file_name().to_string_lossy().to_lowercase().contains(&query_lower) {
+                matches.push(entry.path().to_string_lossy().to_string());
             }
+            ControlFlow::Continue(())
+        });
+        matches
+    })
+    .await;
 
-            let path = entry.path();
-            let path_str = path.to_string_lossy().to_string();
-            
-            // Skip node_modules, .git, target, etc.
-            if path_str.contains("node_modules") || path_str.contains(".git") || path_str.contains("target") {
-                continue;
-            }
+    match walk {
+        Ok(matches) => HopResponse::FsSearch { ok: true, matches: Some(matches), error: None },
+        Err(e) => HopResponse::FsSearch { ok: false, matches: None, error: Some(e.to_string()) },
+    }
+}
 
-            let file_name = entry.file_name().to_string_lossy().to_string();
-            if file_name.to_lowercase().contains(&query_lower) {
-                matches.push(path_str.clone());
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            if path.is_dir() {
-                dirs.push(path);
-            }
-        }
-        if count > MAX_FILES {
-            break;
+    fn appending(encoding: Option<&str>) -> WriteOptions {
+        WriteOptions { encoding: encoding.map(str::to_string), create_if_missing: true, append: true, precondition: Precondition::default() }
+    }
+
+    fn written(response: HopResponse) -> Result<u64, String> {
+        match response {
+            HopResponse::FsWrite { ok: true, bytes_written, .. } => Ok(bytes_written.unwrap()),
+            HopResponse::FsWrite { error, .. } => Err(error.unwrap()),
+            other => Err(format!("{other:?}")),
         }
     }
 
-    HopResponse::FsSearch { ok: true, matches: Some(matches), error: None }
+    #[tokio::test]
+    async fn test_append_keeps_encoding_and_refuses_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_string_lossy().to_string();
+        let file = |name: &str| dir.path().join(name);
+
+        assert_eq!(written(write("new.txt", "a".into(), Some(&root), appending(None)).await), Ok(1));
+        assert_eq!(written(write("new.txt", "b".into(), Some(&root), appending(None)).await), Ok(1));
+        assert_eq!(std::fs::read(file("new.txt")).unwrap(), b"ab");
+
+        let utf16 = Encoding::Utf16Le.encode("hi").unwrap();
+        std::fs::write(file("wide.txt"), &utf16).unwrap();
+        assert_eq!(written(write("wide.txt", "!".into(), Some(&root), appending(None)).await), Ok(2));
+        assert_eq!(std::fs::read(file("wide.txt")).unwrap(), Encoding::Utf16Le.encode("hi!").unwrap());
+        assert!(written(write("wide.txt", "!".into(), Some(&root), appending(Some("utf-8"))).await).is_err());
+
+        std::fs::write(file("blob.bin"), [0u8, 1, 2]).unwrap();
+        assert!(written(write("blob.bin", "text".into(), Some(&root), appending(None)).await).is_err());
+        assert_eq!(written(write("blob.bin", "Aw==".into(), Some(&root), appending(Some("base64"))).await), Ok(1));
+        assert_eq!(std::fs::read(file("blob.bin")).unwrap(), [0, 1, 2, 3]);
+    }
 }
@@ -0,0 +1,387 @@
+use crate::ipc::HopResponse;
+use crate::sandbox::{self, Sandbox, SandboxError};
+use crate::trash::Trash;
+use crate::walker;
+use crate::workspace;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tauri::async_runtime;
+
+#[derive(Debug)]
+pub enum FsError {
+    NotFound(String),
+    /// Something already exists at the destination.
+    Conflict(String),
+    /// A non-empty directory was deleted without `recursive`.
+    NotEmpty(String),
+    /// The operation makes no sense for these paths, e.g. moving a directory
+    /// into itself.
+    Unsupported(String),
+    Sandbox(SandboxError),
+    Io(String),
+}
+
+impl FsError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            FsError::NotFound(_) => "not_found",
+            FsError::Conflict(_) => "conflict",
+            FsError::NotEmpty(_) => "not_empty",
+            FsError::Unsupported(_) => "unsupported",
+            FsError::Sandbox(e) => e.code(),
+            FsError::Io(_) => "io",
+        }
+    }
+}
+
+impl fmt::Display for FsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FsError::NotFound(what) => write!(f, "Not found: {what}"),
+            FsError::Conflict(path) => write!(f, "{path} already exists"),
+            FsError::NotEmpty(path) => write!(f, "{path} is a non-empty directory; pass recursive: true to delete it"),
+            FsError::Unsupported(reason) => write!(f, "{reason}"),
+            FsError::Sandbox(e) => write!(f, "{e}"),
+            FsError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl From<io::Error> for FsError {
+    fn from(e: io::Error) -> Self {
+        FsError::Io(e.to_string())
+    }
+}
+
+impl From<SandboxError> for FsError {
+    fn from(e: SandboxError) -> Self {
+        FsError::Sandbox(e)
+    }
+}
+
+impl From<FsError> for HopResponse {
+    fn from(e: FsError) -> Self {
+        HopResponse::Error { ok: false, code: Some(e.code().into()), error: e.to_string() }
+    }
+}
+
+/// What `fs.copy` does when a destination file already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Refuse the copy before anything is written.
+    Fail,
+    /// Merge into existing directories, leaving existing files alone.
+    Skip,
+    /// Merge into existing directories, replacing existing files.
+    Overwrite,
+}
+
+impl OnConflict {
+    pub fn parse(value: Option<&str>) -> Result<Self, String> {
+        match value {
+            None | Some("fail") => Ok(OnConflict::Fail),
+            Some("skip") => Ok(OnConflict::Skip),
+            Some("overwrite") => Ok(OnConflict::Overwrite),
+            Some(other) => Err(format!("Unsupported onConflict policy: {other}")),
+        }
+    }
+}
+
+pub fn kind_of(meta: &fs::Metadata) -> &'static str {
+    if meta.file_type().is_symlink() {
+        "symlink"
+    } else if meta.is_dir() {
+        "dir"
+    } else {
+        "file"
+    }
+}
+
+/// Renames `from` to `to`, falling back to copy-and-remove when they are on
+/// different file systems (the app data dir often is).
+pub fn move_entry(from: &Path, to: &Path) -> Result<(), FsError> {
+    match fs::rename(from, to) {
+        Ok(()) => Ok(()),
+        Err(e) if crosses_devices(&e) => {
+            let mut stats = CopyStats::default();
+            if let Err(e) = copy_tree(from, to, OnConflict::Fail, &mut stats) {
+                let _ = remove_entry(to);
+                return Err(e);
+            }
+            remove_entry(from)?;
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn crosses_devices(e: &io::Error) -> bool {
+    #[cfg(unix)]
+    return e.raw_os_error() == Some(libc::EXDEV);
+    // ERROR_NOT_SAME_DEVICE
+    #[cfg(windows)]
+    return e.raw_os_error() == Some(17);
+    #[cfg(not(any(unix, windows)))]
+    return false;
+}
+
+#[derive(Debug, Default)]
+pub struct CopyStats {
+    pub copied: u64,
+    pub skipped: u64,
+}
+
+/// Copies a file, symlink or directory tree without following symlinks.
+/// Existing directories are merged into, keeping their own permissions;
+/// existing files are handled per `on_conflict`. An entry is never replaced
+/// by one of a different kind.
+pub fn copy_tree(from: &Path, to: &Path, on_conflict: OnConflict, stats: &mut CopyStats) -> Result<(), FsError> {
+    let meta = fs::symlink_metadata(from)?;
+    let existing = fs::symlink_metadata(to).ok();
+    if let Some(existing) = &existing {
+        if kind_of(existing) != kind_of(&meta) || (on_conflict == OnConflict::Fail && !meta.is_dir()) {
+            return Err(FsError::Conflict(to.display().to_string()));
+        }
+    }
+
+    if meta.is_dir() {
+        if existing.is_none() {
+            fs::create_dir(to)?;
+        }
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_tree(&entry.path(), &to.join(entry.file_name()), on_conflict, stats)?;
+        }
+        // Applied last so a read-only source doesn't block filling the copy.
+        if existing.is_none() {
+            fs::set_permissions(to, meta.permissions())?;
+        }
+        return Ok(());
+    }
+
+    if existing.is_some() {
+        if on_conflict == OnConflict::Skip {
+            stats.skipped += 1;
+            return Ok(());
+        }
+        // Remove first so a symlink at `to` is replaced rather than written through.
+        fs::remove_file(to)?;
+    }
+    if meta.file_type().is_symlink() {
+        copy_symlink(from, to)?;
+    } else {
+        fs::copy(from, to)?;
+    }
+    stats.copied += 1;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(from)?, to)
+}
+
+#[cfg(windows)]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    let target = fs::read_link(from)?;
+    if fs::metadata(from).map(|m| m.is_dir()).unwrap_or(false) {
+        std::os::windows::fs::symlink_dir(target, to)
+    } else {
+        std::os::windows::fs::symlink_file(target, to)
+    }
+}
+
+pub fn remove_entry(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) => Err(e),
+    }
+}
+
+/// Resolves a source/destination pair and rejects moving or copying an
+/// entry onto or into itself.
+fn resolve_pair(from: &str, to: &str, root: Option<&str>) -> Result<(PathBuf, PathBuf), FsError> {
+    // Address the entries themselves so symlinks are moved, not their targets.
+    let from_path = sandbox::resolve_entry(from, root)?;
+    let to_path = sandbox::resolve_entry(to, root)?;
+    if fs::symlink_metadata(&from_path).is_err() {
+        return Err(FsError::NotFound(from.into()));
+    }
+    if to_path.starts_with(&from_path) && to_path != from_path {
+        return Err(FsError::Unsupported(format!("Cannot put {from} inside itself")));
+    }
+    Ok((from_path, to_path))
+}
+
+/// A case-only rename on a case-insensitive file system, where `to`
+/// "exists" because it is `from`.
+fn is_case_rename(from: &Path, to: &Path) -> bool {
+    from != to
+        && from.to_string_lossy().to_lowercase() == to.to_string_lossy().to_lowercase()
+        && fs::canonicalize(from).ok() == fs::canonicalize(to).ok()
+}
+
+fn rename_blocking(trash: &Trash, from: &str, to: &str, root: Option<&str>, overwrite: bool) -> Result<(String, Option<String>), FsError> {
+    let (from_path, to_path) = resolve_pair(from, to, root)?;
+    let mut trash_id = None;
+    if from_path != to_path {
+        if let Ok(existing) = fs::symlink_metadata(&to_path) {
+            if !is_case_rename(&from_path, &to_path) {
+                let meta = fs::symlink_metadata(&from_path)?;
+                if !overwrite || kind_of(&existing) != kind_of(&meta) {
+                    return Err(FsError::Conflict(to.into()));
+                }
+                // Whatever gets replaced stays recoverable.
+                trash_id = Some(trash.put(&to_path, true)?);
+            }
+        }
+        if let Some(parent) = to_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        move_entry(&from_path, &to_path)?;
+    }
+    Ok((to_path.to_string_lossy().to_string(), trash_id))
+}
+
+/// Moves or renames an entry. An existing destination is only replaced with
+/// `overwrite`, and then goes to the trash rather than being lost.
+pub async fn rename(trash: &Trash, from: String, to: String, root: Option<String>, overwrite: bool) -> HopResponse {
+    let trash = trash.clone();
+    let res = async_runtime::spawn_blocking(move || rename_blocking(&trash, &from, &to, root.as_deref(), overwrite)).await;
+    match res {
+        Ok(Ok((path, trash_id))) => HopResponse::FsRename { ok: true, path: Some(path), trash_id, error: None },
+        Ok(Err(e)) => e.into(),
+        Err(e) => HopResponse::FsRename { ok: false, path: None, trash_id: None, error: Some(e.to_string()) },
+    }
+}
+
+fn copy_blocking(from: &str, to: &str, root: Option<&str>, on_conflict: OnConflict) -> Result<CopyStats, FsError> {
+    let (from_path, to_path) = resolve_pair(from, to, root)?;
+    if from_path == to_path {
+        return Err(FsError::Unsupported(format!("Cannot copy {from} onto itself")));
+    }
+    if on_conflict == OnConflict::Fail && fs::symlink_metadata(&to_path).is_ok() {
+        return Err(FsError::Conflict(to.into()));
+    }
+    if let Some(parent) = to_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut stats = CopyStats::default();
+    copy_tree(&from_path, &to_path, on_conflict, &mut stats)?;
+    Ok(stats)
+}
+
+/// Copies a file or directory tree. `on_conflict` decides what happens to
+/// files that already exist at the destination; see [`OnConflict`].
+pub async fn copy(from: String, to: String, root: Option<String>, on_conflict: Option<String>) -> HopResponse {
+    let on_conflict = match OnConflict::parse(on_conflict.as_deref()) {
+        Ok(p) => p,
+        Err(e) => return HopResponse::FsCopy { ok: false, copied: None, skipped: None, error: Some(e) },
+    };
+    match async_runtime::spawn_blocking(move || copy_blocking(&from, &to, root.as_deref(), on_conflict)).await {
+        Ok(Ok(stats)) => HopResponse::FsCopy { ok: true, copied: Some(stats.copied), skipped: Some(stats.skipped), error: None },
+        Ok(Err(e)) => e.into(),
+        Err(e) => HopResponse::FsCopy { ok: false, copied: None, skipped: None, error: Some(e.to_string()) },
+    }
+}
+
+/// Creates a directory and any missing parents. An existing directory is
+/// not an error; `created` tells the two apart. Failures are typed
+/// `FsError`s.
+pub async fn mkdir(path: &str, root: Option<&str>) -> HopResponse {
+    let path = match sandbox::resolve(path, root) {
+        Ok(p) => p,
+        Err(e) => return e.into(),
+    };
+    match tokio::fs::metadata(&path).await {
+        Ok(meta) if meta.is_dir() => return HopResponse::FsMkdir { ok: true, created: Some(false), error: None },
+        Ok(_) => return FsError::Conflict(path.display().to_string()).into(),
+        Err(_) => {}
+    }
+    match tokio::fs::create_dir_all(&path).await {
+        Ok(()) => HopResponse::FsMkdir { ok: true, created: Some(true), error: None },
+        Err(e) => FsError::from(e).into(),
+    }
+}
+
+/// Describes a single entry the way `workspace.list` does, without
+/// following a final symlink. A missing path is a successful response with
+/// `exists: false`; any other failure is an `io` error.
+pub async fn stat(path: &str, root: Option<&str>) -> HopResponse {
+    let path = match sandbox::resolve_entry(path, root) {
+        Ok(p) => p,
+        Err(e) => return e.into(),
+    };
+    let root = root.and_then(|r| Sandbox::new(r).ok()).map(|s| s.root().to_path_buf());
+    let res = async_runtime::spawn_blocking(move || match fs::symlink_metadata(&path) {
+        Ok(meta) => {
+            let ignored = root
+                .map(|root| {
+                    let rel = path.strip_prefix(&root).unwrap_or(&path);
+                    walker::is_always_skipped(rel) || walker::root_ignore(&root).matched_path_or_any_parents(&path, meta.is_dir()).is_ignore()
+                })
+                .unwrap_or(false);
+            Ok(Some(workspace::describe(&path, Some(meta), ignored)))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    })
+    .await;
+    match res {
+        Ok(Ok(entry)) => HopResponse::FsStat { ok: true, exists: Some(entry.is_some()), entry, error: None },
+        Ok(Err(e)) => FsError::from(e).into(),
+        Err(e) => HopResponse::FsStat { ok: false, exists: None, entry: None, error: Some(e.to_string()) },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_tree_policies() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        fs::create_dir_all(src.join("nested")).unwrap();
+        fs::write(src.join("a.txt"), "new a").unwrap();
+        fs::write(src.join("nested/b.txt"), "new b").unwrap();
+        let dst = dir.path().join("dst");
+        fs::create_dir(&dst).unwrap();
+        fs::write(dst.join("a.txt"), "old a").unwrap();
+
+        let mut stats = CopyStats::default();
+        copy_tree(&src, &dst, OnConflict::Skip, &mut stats).unwrap();
+        assert_eq!((stats.copied, stats.skipped), (1, 1));
+        assert_eq!(fs::read_to_string(dst.join("a.txt")).unwrap(), "old a");
+        assert_eq!(fs::read_to_string(dst.join("nested/b.txt")).unwrap(), "new b");
+
+        copy_tree(&src, &dst, OnConflict::Overwrite, &mut CopyStats::default()).unwrap();
+        assert_eq!(fs::read_to_string(dst.join("a.txt")).unwrap(), "new a");
+
+        // A file never replaces a directory, whatever the policy.
+        let err = copy_tree(&src.join("a.txt"), &dst.join("nested"), OnConflict::Overwrite, &mut CopyStats::default()).unwrap_err();
+        assert_eq!(err.code(), "conflict");
+
+        // Merging into a directory leaves its permissions alone.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&src, fs::Permissions::from_mode(0o700)).unwrap();
+            fs::set_permissions(&dst, fs::Permissions::from_mode(0o755)).unwrap();
+            copy_tree(&src, &dst, OnConflict::Skip, &mut CopyStats::default()).unwrap();
+            assert_eq!(fs::metadata(&dst).unwrap().permissions().mode() & 0o777, 0o755);
+        }
+    }
+
+    #[test]
+    fn test_rejects_moving_into_itself() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("a")).unwrap();
+        let root = dir.path().to_str();
+        assert_eq!(resolve_pair("a", "a/b", root).unwrap_err().code(), "unsupported");
+        assert_eq!(resolve_pair("missing", "b", root).unwrap_err().code(), "not_found");
+    }
+}
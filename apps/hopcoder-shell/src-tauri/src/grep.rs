@@ -0,0 +1,289 @@
+use crate::encoding;
+use crate::ipc::{GrepMatch, HopEvent, HopNotificationMessage, HopResponse, HOP_EVENT_CHANNEL, HOP_IPC_VERSION};
+use crate::sandbox::Sandbox;
+use crate::walker::{self, CancelToken, WalkOptions};
+use dashmap::DashMap;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use regex::{Regex, RegexBuilder};
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::sync::Arc;
+use tauri::{async_runtime, AppHandle, Manager};
+
+const DEFAULT_MAX_RESULTS: usize = 1000;
+const MAX_CONTEXT_LINES: usize = 10;
+/// Files bigger than this are almost never source code worth grepping.
+const MAX_FILE_BYTES: u64 = 4 * 1024 * 1024;
+/// Matches are flushed to the frontend in batches of this size.
+const BATCH_SIZE: usize = 100;
+
+pub struct GrepOptions {
+    pub query: String,
+    pub path: Option<String>,
+    pub regex: bool,
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    pub include_globs: Vec<String>,
+    pub exclude_globs: Vec<String>,
+    pub max_results: Option<usize>,
+    pub context_lines: usize,
+}
+
+struct Matcher {
+    pattern: Regex,
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    context_lines: usize,
+}
+
+fn build_globs(globs: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for g in globs {
+        builder.add(Glob::new(g).map_err(|e| e.to_string())?);
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+impl Matcher {
+    fn new(options: &GrepOptions) -> Result<Self, String> {
+        let source = if options.regex { options.query.clone() } else { regex::escape(&options.query) };
+        let source = if options.whole_word { format!(r"\b(?:{source})\b") } else { source };
+        let pattern = RegexBuilder::new(&source)
+            .case_insensitive(!options.case_sensitive)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let include = if options.include_globs.is_empty() { None } else { Some(build_globs(&options.include_globs)?) };
+        Ok(Self {
+            pattern,
+            include,
+            exclude: build_globs(&options.exclude_globs)?,
+            context_lines: options.context_lines.min(MAX_CONTEXT_LINES),
+        })
+    }
+
+    fn wants(&self, rel: &Path) -> bool {
+        !self.exclude.is_match(rel) && self.include.as_ref().map(|i| i.is_match(rel)).unwrap_or(true)
+    }
+
+    /// Adds the matches in `text` to `out`, at most `limit` of them. Line
+    /// and column are 0-based; the column counts characters, not bytes.
+    /// Returns true if it stopped at the limit with more matches left.
+    fn search(&self, rel: &str, text: &str, limit: usize, out: &mut Vec<GrepMatch>) -> bool {
+        let lines: Vec<&str> = text.lines().collect();
+        let mut found = 0;
+        for (i, line) in lines.iter().enumerate() {
+            for m in self.pattern.find_iter(line) {
+                if found >= limit {
+                    return true;
+                }
+                found += 1;
+                let before_start = i.saturating_sub(self.context_lines);
+                let after_end = (i + 1 + self.context_lines).min(lines.len());
+                out.push(GrepMatch {
+                    path: rel.to_string(),
+                    line: i,
+                    column: line[..m.start()].chars().count(),
+                    length: m.as_str().chars().count(),
+                    preview: line.to_string(),
+                    before: lines[before_start..i].iter().map(|l| l.to_string()).collect(),
+                    after: lines[i + 1..after_end].iter().map(|l| l.to_string()).collect(),
+                });
+            }
+        }
+        false
+    }
+}
+
+/// Reads `path` as text in the encoding `fs.read` would use, skipping files
+/// that are too large or binary.
+fn read_text(path: &Path) -> Option<String> {
+    let meta = std::fs::metadata(path).ok()?;
+    if meta.len() > MAX_FILE_BYTES {
+        return None;
+    }
+    encoding::decode_text(&std::fs::read(path).ok()?)
+}
+
+fn emit(app: &AppHandle, event: HopEvent) {
+    let _ = app.emit_all(HOP_EVENT_CHANNEL, HopNotificationMessage { v: HOP_IPC_VERSION, event });
+}
+
+/// Searches `start`, a directory or a single file, handing matches to
+/// `flush` in batches. Returns how many matches there were and whether the
+/// search stopped at `limit` with more left.
+fn run(root: &Path, start: &Path, matcher: &Matcher, limit: usize, cancel: &CancelToken, mut flush: impl FnMut(Vec<GrepMatch>)) -> (usize, bool) {
+    let mut total = 0;
+    let mut truncated = false;
+    let mut batch = Vec::new();
+
+    let mut visit = |path: &Path| {
+        let rel = path.strip_prefix(root).unwrap_or(path);
+        if !matcher.wants(rel) {
+            return ControlFlow::Continue(());
+        }
+        let text = match read_text(path) {
+            Some(t) => t,
+            None => return ControlFlow::Continue(()),
+        };
+
+        let rel = rel.to_string_lossy().replace('\\', "/");
+        // Once the limit is reached this has room for nothing, and only
+        // tells whether any match is left over.
+        truncated = matcher.search(&rel, &text, limit - total - batch.len(), &mut batch);
+        if !batch.is_empty() && (batch.len() >= BATCH_SIZE || total + batch.len() >= limit) {
+            total += batch.len();
+            flush(std::mem::take(&mut batch));
+        }
+        if truncated {
+            return ControlFlow::Break(());
+        }
+        ControlFlow::Continue(())
+    };
+
+    // The walker only reports what is below its start.
+    if start.is_file() {
+        let _ = visit(start);
+    } else {
+        walker::walk(start, &WalkOptions::default(), cancel, |entry| {
+            if !entry.file_type().map(|t| t.is_file()).unwrap_or(false) {
+                return ControlFlow::Continue(());
+            }
+            visit(entry.path())
+        });
+    }
+
+    if !batch.is_empty() {
+        total += batch.len();
+        flush(batch);
+    }
+    (total, truncated)
+}
+
+/// Searches still running, so `fs.grep.cancel` can stop them.
+#[derive(Default)]
+pub struct GrepManager {
+    searches: Arc<DashMap<String, CancelToken>>,
+}
+
+/// Starts a content search and returns right away. Matches arrive as
+/// `fs.grep.matches` events tagged with `search_id`, followed by a single
+/// `fs.grep.done`.
+pub async fn grep(app: &AppHandle, manager: &GrepManager, search_id: String, root: Option<&str>, options: GrepOptions) -> HopResponse {
+    let root = match root {
+        Some(r) => r,
+        None => return HopResponse::FsGrep { ok: false, search_id: None, error: Some("Root required".to_string()) },
+    };
+    let sandbox = match Sandbox::new(root) {
+        Ok(s) => s,
+        Err(e) => return e.into(),
+    };
+    let start = match options.path.as_deref() {
+        Some(p) => match sandbox.resolve(p) {
+            Ok(p) => p,
+            Err(e) => return e.into(),
+        },
+        None => sandbox.root().to_path_buf(),
+    };
+    let matcher = match Matcher::new(&options) {
+        Ok(m) => m,
+        Err(e) => return HopResponse::FsGrep { ok: false, search_id: None, error: Some(e) },
+    };
+    let limit = options.max_results.unwrap_or(DEFAULT_MAX_RESULTS).max(1);
+
+    let cancel = CancelToken::default();
+    manager.searches.insert(search_id.clone(), cancel.clone());
+
+    let app = app.clone();
+    let id = search_id.clone();
+    let searches = manager.searches.clone();
+    async_runtime::spawn_blocking(move || {
+        let (total, truncated) = run(sandbox.root(), &start, &matcher, limit, &cancel, |matches| {
+            emit(&app, HopEvent::FsGrepMatches { search_id: id.clone(), matches });
+        });
+        searches.remove(&id);
+        emit(&app, HopEvent::FsGrepDone { search_id: id, total, truncated, cancelled: cancel.is_cancelled() });
+    });
+
+    HopResponse::FsGrep { ok: true, search_id: Some(search_id), error: None }
+}
+
+pub async fn cancel(manager: &GrepManager, search_id: &str) -> HopResponse {
+    match manager.searches.get(search_id) {
+        Some(token) => {
+            token.cancel();
+            HopResponse::FsGrepCancel { ok: true, error: None }
+        }
+        None => HopResponse::FsGrepCancel { ok: false, error: Some("search not found".into()) },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(query: &str) -> GrepOptions {
+        GrepOptions {
+            query: query.to_string(),
+            path: None,
+            regex: false,
+            case_sensitive: false,
+            whole_word: false,
+            include_globs: Vec::new(),
+            exclude_globs: Vec::new(),
+            max_results: None,
+            context_lines: 0,
+        }
+    }
+
+    fn columns(options: GrepOptions, text: &str) -> Vec<(usize, usize)> {
+        let mut out = Vec::new();
+        Matcher::new(&options).unwrap().search("a.txt", text, usize::MAX, &mut out);
+        out.iter().map(|m| (m.line, m.column)).collect()
+    }
+
+    #[test]
+    fn test_matcher_modes() {
+        let text = "Foo foo\nfood a.o";
+        assert_eq!(columns(options("foo"), text), vec![(0, 0), (0, 4), (1, 0)]);
+        assert_eq!(columns(GrepOptions { case_sensitive: true, ..options("Foo") }, text), vec![(0, 0)]);
+        assert_eq!(columns(GrepOptions { whole_word: true, ..options("foo") }, text), vec![(0, 0), (0, 4)]);
+        // Literal by default: the dot only matches a dot.
+        assert_eq!(columns(options("a.o"), "abo a.o"), vec![(0, 4)]);
+        assert_eq!(columns(GrepOptions { regex: true, ..options("a.o") }, "abo a.o"), vec![(0, 0), (0, 4)]);
+        assert_eq!(columns(GrepOptions { regex: true, whole_word: true, ..options("fo+|bar") }, "foo food bar"), vec![(0, 0), (0, 9)]);
+        // Columns count characters.
+        assert_eq!(columns(options("x"), "äöx"), vec![(0, 2)]);
+        assert!(Matcher::new(&GrepOptions { regex: true, ..options("(") }).is_err());
+    }
+
+    #[test]
+    fn test_limit_and_single_file_start() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "hit\nhit\n").unwrap();
+        std::fs::write(dir.path().join("b.txt"), "miss\n").unwrap();
+        let matcher = Matcher::new(&options("hit")).unwrap();
+        let search = |start: &Path, limit: usize| {
+            let mut matches = Vec::new();
+            let (total, truncated) = run(dir.path(), start, &matcher, limit, &CancelToken::default(), |batch| matches.extend(batch));
+            assert_eq!(total, matches.len());
+            (matches, truncated)
+        };
+
+        let (matches, truncated) = search(dir.path(), 2);
+        assert_eq!((matches.len(), truncated), (2, false));
+        let (matches, truncated) = search(dir.path(), 1);
+        assert_eq!((matches.len(), truncated), (1, true));
+
+        let (matches, truncated) = search(&dir.path().join("a.txt"), 10);
+        assert_eq!((matches.len(), truncated), (2, false));
+        assert_eq!(matches[1].path, "a.txt");
+        assert!(search(&dir.path().join("b.txt"), 10).0.is_empty());
+
+        // Decoded like fs.read: UTF-16 text is searched, binary is skipped.
+        std::fs::write(dir.path().join("wide.txt"), encoding::Encoding::Utf16Le.encode("a hit").unwrap()).unwrap();
+        std::fs::write(dir.path().join("blob.bin"), b"hit\0").unwrap();
+        let (matches, _) = search(&dir.path().join("wide.txt"), 10);
+        assert_eq!(matches.iter().map(|m| m.column).collect::<Vec<_>>(), vec![2]);
+        assert!(search(&dir.path().join("blob.bin"), 10).0.is_empty());
+    }
+}
@@ -1,8 +1,13 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 pub const HOP_IPC_VERSION: u8 = 1;
 pub const HOP_EVENT_CHANNEL: &str = "hop://event";
 
+fn default_true() -> bool {
+    true
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "kind")]
 pub enum HopMessage {
@@ -37,45 +42,293 @@ pub struct HopNotificationMessage {
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type")]
 pub enum HopRequest {
-    #[serde(rename = "fs.read")]
-    FsRead { path: String, root: Option<String> },
-    #[serde(rename = "fs.write")]
-    FsWrite { path: String, content: String, root: Option<String> },
+    #[serde(rename = "fs.read", rename_all = "camelCase")]
+    FsRead {
+        path: String,
+        root: Option<String>,
+        offset: Option<u64>,
+        length: Option<u64>,
+        max_bytes: Option<u64>,
+        encoding: Option<String>,
+    },
+    #[serde(rename = "fs.write", rename_all = "camelCase")]
+    FsWrite {
+        path: String,
+        content: String,
+        root: Option<String>,
+        encoding: Option<String>,
+        #[serde(default = "default_true")]
+        create_if_missing: bool,
+        /// Add `content` to the end of the file instead of replacing it
+        #[serde(default)]
+        append: bool,
+        expected_hash: Option<String>,
+        /// Epoch milliseconds, as reported in `modifiedMs`
+        expected_mtime: Option<i64>,
+    },
+    /// Moves the entry into the trash; non-empty directories need `recursive`
     #[serde(rename = "fs.delete")]
-    FsDelete { path: String, root: Option<String> },
+    FsDelete {
+        path: String,
+        root: Option<String>,
+        #[serde(default)]
+        recursive: bool,
+    },
+    #[serde(rename = "fs.restore")]
+    FsRestore { id: String, root: Option<String> },
+    #[serde(rename = "fs.trash.list")]
+    FsTrashList { root: Option<String> },
+    /// Moves or renames; a replaced destination goes to the trash
+    #[serde(rename = "fs.rename")]
+    FsRename {
+        from: String,
+        to: String,
+        root: Option<String>,
+        #[serde(default)]
+        overwrite: bool,
+    },
+    #[serde(rename = "fs.copy")]
+    FsCopy {
+        from: String,
+        to: String,
+        root: Option<String>,
+        /// "fail" (default), "skip" or "overwrite"
+        #[serde(rename = "onConflict")]
+        on_conflict: Option<String>,
+    },
+    #[serde(rename = "fs.mkdir")]
+    FsMkdir { path: String, root: Option<String> },
+    #[serde(rename = "fs.stat")]
+    FsStat { path: String, root: Option<String> },
+    #[serde(rename = "fs.applyEdits")]
+    FsApplyEdits {
+        root: Option<String>,
+        #[serde(default)]
+        files: Vec<FileEdits>,
+        /// Multi-file unified diff; paths are taken from its `---`/`+++` headers
+        patch: Option<String>,
+        #[serde(rename = "dryRun", default)]
+        dry_run: bool,
+    },
     #[serde(rename = "fs.search")]
     FsSearch { query: String, root: Option<String> },
+    #[serde(rename = "fs.grep")]
+    FsGrep {
+        query: String,
+        root: Option<String>,
+        path: Option<String>,
+        #[serde(default)]
+        regex: bool,
+        #[serde(rename = "caseSensitive", default)]
+        case_sensitive: bool,
+        /// Only match where `query` is not part of a longer word
+        #[serde(rename = "wholeWord", default)]
+        whole_word: bool,
+        #[serde(rename = "includeGlobs", default)]
+        include_globs: Vec<String>,
+        #[serde(rename = "excludeGlobs", default)]
+        exclude_globs: Vec<String>,
+        #[serde(rename = "maxResults")]
+        max_results: Option<usize>,
+        #[serde(rename = "contextLines", default)]
+        context_lines: usize,
+    },
+    #[serde(rename = "fs.grep.cancel")]
+    FsGrepCancel { #[serde(rename = "searchId")] search_id: String },
     #[serde(rename = "workspace.open")]
-    WorkspaceOpen { root: String },
+    WorkspaceOpen {
+        /// Single-root shorthand for `roots: [root]`
+        root: Option<String>,
+        #[serde(default)]
+        roots: Vec<String>,
+        /// Reopen a workspace from `workspace.recent` instead of giving roots
+        id: Option<String>,
+        name: Option<String>,
+    },
+    #[serde(rename = "workspace.close")]
+    WorkspaceClose,
+    #[serde(rename = "workspace.updateRoots")]
+    WorkspaceUpdateRoots {
+        #[serde(default)]
+        add: Vec<String>,
+        #[serde(default)]
+        remove: Vec<String>,
+    },
+    #[serde(rename = "workspace.recent")]
+    WorkspaceRecent { limit: Option<usize> },
+    #[serde(rename = "workspace.forget")]
+    WorkspaceForget { id: String },
+    /// Reads a workspace's settings, shallow-merging `update` first if given
+    #[serde(rename = "workspace.settings")]
+    WorkspaceSettings { id: Option<String>, update: Option<serde_json::Map<String, serde_json::Value>> },
     #[serde(rename = "workspace.list")]
-    WorkspaceList { root: String },
+    WorkspaceList {
+        root: String,
+        depth: Option<usize>,
+        cursor: Option<String>,
+        limit: Option<usize>,
+        sort: Option<String>,
+        #[serde(rename = "includeIgnored", default)]
+        include_ignored: bool,
+    },
+    #[serde(rename = "workspace.watch")]
+    WorkspaceWatch { root: Option<String> },
+    #[serde(rename = "workspace.unwatch")]
+    WorkspaceUnwatch { root: Option<String> },
     #[serde(rename = "terminal.spawn")]
-    TerminalSpawn { id: String, shell: Option<String> },
+    TerminalSpawn {
+        id: String,
+        shell: Option<String>,
+        args: Option<Vec<String>>,
+        cwd: Option<String>,
+        env: Option<HashMap<String, String>>,
+        #[serde(rename = "loginShell", default)]
+        login_shell: bool,
+        cols: Option<u32>,
+        rows: Option<u32>,
+    },
     #[serde(rename = "terminal.write")]
     TerminalWrite { id: String, data: String },
     #[serde(rename = "terminal.resize")]
     TerminalResize { id: String, cols: u32, rows: u32 },
     #[serde(rename = "terminal.kill")]
     TerminalKill { id: String, signal: Option<String> },
+    #[serde(rename = "terminal.attach")]
+    TerminalAttach { id: String },
+    #[serde(rename = "terminal.list")]
+    TerminalList,
+    #[serde(rename = "terminal.info")]
+    TerminalInfo { id: String },
+    /// Requests (payloads with `method` and `id`) wait for the server's
+    /// response; anything else is forwarded without waiting.
     #[serde(rename = "lsp.request")]
-    LspRequest { server: String, payload: serde_json::Value },
+    LspRequest {
+        server: String,
+        payload: serde_json::Value,
+        #[serde(rename = "timeoutMs")]
+        timeout_ms: Option<u64>,
+    },
+    /// Shuts a server down with the `shutdown`/`exit` handshake
+    #[serde(rename = "lsp.stop")]
+    LspStop { server: String },
+    #[serde(rename = "lsp.restart")]
+    LspRestart { server: String },
+    /// Configured language servers, optionally only those handling a
+    /// language and/or file
+    #[serde(rename = "lsp.servers")]
+    LspServers { language: Option<String>, path: Option<String> },
+    /// Latest published diagnostics. With a `path` the file is opened (or
+    /// synced from disk) first and fresh diagnostics are waited for, up to
+    /// `timeoutMs`; without one every cached file is returned.
+    #[serde(rename = "lsp.diagnostics")]
+    LspDiagnostics {
+        path: Option<String>,
+        root: Option<String>,
+        /// Defaults to the first available server handling the file
+        server: Option<String>,
+        #[serde(rename = "timeoutMs")]
+        timeout_ms: Option<u64>,
+    },
+    #[serde(rename = "lsp.definition")]
+    LspDefinition { path: String, position: EditPosition, root: Option<String>, server: Option<String> },
+    #[serde(rename = "lsp.references")]
+    LspReferences {
+        path: String,
+        position: EditPosition,
+        #[serde(rename = "includeDeclaration")]
+        include_declaration: Option<bool>,
+        root: Option<String>,
+        server: Option<String>,
+    },
+    #[serde(rename = "lsp.hover")]
+    LspHover { path: String, position: EditPosition, root: Option<String>, server: Option<String> },
+    /// Symbols declared in `path`, or without one the workspace symbols
+    /// matching `query` from the given or every running server
+    #[serde(rename = "lsp.symbols")]
+    LspSymbols { path: Option<String>, query: Option<String>, root: Option<String>, server: Option<String> },
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type")]
 pub enum HopResponse {
-    #[serde(rename = "fs.read")]
-    FsRead { ok: bool, content: Option<String>, error: Option<String> },
-    #[serde(rename = "fs.write")]
-    FsWrite { ok: bool, error: Option<String> },
+    #[serde(rename = "fs.read", rename_all = "camelCase")]
+    FsRead {
+        ok: bool,
+        content: Option<String>,
+        encoding: Option<String>,
+        offset: Option<u64>,
+        length: Option<u64>,
+        size: Option<u64>,
+        truncated: Option<bool>,
+        /// SHA-256 of the file, only when the whole file was read
+        hash: Option<String>,
+        modified_ms: Option<i64>,
+        error: Option<String>,
+    },
+    #[serde(rename = "fs.write", rename_all = "camelCase")]
+    FsWrite { ok: bool, bytes_written: Option<u64>, hash: Option<String>, modified_ms: Option<i64>, error: Option<String> },
     #[serde(rename = "fs.delete")]
-    FsDelete { ok: bool, error: Option<String> },
+    FsDelete { ok: bool, #[serde(rename = "trashId")] trash_id: Option<String>, error: Option<String> },
+    #[serde(rename = "fs.restore")]
+    FsRestore { ok: bool, path: Option<String>, error: Option<String> },
+    #[serde(rename = "fs.trash.list")]
+    FsTrashList { ok: bool, entries: Option<Vec<TrashEntry>>, error: Option<String> },
+    #[serde(rename = "fs.rename")]
+    FsRename {
+        ok: bool,
+        path: Option<String>,
+        /// Set when an existing destination was replaced
+        #[serde(rename = "trashId")]
+        trash_id: Option<String>,
+        error: Option<String>,
+    },
+    #[serde(rename = "fs.copy")]
+    FsCopy { ok: bool, copied: Option<u64>, skipped: Option<u64>, error: Option<String> },
+    #[serde(rename = "fs.mkdir")]
+    FsMkdir { ok: bool, created: Option<bool>, error: Option<String> },
+    #[serde(rename = "fs.stat")]
+    FsStat { ok: bool, exists: Option<bool>, entry: Option<WorkspaceEntry>, error: Option<String> },
+    #[serde(rename = "fs.applyEdits")]
+    FsApplyEdits { ok: bool, files: Option<Vec<FileEditResult>>, error: Option<String> },
     #[serde(rename = "fs.search")]
     FsSearch { ok: bool, matches: Option<Vec<String>>, error: Option<String> },
+    #[serde(rename = "fs.grep")]
+    FsGrep { ok: bool, #[serde(rename = "searchId")] search_id: Option<String>, error: Option<String> },
+    #[serde(rename = "fs.grep.cancel")]
+    FsGrepCancel { ok: bool, error: Option<String> },
     #[serde(rename = "workspace.open")]
-    WorkspaceOpen { ok: bool, #[serde(rename = "workspaceRoot")] workspace_root: Option<String>, error: Option<String> },
+    WorkspaceOpen {
+        ok: bool,
+        /// Primary root
+        #[serde(rename = "workspaceRoot")]
+        workspace_root: Option<String>,
+        workspace: Option<WorkspaceInfo>,
+        error: Option<String>,
+    },
+    #[serde(rename = "workspace.close")]
+    WorkspaceClose { ok: bool, error: Option<String> },
+    #[serde(rename = "workspace.updateRoots")]
+    WorkspaceUpdateRoots { ok: bool, workspace: Option<WorkspaceInfo>, error: Option<String> },
+    #[serde(rename = "workspace.recent")]
+    WorkspaceRecent { ok: bool, workspaces: Option<Vec<WorkspaceInfo>>, error: Option<String> },
+    #[serde(rename = "workspace.forget")]
+    WorkspaceForget { ok: bool, error: Option<String> },
+    #[serde(rename = "workspace.settings")]
+    WorkspaceSettings { ok: bool, settings: Option<serde_json::Value>, error: Option<String> },
     #[serde(rename = "workspace.list")]
-    WorkspaceList { ok: bool, entries: Option<Vec<WorkspaceEntry>>, error: Option<String> },
+    WorkspaceList {
+        ok: bool,
+        entries: Option<Vec<WorkspaceEntry>>,
+        #[serde(rename = "nextCursor")]
+        next_cursor: Option<String>,
+        /// The walk stopped at its entry cap
+        truncated: Option<bool>,
+        error: Option<String>,
+    },
+    #[serde(rename = "workspace.watch")]
+    WorkspaceWatch { ok: bool, root: Option<String>, error: Option<String> },
+    #[serde(rename = "workspace.unwatch")]
+    WorkspaceUnwatch { ok: bool, error: Option<String> },
     #[serde(rename = "terminal.spawn")]
     TerminalSpawn { ok: bool, pid: Option<u32>, error: Option<String> },
     #[serde(rename = "terminal.write")]
@@ -84,12 +337,52 @@ pub enum HopResponse {
     TerminalResize { ok: bool, error: Option<String> },
     #[serde(rename = "terminal.kill")]
     TerminalKill { ok: bool, error: Option<String> },
+    #[serde(rename = "terminal.attach")]
+    TerminalAttach { ok: bool, data: Option<String>, offset: Option<u64>, error: Option<String> },
+    #[serde(rename = "terminal.list")]
+    TerminalList { ok: bool, terminals: Option<Vec<TerminalInfo>>, error: Option<String> },
+    #[serde(rename = "terminal.info")]
+    TerminalInfo { ok: bool, terminal: Option<TerminalInfo>, error: Option<String> },
     #[serde(rename = "lsp.request")]
-    LspRequest { ok: bool, result: Option<serde_json::Value>, error: Option<String> },
+    LspRequest {
+        ok: bool,
+        result: Option<serde_json::Value>,
+        /// Set for failures the caller may want to tell apart: "timeout"
+        code: Option<String>,
+        error: Option<String>,
+    },
+    #[serde(rename = "lsp.stop")]
+    LspStop { ok: bool, error: Option<String> },
+    #[serde(rename = "lsp.restart")]
+    LspRestart { ok: bool, error: Option<String> },
+    #[serde(rename = "lsp.servers")]
+    LspServers { ok: bool, servers: Option<Vec<LspServerInfo>>, error: Option<String> },
+    #[serde(rename = "lsp.diagnostics")]
+    LspDiagnostics { ok: bool, files: Option<Vec<LspFileDiagnostics>>, error: Option<String> },
+    #[serde(rename = "lsp.definition")]
+    LspDefinition { ok: bool, locations: Option<Vec<LspLocation>>, error: Option<String> },
+    #[serde(rename = "lsp.references")]
+    LspReferences { ok: bool, locations: Option<Vec<LspLocation>>, error: Option<String> },
+    /// `contents` is Markdown; both are `None` when there is nothing to show
+    #[serde(rename = "lsp.hover")]
+    LspHover { ok: bool, contents: Option<String>, range: Option<EditRange>, error: Option<String> },
+    #[serde(rename = "lsp.symbols")]
+    LspSymbols { ok: bool, symbols: Option<Vec<LspSymbol>>, error: Option<String> },
     #[serde(rename = "error")]
     Error { ok: bool, code: Option<String>, error: String },
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WorkspaceInfo {
+    pub id: String,
+    pub name: String,
+    /// Canonical roots; the first is the primary root
+    pub roots: Vec<String>,
+    pub settings: serde_json::Value,
+    #[serde(rename = "lastOpenedMs")]
+    pub last_opened_ms: i64,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct WorkspaceEntry {
     pub path: String,
@@ -97,17 +390,210 @@ pub struct WorkspaceEntry {
     pub size: Option<u64>,
     #[serde(rename = "modified_ms")]
     pub modified_ms: Option<i64>,
+    /// Link target as stored in the symlink, for `kind == "symlink"`
+    pub target: Option<String>,
+    /// Symlink whose target does not exist
+    pub broken: bool,
+    pub hidden: bool,
+    /// Matched by `.gitignore`/`.hopcoderignore`; only reported with `includeIgnored`
+    pub ignored: bool,
+    pub executable: bool,
+    pub readonly: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LspServerInfo {
+    pub id: String,
+    pub command: String,
+    pub args: Vec<String>,
+    pub languages: Vec<String>,
+    pub globs: Vec<String>,
+    #[serde(rename = "rootMarkers")]
+    pub root_markers: Vec<String>,
+    /// The command was found on PATH
+    pub available: bool,
+    /// Resolved path of the command
+    pub binary: Option<String>,
+    pub running: bool,
+    /// Project root found from the root markers, when listing for a `path`
+    pub root: Option<String>,
+}
+
+/// Positions in LSP results are converted from the server's encoding to
+/// Unicode scalar values, like [`EditPosition`] everywhere else.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LspLocation {
+    /// Root-relative path with `/` separators, or absolute for files
+    /// outside the root (dependencies, the standard library)
+    pub path: String,
+    pub range: EditRange,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LspDiagnostic {
+    pub range: EditRange,
+    /// "error", "warning", "information" or "hint"
+    pub severity: Option<String>,
+    pub message: String,
+    pub source: Option<String>,
+    pub code: Option<String>,
+    /// Id of the server that reported it
+    pub server: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LspFileDiagnostics {
+    pub path: String,
+    pub diagnostics: Vec<LspDiagnostic>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LspSymbol {
+    pub name: String,
+    /// Lowercase LSP symbol kind, e.g. "function" or "struct"
+    pub kind: String,
+    pub detail: Option<String>,
+    /// Enclosing symbol, e.g. the impl or class of a method
+    #[serde(rename = "containerName")]
+    pub container_name: Option<String>,
+    pub path: String,
+    /// Missing for workspace symbols the server resolves lazily
+    pub range: Option<EditRange>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GrepMatch {
+    /// Root-relative path with `/` separators
+    pub path: String,
+    /// 0-based line index
+    pub line: usize,
+    /// 0-based character index within the line
+    pub column: usize,
+    /// Match length in characters
+    pub length: usize,
+    pub preview: String,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+/// 0-based line and character, counted in Unicode scalar values like
+/// [`GrepMatch::column`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EditPosition {
+    pub line: usize,
+    pub character: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EditRange {
+    pub start: EditPosition,
+    pub end: EditPosition,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TextEdit {
+    pub range: EditRange,
+    #[serde(rename = "newText")]
+    pub new_text: String,
+}
+
+/// Changes for one file: either range `edits` against its current content
+/// or a single-file unified `diff`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileEdits {
+    pub path: String,
+    /// Hash from `fs.read`; the file is left alone if it no longer matches
+    #[serde(rename = "expectedHash")]
+    pub expected_hash: Option<String>,
+    #[serde(default)]
+    pub edits: Vec<TextEdit>,
+    pub diff: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HunkResult {
+    /// Position of the edit or hunk in the request
+    pub index: usize,
+    pub ok: bool,
+    /// 0-based line the hunk was applied at, which may differ from the diff's
+    pub line: Option<usize>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileEditResult {
+    pub path: String,
+    pub ok: bool,
+    /// Hash of the new content
+    pub hash: Option<String>,
+    pub hunks: Vec<HunkResult>,
+    /// `conflict`, `not_found` or `io` when `ok` is false
+    pub code: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FsRename {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrashEntry {
+    /// Pass to `fs.restore`
+    pub id: String,
+    /// Absolute path the entry was deleted from
+    pub path: String,
+    pub kind: String, // "file", "dir", or "symlink"
+    /// Total bytes of the files it contains
+    pub size: u64,
+    #[serde(rename = "deletedAtMs")]
+    pub deleted_at_ms: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TerminalInfo {
+    pub id: String,
+    pub pid: Option<u32>,
+    pub shell: String,
+    pub cwd: Option<String>,
+    #[serde(rename = "startedAtMs")]
+    pub started_at_ms: i64,
+    pub state: String, // "running" or "exited"
+    #[serde(rename = "exitCode")]
+    pub exit_code: Option<i32>,
+    pub signal: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type")]
 pub enum HopEvent {
     #[serde(rename = "terminal.data")]
-    TerminalData { id: String, data: String },
+    TerminalData { id: String, data: String, offset: u64 },
     #[serde(rename = "terminal.exit")]
     TerminalExit { id: String, code: Option<i32>, signal: Option<String> },
+    #[serde(rename = "fs.grep.matches")]
+    FsGrepMatches { #[serde(rename = "searchId")] search_id: String, matches: Vec<GrepMatch> },
+    #[serde(rename = "fs.grep.done")]
+    FsGrepDone { #[serde(rename = "searchId")] search_id: String, total: usize, truncated: bool, cancelled: bool },
+    #[serde(rename = "fs.created")]
+    FsCreated { root: String, paths: Vec<String> },
+    #[serde(rename = "fs.modified")]
+    FsModified { root: String, paths: Vec<String> },
+    #[serde(rename = "fs.deleted")]
+    FsDeleted { root: String, paths: Vec<String> },
+    #[serde(rename = "fs.renamed")]
+    FsRenamed { root: String, renames: Vec<FsRename> },
     #[serde(rename = "lsp.message")]
     LspMessage { server: String, message: serde_json::Value },
+    /// A request from the server; answer it with an `lsp.request` whose
+    /// payload is the response carrying the same `id`.
+    #[serde(rename = "lsp.serverRequest")]
+    LspServerRequest { server: String, id: serde_json::Value, method: String, params: serde_json::Value },
+    /// `running`, `crashed` (a restart follows), `failed` (left down) or
+    /// `stopped`; `code` is the exit code when the process exited
+    #[serde(rename = "lsp.status")]
+    LspStatus { server: String, status: String, code: Option<i32>, error: Option<String> },
     #[serde(rename = "log")]
     Log { level: String, message: String, scope: Option<String> },
 }
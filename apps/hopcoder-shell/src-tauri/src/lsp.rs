@@ -1,154 +1,803 @@
-use crate::ipc::{HopEvent, HopNotificationMessage, HopResponse, HOP_EVENT_CHANNEL, HOP_IPC_VERSION};
+use crate::atomic_write::content_hash;
+use crate::ipc::{HopEvent, HopNotificationMessage, HopResponse, LspServerInfo, HOP_EVENT_CHANNEL, HOP_IPC_VERSION};
+use crate::lsp_codec::LspCodec;
+use crate::lsp_registry::{self, LspRegistry, ServerConfig};
+use crate::lsp_tools::normalize_uri;
+use bytes::BytesMut;
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
-use serde_json::Value;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tauri::{AppHandle, Manager};
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
-use tokio::process::{Child, Command};
-use tokio::sync::Mutex;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{ChildStdin, Command};
+use tokio::sync::{oneshot, watch, Mutex, Notify};
+use tokio_stream::StreamExt;
+use tokio_util::codec::{Encoder, FramedRead};
 
-#[derive(Default)]
+/// How long `lsp.request` waits for a response unless told otherwise.
+pub const DEFAULT_REQUEST_TIMEOUT_MS: u64 = 30_000;
+/// How long a server gets to answer `shutdown` and then to exit before it
+/// is killed.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(3);
+/// Delay before the first automatic restart; doubled for each crash in a row.
+const RESTART_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(30);
+/// Crashes in a row after which a server is left down.
+const MAX_RESTARTS: u32 = 5;
+/// A server that stayed up this long no longer counts as crashing in a row.
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/// Requests awaiting the server's response, by the id they were sent with.
+type Pending = Arc<DashMap<u64, oneshot::Sender<Value>>>;
+
+/// Latest `publishDiagnostics` per document URI, from each server.
+type Diagnostics = Arc<DashMap<String, BTreeMap<String, Vec<Value>>>>;
+
+/// A document open in a server, by normalized URI.
+struct Document {
+    version: i64,
+    /// Hash of the text the shell sent; `None` for documents the editor
+    /// opened, whose contents it owns.
+    hash: Option<String>,
+}
+
+/// What [`LspManager::sync_document`] has to tell the server.
+enum Sync {
+    Open,
+    Change(i64),
+    Current,
+}
+
+struct Server {
+    stdin: Mutex<ChildStdin>,
+    pending: Pending,
+    /// Ids are assigned here rather than by callers, so concurrent clients
+    /// of the same server can't collide.
+    next_id: AtomicU64,
+    binary: PathBuf,
+    config: ServerConfig,
+    started_at: Instant,
+    /// Automatic restarts in a row that led to this process.
+    restarts: u32,
+    /// Set when the server is stopped on purpose, so its exit isn't a crash.
+    stopping: AtomicBool,
+    /// Asks the exit monitor to kill the process.
+    kill: Notify,
+    exited: watch::Receiver<bool>,
+    /// Set once an `initialize` request went out.
+    initialize_sent: AtomicBool,
+    /// The `initialize` result, once the handshake is done.
+    initialized: watch::Sender<Option<Value>>,
+    initialized_sent: AtomicBool,
+    documents: DashMap<String, Document>,
+}
+
+impl Server {
+    async fn write(&self, payload: &Value) -> Result<(), String> {
+        let mut message = BytesMut::new();
+        LspCodec::default().encode(payload, &mut message).map_err(|e| e.to_string())?;
+        let mut stdin = self.stdin.lock().await;
+        stdin.write_all(&message).await.map_err(|e| e.to_string())?;
+        stdin.flush().await.map_err(|e| e.to_string())
+    }
+
+    /// Sends a request under a fresh id and waits for the server's response
+    /// message (`result` or `error`). On timeout the request is cancelled
+    /// with `$/cancelRequest`.
+    async fn request(&self, mut payload: Value, timeout: Duration) -> Result<Value, RequestError> {
+        let initialize = payload.get("method").and_then(Value::as_str) == Some("initialize");
+        if initialize {
+            self.initialize_sent.store(true, Ordering::SeqCst);
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        payload["id"] = id.into();
+        let (tx, rx) = oneshot::channel();
+        self.pending.insert(id, tx);
+        if let Err(e) = self.write(&payload).await {
+            self.pending.remove(&id);
+            return Err(RequestError::Failed(e));
+        }
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(response)) => {
+                if let (true, Some(result)) = (initialize, response.get("result")) {
+                    self.initialized.send_replace(Some(result.clone()));
+                }
+                Ok(response)
+            }
+            Ok(Err(_)) => Err(RequestError::Failed("Server exited before responding".into())),
+            Err(_) => {
+                self.pending.remove(&id);
+                let cancel = json!({ "jsonrpc": "2.0", "method": "$/cancelRequest", "params": { "id": id } });
+                let _ = self.write(&cancel).await;
+                Err(RequestError::Timeout(timeout.as_millis() as u64))
+            }
+        }
+    }
+
+    /// Sends `initialized` unless it already went out.
+    async fn notify_initialized(&self) -> Result<(), String> {
+        if self.initialized_sent.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        self.write(&json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} })).await
+    }
+
+    /// `shutdown` request, `exit` notification, then a kill if the process
+    /// is still around. Returns once it has exited.
+    async fn shutdown(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        let mut exited = self.exited.clone();
+        let mut grace = Duration::ZERO;
+        if !*exited.borrow() {
+            // A server that doesn't answer `shutdown` isn't given more time.
+            let shutdown = json!({ "jsonrpc": "2.0", "id": 0, "method": "shutdown" });
+            if self.request(shutdown, SHUTDOWN_TIMEOUT).await.is_ok() {
+                grace = SHUTDOWN_TIMEOUT;
+            }
+            let _ = self.write(&json!({ "jsonrpc": "2.0", "method": "exit" })).await;
+        }
+        if tokio::time::timeout(grace, exited.wait_for(|e| *e)).await.is_err() {
+            self.kill.notify_one();
+            let _ = exited.wait_for(|e| *e).await;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RequestError {
+    /// No response in time; the server was sent `$/cancelRequest`.
+    Timeout(u64),
+    Failed(String),
+}
+
+impl RequestError {
+    pub fn code(&self) -> Option<&'static str> {
+        match self {
+            RequestError::Timeout(_) => Some("timeout"),
+            RequestError::Failed(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::Timeout(ms) => write!(f, "No response from language server within {} ms", ms),
+            RequestError::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+fn log(app: &AppHandle, level: &str, message: String) {
+    let _ = app.emit_all(
+        HOP_EVENT_CHANNEL,
+        HopNotificationMessage {
+            v: HOP_IPC_VERSION,
+            event: HopEvent::Log { level: level.into(), message, scope: Some("lsp".into()) },
+        },
+    );
+}
+
+fn emit_status(app: &AppHandle, server_id: &str, status: &str, code: Option<i32>, error: Option<String>) {
+    let _ = app.emit_all(
+        HOP_EVENT_CHANNEL,
+        HopNotificationMessage {
+            v: HOP_IPC_VERSION,
+            event: HopEvent::LspStatus { server: server_id.to_string(), status: status.into(), code, error },
+        },
+    );
+}
+
+/// Running language servers. Cheap to clone; exit monitors hold a clone so
+/// they can restart crashed servers.
+#[derive(Default, Clone)]
 pub struct LspManager {
-    servers: DashMap<String, Arc<Mutex<Child>>>,
+    servers: Arc<DashMap<String, Arc<Server>>>,
+    /// Params of each server's `initialize`, replayed when it is restarted.
+    init_params: Arc<DashMap<String, Value>>,
+    /// Crashed servers waiting out their backoff; removing the entry
+    /// cancels the restart.
+    restarting: Arc<DashMap<String, ()>>,
+    /// Held while checking for and spawning a server, so concurrent
+    /// callers don't start it twice.
+    starting: Arc<Mutex<()>>,
+    diagnostics: Diagnostics,
+    diagnostics_changed: Arc<Notify>,
 }
 
 impl LspManager {
-    pub async fn start_server(&self, app: AppHandle, server_id: String, cmd: String, args: Vec<String>) -> Result<(), String> {
-        let mut child = Command::new(cmd)
-            .args(args)
+    /// Starts a server unless it is already running.
+    pub async fn start_server(&self, app: AppHandle, server_id: String, binary: PathBuf, config: &ServerConfig) -> Result<(), String> {
+        let _starting = self.starting.lock().await;
+        if !self.is_running(&server_id) {
+            self.spawn(&app, &server_id, binary, config.clone(), 0)?;
+            emit_status(&app, &server_id, "running", None, None);
+        }
+        Ok(())
+    }
+
+    /// Starts a server if needed, doing the `initialize` handshake with
+    /// `params`, and waits until it is initialized. Returns the
+    /// `initialize` result.
+    pub async fn ensure_ready(&self, app: &AppHandle, server_id: &str, binary: PathBuf, config: &ServerConfig, params: Value) -> Result<Value, RequestError> {
+        let timeout = Duration::from_millis(DEFAULT_REQUEST_TIMEOUT_MS);
+        let started = {
+            let _starting = self.starting.lock().await;
+            match self.server(server_id) {
+                Ok(_) => None,
+                Err(_) => {
+                    let server = self.spawn(app, server_id, binary, config.clone(), 0).map_err(RequestError::Failed)?;
+                    emit_status(app, server_id, "running", None, None);
+                    self.init_params.insert(server_id.to_string(), params.clone());
+                    Some(server)
+                }
+            }
+        };
+        if let Some(server) = started {
+            let initialize = json!({ "jsonrpc": "2.0", "id": 0, "method": "initialize", "params": params });
+            let response = server.request(initialize, timeout).await?;
+            if let Some(error) = response.get("error") {
+                return Err(RequestError::Failed(format!("initialize failed: {}", error)));
+            }
+            server.notify_initialized().await.map_err(RequestError::Failed)?;
+        }
+        // Whoever started the server may still be in the middle of the handshake.
+        let server = self.server(server_id).map_err(RequestError::Failed)?;
+        let mut initialized = server.initialized.subscribe();
+        let result = tokio::time::timeout(timeout, initialized.wait_for(Option::is_some)).await;
+        match result {
+            Ok(Ok(result)) => Ok(result.clone().unwrap_or_default()),
+            Ok(Err(_)) => Err(RequestError::Failed("Server exited during initialize".into())),
+            Err(_) => Err(RequestError::Timeout(timeout.as_millis() as u64)),
+        }
+    }
+
+    /// The `initialize` result of a server whose handshake someone already
+    /// started, waiting for it if need be.
+    async fn initialize_result(&self, server_id: &str) -> Option<Value> {
+        let server = self.server(server_id).ok()?;
+        if !server.initialize_sent.load(Ordering::SeqCst) {
+            return None;
+        }
+        let mut initialized = server.initialized.subscribe();
+        let timeout = Duration::from_millis(DEFAULT_REQUEST_TIMEOUT_MS);
+        if let Ok(Ok(result)) = tokio::time::timeout(timeout, initialized.wait_for(Option::is_some)).await {
+            return result.clone();
+        }
+        None
+    }
+
+    /// Capabilities of a running, initialized server.
+    pub fn capabilities(&self, server_id: &str) -> Option<Value> {
+        let server = self.server(server_id).ok()?;
+        let initialized = server.initialized.borrow();
+        initialized.as_ref()?.get("capabilities").cloned()
+    }
+
+    /// Opens `uri` with `text` in a server, or sends the new text if the
+    /// file changed since. Documents the editor opened are left alone.
+    /// Returns whether anything was sent; cached diagnostics for the
+    /// document are then dropped, as a fresh set is on its way.
+    pub async fn sync_document(&self, server_id: &str, uri: &str, language_id: &str, text: &str) -> Result<bool, String> {
+        let server = self.server(server_id)?;
+        let hash = content_hash(text.as_bytes());
+        let sync = match server.documents.entry(normalize_uri(uri)) {
+            Entry::Vacant(entry) => {
+                entry.insert(Document { version: 1, hash: Some(hash) });
+                Sync::Open
+            }
+            Entry::Occupied(mut entry) => match &entry.get().hash {
+                Some(sent) if *sent != hash => {
+                    let doc = entry.get_mut();
+                    doc.version += 1;
+                    doc.hash = Some(hash);
+                    Sync::Change(doc.version)
+                }
+                _ => Sync::Current,
+            },
+        };
+        let message = match sync {
+            Sync::Current => return Ok(false),
+            Sync::Open => json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didOpen",
+                "params": { "textDocument": { "uri": uri, "languageId": language_id, "version": 1, "text": text } },
+            }),
+            Sync::Change(version) => json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didChange",
+                "params": { "textDocument": { "uri": uri, "version": version }, "contentChanges": [{ "text": text }] },
+            }),
+        };
+        if let Some(mut cached) = self.diagnostics.get_mut(&normalize_uri(uri)) {
+            cached.remove(server_id);
+        }
+        server.write(&message).await?;
+        Ok(true)
+    }
+
+    /// Keeps track of the documents the editor opens, so the shell doesn't
+    /// open them a second time. Returns a `didClose` to send first when the
+    /// editor opens a document the shell had opened.
+    fn track_editor_document(&self, server: &Server, payload: &Value) -> Option<Value> {
+        let method = payload.get("method").and_then(Value::as_str)?;
+        let doc = payload.get("params")?.get("textDocument")?;
+        let uri = doc.get("uri").and_then(Value::as_str)?;
+        let key = normalize_uri(uri);
+        let version = doc.get("version").and_then(Value::as_i64).unwrap_or(0);
+        match method {
+            "textDocument/didOpen" => {
+                let previous = server.documents.insert(key, Document { version, hash: None });
+                previous.filter(|d| d.hash.is_some()).map(|_| {
+                    json!({ "jsonrpc": "2.0", "method": "textDocument/didClose", "params": { "textDocument": { "uri": uri } } })
+                })
+            }
+            "textDocument/didChange" => {
+                if let Some(mut d) = server.documents.get_mut(&key) {
+                    d.version = version;
+                }
+                None
+            }
+            "textDocument/didClose" => {
+                server.documents.remove(&key);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Cached diagnostics by document URI and server; all documents when
+    /// `uri` is `None`.
+    pub fn diagnostics(&self, uri: Option<&str>) -> Vec<(String, String, Vec<Value>)> {
+        let mut out = Vec::new();
+        let mut push = |uri: &String, by_server: &BTreeMap<String, Vec<Value>>| {
+            for (server, diagnostics) in by_server {
+                out.push((uri.clone(), server.clone(), diagnostics.clone()));
+            }
+        };
+        match uri {
+            Some(uri) => {
+                if let Some(entry) = self.diagnostics.get(&normalize_uri(uri)) {
+                    push(entry.key(), entry.value());
+                }
+            }
+            None => self.diagnostics.iter().for_each(|entry| push(entry.key(), entry.value())),
+        }
+        out
+    }
+
+    /// Waits until `server_id` has published diagnostics for `uri`, or
+    /// `timeout` passes. Returns whether they arrived.
+    pub async fn wait_for_diagnostics(&self, server_id: &str, uri: &str, timeout: Duration) -> bool {
+        let key = normalize_uri(uri);
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let changed = self.diagnostics_changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+            if self.diagnostics.get(&key).is_some_and(|d| d.contains_key(server_id)) {
+                return true;
+            }
+            if tokio::time::timeout_at(deadline, changed).await.is_err() {
+                return false;
+            }
+        }
+    }
+
+    /// Drops a server's diagnostics once its process is gone.
+    fn forget_diagnostics(&self, server_id: &str) {
+        self.diagnostics.retain(|_, by_server| {
+            by_server.remove(server_id);
+            !by_server.is_empty()
+        });
+    }
+
+    fn spawn(&self, app: &AppHandle, server_id: &str, binary: PathBuf, config: ServerConfig, restarts: u32) -> Result<Arc<Server>, String> {
+        let mut child = Command::new(&binary)
+            .args(&config.args)
+            .envs(&config.env)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
+            .kill_on_drop(true)
             .spawn()
             .map_err(|e| e.to_string())?;
 
+        let stdin = child.stdin.take().ok_or("Failed to open stdin")?;
         let stdout = child.stdout.take().ok_or("Failed to open stdout")?;
         let stderr = child.stderr.take().ok_or("Failed to open stderr")?;
-        
-        let server_id_clone = server_id.clone();
+
+        let server_id_clone = server_id.to_string();
         let app_clone = app.clone();
+        let pending: Pending = Arc::default();
+        let pending_reader = pending.clone();
+        let manager_reader = self.clone();
 
         // Stdout reader (JSON-RPC)
         tokio::spawn(async move {
-            let mut reader = BufReader::new(stdout);
+            let mut messages = FramedRead::new(stdout, LspCodec::default());
             loop {
-                // 1. Read headers
-                let mut content_length = 0;
-                let mut line = String::new();
-                
-                loop {
-                    line.clear();
-                    if reader.read_line(&mut line).await.unwrap_or(0) == 0 {
-                        return; // EOF
-                    }
-                    if line == "\r\n" {
-                        break; // End of headers
-                    }
-                    if line.to_lowercase().starts_with("content-length:") {
-                        if let Ok(len) = line.trim_start_matches("Content-Length:").trim().parse::<usize>() {
-                            content_length = len;
-                        }
-                    }
-                }
-
-                if content_length > 0 {
-                    // 2. Read body
-                    let mut buffer = vec![0; content_length];
-                    if reader.read_exact(&mut buffer).await.is_ok() {
-                        if let Ok(json_str) = String::from_utf8(buffer) {
-                            if let Ok(json_val) = serde_json::from_str::<Value>(&json_str) {
-                                // Emit event to frontend
-                                let _ = app_clone.emit_all(
-                                    HOP_EVENT_CHANNEL,
-                                    HopNotificationMessage {
-                                        v: HOP_IPC_VERSION,
-                                        event: HopEvent::LspMessage {
-                                            server: server_id_clone.clone(),
-                                            message: json_val,
-                                        },
-                                    },
-                                );
-                            }
-                        }
+                match messages.next().await {
+                    Some(Ok(Ok(message))) => manager_reader.route(&app_clone, &server_id_clone, &pending_reader, message),
+                    Some(Ok(Err(e))) => log(&app_clone, "warn", format!("[LSP:{}] protocol error: {}", server_id_clone, e)),
+                    Some(Err(e)) => {
+                        log(&app_clone, "warn", format!("[LSP:{}] protocol error: {}", server_id_clone, e));
+                        break;
                     }
+                    None => break,
                 }
             }
+            // EOF: fail whatever is still waiting on this server.
+            pending_reader.clear();
         });
 
         // Stderr reader (Logs)
-        let server_id_log = server_id.clone();
+        let server_id_log = server_id.to_string();
         let app_log = app.clone();
         tokio::spawn(async move {
             let mut reader = BufReader::new(stderr).lines();
             while let Ok(Some(line)) = reader.next_line().await {
-                let _ = app_log.emit_all(
-                    HOP_EVENT_CHANNEL,
-                    HopNotificationMessage {
-                        v: HOP_IPC_VERSION,
-                        event: HopEvent::Log {
-                            level: "info".into(),
-                            message: format!("[LSP:{}] {}", server_id_log, line),
-                            scope: Some("lsp".into()),
-                        },
-                    },
-                );
+                log(&app_log, "info", format!("[LSP:{}] {}", server_id_log, line));
             }
         });
 
-        self.servers.insert(server_id, Arc::new(Mutex::new(child)));
+        let (exited_tx, exited) = watch::channel(false);
+        let server = Arc::new(Server {
+            stdin: Mutex::new(stdin),
+            pending,
+            next_id: AtomicU64::new(1),
+            binary,
+            config,
+            started_at: Instant::now(),
+            restarts,
+            stopping: AtomicBool::new(false),
+            kill: Notify::new(),
+            exited,
+            initialize_sent: AtomicBool::new(false),
+            initialized: watch::channel(None).0,
+            initialized_sent: AtomicBool::new(false),
+            documents: DashMap::new(),
+        });
+        self.servers.insert(server_id.to_string(), server.clone());
+
+        // Exit monitor
+        let manager = self.clone();
+        let app = app.clone();
+        let server_id = server_id.to_string();
+        let monitored = server.clone();
+        tokio::spawn(async move {
+            let status = tokio::select! {
+                status = child.wait() => status,
+                _ = monitored.kill.notified() => {
+                    let _ = child.kill().await;
+                    child.wait().await
+                }
+            };
+            monitored.pending.clear();
+            // A restart may already have replaced this process.
+            let current = manager.servers.remove_if(&server_id, |_, s| Arc::ptr_eq(s, &monitored)).is_some();
+            if current {
+                manager.forget_diagnostics(&server_id);
+            }
+            let _ = exited_tx.send(true);
+            let code = status.ok().and_then(|s| s.code());
+            if current && !monitored.stopping.load(Ordering::SeqCst) {
+                manager.recover(app, server_id, monitored, code).await;
+            } else {
+                emit_status(&app, &server_id, "stopped", code, None);
+            }
+        });
+
+        Ok(server)
+    }
+
+    /// Restarts a crashed server after a backoff, unless it crashed too
+    /// many times in a row or was started or stopped in the meantime.
+    async fn recover(&self, app: AppHandle, server_id: String, crashed: Arc<Server>, code: Option<i32>) {
+        let restarts = if crashed.started_at.elapsed() >= STABLE_AFTER { 0 } else { crashed.restarts };
+        if restarts >= MAX_RESTARTS {
+            let error = format!("Crashed {} times in a row; not restarting", restarts + 1);
+            emit_status(&app, &server_id, "failed", code, Some(error));
+            return;
+        }
+        emit_status(&app, &server_id, "crashed", code, None);
+        let backoff = RESTART_BACKOFF.saturating_mul(1 << restarts).min(MAX_RESTART_BACKOFF);
+        self.restarting.insert(server_id.clone(), ());
+        tokio::time::sleep(backoff).await;
+        if self.restarting.remove(&server_id).is_none() || self.servers.contains_key(&server_id) {
+            return;
+        }
+        let binary = crashed.binary.clone();
+        let config = crashed.config.clone();
+        if let Err(e) = self.launch(&app, &server_id, binary, config, restarts + 1).await {
+            emit_status(&app, &server_id, "failed", None, Some(e));
+        }
+    }
+
+    /// Starts a server and replays the `initialize` handshake it had before.
+    async fn launch(&self, app: &AppHandle, server_id: &str, binary: PathBuf, config: ServerConfig, restarts: u32) -> Result<(), String> {
+        let server = self.spawn(app, server_id, binary, config, restarts)?;
+        if let Some(params) = self.init_params.get(server_id).map(|p| p.clone()) {
+            let initialize = json!({ "jsonrpc": "2.0", "id": 0, "method": "initialize", "params": params });
+            let timeout = Duration::from_millis(DEFAULT_REQUEST_TIMEOUT_MS);
+            let response = server.request(initialize, timeout).await.map_err(|e| match e {
+                RequestError::Timeout(_) => "initialize timed out".to_string(),
+                RequestError::Failed(e) => e,
+            })?;
+            if let Some(error) = response.get("error") {
+                return Err(format!("initialize failed: {}", error));
+            }
+            server.notify_initialized().await?;
+        }
+        emit_status(app, server_id, "running", None, None);
         Ok(())
     }
 
+    fn server(&self, server_id: &str) -> Result<Arc<Server>, String> {
+        self.servers.get(server_id).map(|s| s.clone()).ok_or_else(|| "Server not running".into())
+    }
+
+    pub fn is_running(&self, server_id: &str) -> bool {
+        self.servers.contains_key(server_id)
+    }
+
+    pub fn running(&self) -> HashSet<String> {
+        self.servers.iter().map(|e| e.key().clone()).collect()
+    }
+
+    /// Sends a notification, or a response to a server-initiated request.
     pub async fn send_payload(&self, server_id: &str, payload: Value) -> Result<(), String> {
-        if let Some(server) = self.servers.get(server_id) {
-            let mut child = server.lock().await;
-            if let Some(stdin) = child.stdin.as_mut() {
-                let json = payload.to_string();
-                let message = format!("Content-Length: {}\r\n\r\n{}", json.len(), json);
-                stdin.write_all(message.as_bytes()).await.map_err(|e| e.to_string())?;
-                stdin.flush().await.map_err(|e| e.to_string())?;
-                return Ok(());
+        self.server(server_id)?.write(&payload).await
+    }
+
+    pub async fn request(&self, server_id: &str, payload: Value, timeout: Duration) -> Result<Value, RequestError> {
+        let server = self.server(server_id).map_err(RequestError::Failed)?;
+        if payload.get("method").and_then(Value::as_str) == Some("initialize") {
+            let params = payload.get("params").cloned().unwrap_or(Value::Null);
+            self.init_params.insert(server_id.to_string(), params);
+        }
+        server.request(payload, timeout).await
+    }
+
+    /// Shuts a server down; it won't be restarted.
+    pub async fn stop(&self, server_id: &str) -> Result<(), String> {
+        self.init_params.remove(server_id);
+        let cancelled = self.restarting.remove(server_id).is_some();
+        match self.server(server_id) {
+            Ok(server) => {
+                server.shutdown().await;
+                Ok(())
             }
+            Err(_) if cancelled => Ok(()),
+            Err(e) => Err(e),
         }
-        Err("Server not found or stdin closed".into())
     }
+
+    /// Shuts a server down if it is running and starts it again, replaying
+    /// its `initialize` handshake. Also brings back servers that were left
+    /// down after crashing.
+    pub async fn restart(&self, app: &AppHandle, server_id: &str, binary: PathBuf, config: ServerConfig) -> Result<(), String> {
+        self.restarting.remove(server_id);
+        if let Ok(server) = self.server(server_id) {
+            server.shutdown().await;
+        }
+        self.launch(app, server_id, binary, config, 0).await
+    }
+
+    /// Hands a message from a server to whoever is waiting for it:
+    /// responses complete their pending request, server requests and
+    /// notifications go to the frontend as separate events. Diagnostics are
+    /// cached on the way.
+    fn route(&self, app: &AppHandle, server_id: &str, pending: &Pending, message: Value) {
+        let event = match (message.get("id"), message.get("method").and_then(Value::as_str)) {
+            (Some(id), None) => {
+                // A response; one nobody waits for any more (timed out) is dropped.
+                if let Some((_, tx)) = id.as_u64().and_then(|id| pending.remove(&id)) {
+                    let _ = tx.send(message);
+                }
+                return;
+            }
+            (Some(id), Some(method)) => HopEvent::LspServerRequest {
+                server: server_id.to_string(),
+                id: id.clone(),
+                method: method.to_string(),
+                params: message.get("params").cloned().unwrap_or(Value::Null),
+            },
+            (None, Some("textDocument/publishDiagnostics")) => {
+                let params = message.get("params");
+                let uri = params.and_then(|p| p.get("uri")).and_then(Value::as_str);
+                let diagnostics = params.and_then(|p| p.get("diagnostics")).and_then(Value::as_array);
+                if let (Some(uri), Some(diagnostics)) = (uri, diagnostics) {
+                    self.diagnostics.entry(normalize_uri(uri)).or_default().insert(server_id.to_string(), diagnostics.clone());
+                    self.diagnostics_changed.notify_waiters();
+                }
+                HopEvent::LspMessage { server: server_id.to_string(), message }
+            }
+            _ => HopEvent::LspMessage { server: server_id.to_string(), message },
+        };
+        let _ = app.emit_all(HOP_EVENT_CHANNEL, HopNotificationMessage { v: HOP_IPC_VERSION, event });
+    }
+
+    /// Shuts every server down, for app exit.
+    pub async fn shutdown_all(&self) {
+        self.restarting.clear();
+        let mut tasks = tokio::task::JoinSet::new();
+        for server in self.servers.iter().map(|e| e.value().clone()).collect::<Vec<_>>() {
+            tasks.spawn(async move { server.shutdown().await });
+        }
+        while tasks.join_next().await.is_some() {}
+    }
+}
+
+pub fn load_registry(app: &AppHandle, workspace_root: Option<&str>) -> Result<LspRegistry, String> {
+    LspRegistry::load(app.path_resolver().app_config_dir().as_deref(), workspace_root)
 }
 
 pub async fn dispatch(
     app: &AppHandle,
     manager: &LspManager,
+    workspace_root: Option<&str>,
     server: &str,
-    payload: Value,
+    mut payload: Value,
+    timeout_ms: Option<u64>,
 ) -> HopResponse {
     // Special "initialize" payload to start the server if not running?
     // For now, let's assume we have a separate "start" command or we auto-start.
     // Let's add a hack: if payload has "method": "initialize", we try to start it.
     
     if let Some(method) = payload.get("method").and_then(|m| m.as_str()) {
-        if method == "initialize" && !manager.servers.contains_key(server) {
-            // TODO: Map server ID to actual binary. Hardcoded for now.
-            let (cmd, args) = match server {
-                "rust" => ("rust-analyzer".to_string(), vec![]),
-                "typescript" => {
-                    #[cfg(target_os = "windows")]
-                    let cmd = "npx.cmd".to_string();
-                    #[cfg(not(target_os = "windows"))]
-                    let cmd = "npx".to_string();
-                    
-                    (cmd, vec!["typescript-language-server".into(), "--stdio".into()])
+        if method == "initialize" && !manager.is_running(server) {
+            let registry = match load_registry(app, workspace_root) {
+                Ok(r) => r,
+                Err(e) => return HopResponse::LspRequest { ok: false, result: None, code: None, error: Some(e) },
+            };
+            let Some(config) = registry.get(server) else {
+                return HopResponse::LspRequest { ok: false, result: None, code: None, error: Some(format!("Unknown language server: {}", server)) };
+            };
+            let Some(binary) = lsp_registry::find_on_path(&config.command) else {
+                return HopResponse::LspRequest { ok: false, result: None, code: None, error: Some(format!("{} not found on PATH", config.command)) };
+            };
+
+            if let (Some(options), Some(params)) = (&config.initialization_options, payload.get_mut("params").and_then(Value::as_object_mut)) {
+                if params.get("initializationOptions").filter(|v| !v.is_null()).is_none() {
+                    params.insert("initializationOptions".into(), options.clone());
+                }
+            }
+
+            if let Err(e) = manager.start_server(app.clone(), server.to_string(), binary, config).await {
+                return HopResponse::LspRequest { ok: false, result: None, code: None, error: Some(format!("Failed to start server: {}", e)) };
+            }
+        }
+    }
+
+    // The shell may already have done the handshake for lsp.diagnostics and
+    // friends; the editor then gets the same result instead of a second one.
+    match payload.get("method").and_then(Value::as_str) {
+        Some("initialize") => {
+            if let Some(result) = manager.initialize_result(server).await {
+                return HopResponse::LspRequest { ok: true, result: Some(result), code: None, error: None };
+            }
+        }
+        Some("initialized") => {
+            return match manager.server(server) {
+                Ok(s) => match s.notify_initialized().await {
+                    Ok(()) => HopResponse::LspRequest { ok: true, result: None, code: None, error: None },
+                    Err(e) => HopResponse::LspRequest { ok: false, result: None, code: None, error: Some(e) },
                 },
-                _ => return HopResponse::LspRequest { ok: false, result: None, error: Some("Unknown server type".into()) },
+                Err(e) => HopResponse::LspRequest { ok: false, result: None, code: None, error: Some(e) },
             };
-            
-            if let Err(e) = manager.start_server(app.clone(), server.to_string(), cmd, args).await {
-                return HopResponse::LspRequest { ok: false, result: None, error: Some(format!("Failed to start server: {}", e)) };
+        }
+        _ => {
+            if let Ok(s) = manager.server(server) {
+                if let Some(close) = manager.track_editor_document(&s, &payload) {
+                    if let Err(e) = s.write(&close).await {
+                        return HopResponse::LspRequest { ok: false, result: None, code: None, error: Some(e) };
+                    }
+                }
             }
         }
     }
 
-    match manager.send_payload(server, payload).await {
-        Ok(_) => HopResponse::LspRequest { ok: true, result: None, error: None },
-        Err(e) => HopResponse::LspRequest { ok: false, result: None, error: Some(e) },
+    // Requests carry a method and an id; everything else (notifications,
+    // responses to server requests) is just forwarded.
+    if payload.get("method").is_none() || payload.get("id").is_none() {
+        return match manager.send_payload(server, payload).await {
+            Ok(_) => HopResponse::LspRequest { ok: true, result: None, code: None, error: None },
+            Err(e) => HopResponse::LspRequest { ok: false, result: None, code: None, error: Some(e) },
+        };
+    }
+
+    let timeout = Duration::from_millis(timeout_ms.unwrap_or(DEFAULT_REQUEST_TIMEOUT_MS));
+    match manager.request(server, payload, timeout).await {
+        Ok(mut response) => match response.get_mut("error").map(Value::take) {
+            Some(error) => {
+                let message = error.get("message").and_then(Value::as_str).unwrap_or("Language server error").to_string();
+                HopResponse::LspRequest { ok: false, result: Some(error), code: None, error: Some(message) }
+            }
+            None => HopResponse::LspRequest { ok: true, result: Some(response["result"].take()), code: None, error: None },
+        },
+        Err(e) => HopResponse::LspRequest { ok: false, result: None, code: e.code().map(String::from), error: Some(e.to_string()) },
+    }
+}
+
+pub async fn stop(manager: &LspManager, server: &str) -> HopResponse {
+    match manager.stop(server).await {
+        Ok(()) => HopResponse::LspStop { ok: true, error: None },
+        Err(e) => HopResponse::LspStop { ok: false, error: Some(e) },
+    }
+}
+
+/// Restarts a server with its current configuration, so edits to `lsp.json`
+/// take effect.
+pub async fn restart(app: &AppHandle, manager: &LspManager, workspace_root: Option<&str>, server: &str) -> HopResponse {
+    let registry = match load_registry(app, workspace_root) {
+        Ok(r) => r,
+        Err(e) => return HopResponse::LspRestart { ok: false, error: Some(e) },
+    };
+    let Some(config) = registry.get(server) else {
+        return HopResponse::LspRestart { ok: false, error: Some(format!("Unknown language server: {}", server)) };
+    };
+    let Some(binary) = lsp_registry::find_on_path(&config.command) else {
+        return HopResponse::LspRestart { ok: false, error: Some(format!("{} not found on PATH", config.command)) };
+    };
+    match manager.restart(app, server, binary, config.clone()).await {
+        Ok(()) => HopResponse::LspRestart { ok: true, error: None },
+        Err(e) => HopResponse::LspRestart { ok: false, error: Some(e) },
+    }
+}
+
+/// Lists the configured servers, with whether each can be started. Given a
+/// `path`, only the servers handling it are listed, each with the project
+/// root its root markers point at.
+pub async fn servers(
+    app: &AppHandle,
+    manager: &LspManager,
+    workspace_root: Option<String>,
+    language: Option<String>,
+    path: Option<String>,
+) -> HopResponse {
+    let config_dir = app.path_resolver().app_config_dir();
+    let running = manager.running();
+    let res = tauri::async_runtime::spawn_blocking(move || {
+        let registry = LspRegistry::load(config_dir.as_deref(), workspace_root.as_deref())?;
+        let stop_at = workspace_root.as_deref().map(Path::new);
+        // Relative paths are taken relative to the workspace root.
+        let file = path.as_deref().map(|p| stop_at.map(|r| r.join(p)).unwrap_or_else(|| PathBuf::from(p)));
+        let servers = registry
+            .iter()
+            .filter(|(id, _)| registry.handles(id, language.as_deref(), path.as_deref()))
+            .map(|(id, config)| {
+                let binary = lsp_registry::find_on_path(&config.command);
+                LspServerInfo {
+                    id: id.to_string(),
+                    command: config.command.clone(),
+                    args: config.args.clone(),
+                    languages: config.languages.clone(),
+                    globs: config.globs.clone(),
+                    root_markers: config.root_markers.clone(),
+                    available: binary.is_some(),
+                    binary: binary.map(|b| b.to_string_lossy().to_string()),
+                    running: running.contains(id),
+                    root: file
+                        .as_deref()
+                        .and_then(|f| lsp_registry::find_root(f, &config.root_markers, stop_at))
+                        .map(|r| r.to_string_lossy().to_string()),
+                }
+            })
+            .collect::<Vec<_>>();
+        Ok::<_, String>(servers)
+    })
+    .await;
+    match res {
+        Ok(Ok(servers)) => HopResponse::LspServers { ok: true, servers: Some(servers), error: None },
+        Ok(Err(e)) => HopResponse::LspServers { ok: false, servers: None, error: Some(e) },
+        Err(e) => HopResponse::LspServers { ok: false, servers: None, error: Some(e.to_string()) },
     }
 }
@@ -0,0 +1,191 @@
+use bytes::{Buf, BufMut, BytesMut};
+use serde_json::Value;
+use std::fmt;
+use std::io;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// A header block that hasn't ended after this many bytes is garbage.
+const MAX_HEADER_BYTES: usize = 8 * 1024;
+
+/// Something a server sent that isn't a valid message. The offending
+/// bytes are skipped, so reading can go on with the next message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolError(pub String);
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The LSP base protocol: a header block (`Content-Length` and optionally
+/// `Content-Type`) terminated by an empty line, then a JSON body.
+///
+/// Decoding is lenient where servers are known to be sloppy: header names
+/// are case-insensitive, lines may end with a bare `\n` and blank lines
+/// between messages are skipped. Malformed messages decode to a
+/// [`ProtocolError`] instead of failing the stream.
+#[derive(Debug, Default)]
+pub struct LspCodec {
+    /// A message whose headers were read but whose body hasn't fully
+    /// arrived yet.
+    body: Option<Body>,
+}
+
+#[derive(Debug)]
+struct Body {
+    len: usize,
+    /// Set when the headers were bad; the body is skipped and this is
+    /// reported instead.
+    error: Option<ProtocolError>,
+}
+
+impl LspCodec {
+    /// Parses a header block if one has fully arrived, consuming it. Fails
+    /// only if the body length is unknown.
+    fn decode_headers(src: &mut BytesMut) -> Option<Result<Body, ProtocolError>> {
+        let mut pos = 0;
+        let mut seen_header = false;
+        let mut content_length = None;
+        let mut error = None;
+        loop {
+            let Some(newline) = src[pos..].iter().position(|&b| b == b'\n') else {
+                if src.len() > MAX_HEADER_BYTES {
+                    src.clear();
+                    return Some(Err(ProtocolError(format!("no end of headers after {} bytes", MAX_HEADER_BYTES))));
+                }
+                return None;
+            };
+            let line = &src[pos..pos + newline];
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            pos += newline + 1;
+            if line.is_empty() {
+                if seen_header {
+                    break;
+                }
+                continue;
+            }
+            seen_header = true;
+            if error.is_none() {
+                error = parse_header(line, &mut content_length).err();
+            }
+        }
+        src.advance(pos);
+        Some(match content_length {
+            Some(len) => Ok(Body { len, error }),
+            None => Err(error.unwrap_or_else(|| ProtocolError("missing Content-Length header".into()))),
+        })
+    }
+}
+
+fn parse_header(line: &[u8], content_length: &mut Option<usize>) -> Result<(), ProtocolError> {
+    let line = std::str::from_utf8(line).map_err(|_| ProtocolError("header is not valid UTF-8".into()))?;
+    let (name, value) = line.split_once(':').ok_or_else(|| ProtocolError(format!("malformed header: {:?}", line)))?;
+    let (name, value) = (name.trim(), value.trim());
+    if name.eq_ignore_ascii_case("content-length") {
+        let len = value.parse().map_err(|_| ProtocolError(format!("invalid Content-Length: {:?}", value)))?;
+        *content_length = Some(len);
+    } else if name.eq_ignore_ascii_case("content-type") {
+        // `utf8` is accepted for backwards compatibility, as the spec asks.
+        let charset = value
+            .split(';')
+            .filter_map(|param| param.trim().split_once('='))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case("charset"))
+            .map(|(_, charset)| charset.trim().trim_matches('"'));
+        if let Some(charset) = charset {
+            if !charset.eq_ignore_ascii_case("utf-8") && !charset.eq_ignore_ascii_case("utf8") {
+                return Err(ProtocolError(format!("unsupported charset: {}", charset)));
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Decoder for LspCodec {
+    type Item = Result<Value, ProtocolError>;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let body = match self.body.take() {
+            Some(body) => body,
+            None => match Self::decode_headers(src) {
+                None => return Ok(None),
+                Some(Err(e)) => return Ok(Some(Err(e))),
+                Some(Ok(body)) => body,
+            },
+        };
+        if src.len() < body.len {
+            src.reserve(body.len - src.len());
+            self.body = Some(body);
+            return Ok(None);
+        }
+        let bytes = src.split_to(body.len);
+        if let Some(e) = body.error {
+            return Ok(Some(Err(e)));
+        }
+        Ok(Some(serde_json::from_slice(&bytes).map_err(|e| ProtocolError(format!("invalid JSON body: {}", e)))))
+    }
+}
+
+impl Encoder<&Value> for LspCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, message: &Value, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let body = serde_json::to_vec(message)?;
+        let header = format!("Content-Length: {}\r\n\r\n", body.len());
+        dst.reserve(header.len() + body.len());
+        dst.put_slice(header.as_bytes());
+        dst.put_slice(&body);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn decode_all(codec: &mut LspCodec, buf: &mut BytesMut) -> Vec<Result<Value, ProtocolError>> {
+        std::iter::from_fn(|| codec.decode(buf).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_decodes_lenient_headers_and_partial_reads() {
+        let mut codec = LspCodec::default();
+        let mut wire = BytesMut::new();
+        codec.encode(&json!({ "id": 1 }), &mut wire).unwrap();
+        wire.extend_from_slice(b"content-length: 8\ncontent-type: application/vscode-jsonrpc; charset=utf8\n\n{\"id\":2}");
+        wire.extend_from_slice(b"\r\nCONTENT-LENGTH:  8 \r\nX-Extra: 1\r\n\r\n{\"id\":3}");
+
+        // Feed one byte at a time; nothing may be lost or duplicated.
+        let mut buf = BytesMut::new();
+        let mut out = Vec::new();
+        for byte in wire.iter() {
+            buf.put_u8(*byte);
+            out.extend(decode_all(&mut codec, &mut buf));
+        }
+        assert_eq!(out, vec![Ok(json!({ "id": 1 })), Ok(json!({ "id": 2 })), Ok(json!({ "id": 3 }))]);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_skips_malformed_messages() {
+        let mut codec = LspCodec::default();
+        let mut buf = BytesMut::from(
+            &b"Content-Length: 3\r\n\r\nnot\
+               Content-Type: text/plain; charset=latin1\r\nContent-Length: 2\r\n\r\n{}\
+               X-Only: 1\r\n\r\n\
+               Content-Length: 2\r\n\r\n{}"[..],
+        );
+        let out = decode_all(&mut codec, &mut buf);
+        assert_eq!(out.len(), 4);
+        assert!(out[0].as_ref().unwrap_err().0.starts_with("invalid JSON body"));
+        assert_eq!(out[1], Err(ProtocolError("unsupported charset: latin1".into())));
+        assert_eq!(out[2], Err(ProtocolError("missing Content-Length header".into())));
+        assert_eq!(out[3], Ok(json!({})));
+
+        let mut buf = BytesMut::from(&vec![b'x'; MAX_HEADER_BYTES + 1][..]);
+        assert!(codec.decode(&mut buf).unwrap().unwrap().is_err());
+        assert!(buf.is_empty());
+    }
+}
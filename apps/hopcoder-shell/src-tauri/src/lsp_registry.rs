@@ -0,0 +1,259 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Name of the config file, both in the app config dir and in a
+/// workspace's `.hopcoder/` dir.
+pub const CONFIG_FILE: &str = "lsp.json";
+pub const WORKSPACE_CONFIG_DIR: &str = ".hopcoder";
+
+/// How to run one language server.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerConfig {
+    /// Binary name looked up on PATH, or a path to the binary
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Language ids (as used by the editor) this server handles
+    #[serde(default)]
+    pub languages: Vec<String>,
+    /// File globs this server handles, e.g. `*.rs`
+    #[serde(default)]
+    pub globs: Vec<String>,
+    /// Sent as `initializationOptions` unless the client provides its own
+    pub initialization_options: Option<Value>,
+    /// Files or dirs marking a project root, e.g. `Cargo.toml`
+    #[serde(default)]
+    pub root_markers: Vec<String>,
+}
+
+struct Server {
+    config: ServerConfig,
+    globs: GlobSet,
+}
+
+/// Language servers by id, built from the defaults below overlaid with the
+/// user's `lsp.json` and then the workspace's `.hopcoder/lsp.json`:
+///
+/// ```json
+/// { "servers": { "python": { "command": "pylsp", "languages": ["python"], "globs": ["*.py"] } } }
+/// ```
+///
+/// A server entry replaces the one with the same id; `null` removes it.
+pub struct LspRegistry {
+    servers: BTreeMap<String, Server>,
+}
+
+fn defaults() -> Value {
+    json!({
+        "rust": {
+            "command": "rust-analyzer",
+            "languages": ["rust"],
+            "globs": ["*.rs"],
+            "rootMarkers": ["Cargo.toml", "rust-project.json"]
+        },
+        "typescript": {
+            "command": "typescript-language-server",
+            "args": ["--stdio"],
+            "languages": ["typescript", "typescriptreact", "javascript", "javascriptreact"],
+            "globs": ["*.ts", "*.tsx", "*.mts", "*.cts", "*.js", "*.jsx", "*.mjs", "*.cjs"],
+            "rootMarkers": ["tsconfig.json", "jsconfig.json", "package.json"]
+        },
+        "python": {
+            "command": "pyright-langserver",
+            "args": ["--stdio"],
+            "languages": ["python"],
+            "globs": ["*.py", "*.pyi"],
+            "rootMarkers": ["pyproject.toml", "setup.py", "setup.cfg", "requirements.txt"]
+        },
+        "go": {
+            "command": "gopls",
+            "languages": ["go"],
+            "globs": ["*.go"],
+            "rootMarkers": ["go.work", "go.mod"]
+        },
+        "clangd": {
+            "command": "clangd",
+            "languages": ["c", "cpp"],
+            "globs": ["*.c", "*.h", "*.cc", "*.cpp", "*.cxx", "*.hpp", "*.hh"],
+            "rootMarkers": ["compile_commands.json", "compile_flags.txt", ".clangd"]
+        }
+    })
+}
+
+impl LspRegistry {
+    /// Loads the registry. Missing config files are fine; malformed ones
+    /// are an error naming the file.
+    pub fn load(user_config_dir: Option<&Path>, workspace_root: Option<&str>) -> Result<Self, String> {
+        let mut servers = match defaults() {
+            Value::Object(map) => map,
+            _ => Map::new(),
+        };
+        let files = [
+            user_config_dir.map(|dir| dir.join(CONFIG_FILE)),
+            workspace_root.map(|root| Path::new(root).join(WORKSPACE_CONFIG_DIR).join(CONFIG_FILE)),
+        ];
+        for file in files.iter().flatten() {
+            let Ok(text) = std::fs::read_to_string(file) else { continue };
+            let overlay = parse_config(&text).map_err(|e| format!("{}: {}", file.display(), e))?;
+            for (id, server) in overlay {
+                if server.is_null() {
+                    servers.remove(&id);
+                } else {
+                    servers.insert(id, server);
+                }
+            }
+        }
+        Self::from_json(servers)
+    }
+
+    fn from_json(servers: Map<String, Value>) -> Result<Self, String> {
+        let mut out = BTreeMap::new();
+        for (id, server) in servers {
+            let config: ServerConfig = serde_json::from_value(server).map_err(|e| format!("server {id}: {e}"))?;
+            let mut globs = GlobSetBuilder::new();
+            for glob in &config.globs {
+                globs.add(Glob::new(glob).map_err(|e| format!("server {id}: {e}"))?);
+            }
+            let globs = globs.build().map_err(|e| format!("server {id}: {e}"))?;
+            out.insert(id, Server { config, globs });
+        }
+        Ok(Self { servers: out })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&ServerConfig> {
+        self.servers.get(id).map(|s| &s.config)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ServerConfig)> {
+        self.servers.iter().map(|(id, s)| (id.as_str(), &s.config))
+    }
+
+    /// Whether server `id` handles `language` and/or the file at `path`;
+    /// with neither given every server matches.
+    pub fn handles(&self, id: &str, language: Option<&str>, path: Option<&str>) -> bool {
+        let Some(server) = self.servers.get(id) else { return false };
+        let by_language = language.map(|l| server.config.languages.iter().any(|s| s == l));
+        let by_path = path.map(|p| {
+            let name = Path::new(p).file_name().map(Path::new).unwrap_or(Path::new(p));
+            server.globs.is_match(p) || server.globs.is_match(name)
+        });
+        match (by_language, by_path) {
+            (Some(a), Some(b)) => a || b,
+            (Some(a), None) | (None, Some(a)) => a,
+            (None, None) => true,
+        }
+    }
+}
+
+fn parse_config(text: &str) -> Result<Map<String, Value>, String> {
+    let value: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    match value.get("servers") {
+        Some(Value::Object(servers)) => Ok(servers.clone()),
+        Some(_) => Err("\"servers\" must be an object".into()),
+        None => Ok(Map::new()),
+    }
+}
+
+/// Finds `command` the way a shell would: as given when it contains a path
+/// separator, otherwise in the PATH directories (trying PATHEXT on Windows).
+pub fn find_on_path(command: &str) -> Option<PathBuf> {
+    let as_given = Path::new(command);
+    if as_given.components().count() > 1 {
+        return is_executable(as_given).then(|| as_given.to_path_buf());
+    }
+    let exts: Vec<String> = if cfg!(windows) {
+        let pathext = std::env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".into());
+        std::iter::once(String::new()).chain(pathext.split(';').map(str::to_string)).collect()
+    } else {
+        vec![String::new()]
+    };
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path).find_map(|dir| {
+        exts.iter()
+            .map(|ext| dir.join(format!("{command}{ext}")))
+            .find(|candidate| is_executable(candidate))
+    })
+}
+
+fn is_executable(path: &Path) -> bool {
+    match std::fs::metadata(path) {
+        #[cfg(unix)]
+        Ok(meta) => {
+            use std::os::unix::fs::PermissionsExt;
+            meta.is_file() && meta.permissions().mode() & 0o111 != 0
+        }
+        #[cfg(not(unix))]
+        Ok(meta) => meta.is_file(),
+        Err(_) => false,
+    }
+}
+
+/// The nearest directory at or above `file` containing one of `markers`,
+/// not looking above `stop_at`.
+pub fn find_root(file: &Path, markers: &[String], stop_at: Option<&Path>) -> Option<PathBuf> {
+    let start = if file.is_dir() { Some(file) } else { file.parent() };
+    for dir in start.into_iter().flat_map(Path::ancestors) {
+        if markers.iter().any(|m| dir.join(m).exists()) {
+            return Some(dir.to_path_buf());
+        }
+        if Some(dir) == stop_at {
+            break;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_workspace_config_overrides_defaults() {
+        let user = tempfile::tempdir().unwrap();
+        let work = tempfile::tempdir().unwrap();
+        fs::write(
+            user.path().join(CONFIG_FILE),
+            r#"{ "servers": { "python": { "command": "pylsp", "languages": ["python"], "globs": ["*.py"] } } }"#,
+        )
+        .unwrap();
+        fs::create_dir(work.path().join(WORKSPACE_CONFIG_DIR)).unwrap();
+        fs::write(
+            work.path().join(WORKSPACE_CONFIG_DIR).join(CONFIG_FILE),
+            r#"{ "servers": { "go": null, "zig": { "command": "zls", "globs": ["*.zig"] } } }"#,
+        )
+        .unwrap();
+
+        let registry = LspRegistry::load(Some(user.path()), Some(&work.path().to_string_lossy())).unwrap();
+        assert_eq!(registry.get("python").unwrap().command, "pylsp");
+        assert!(registry.get("go").is_none());
+        assert!(registry.handles("zig", None, Some("/src/main.zig")));
+        assert!(registry.handles("rust", Some("rust"), None));
+        assert!(registry.handles("rust", None, Some("src/lib.rs")));
+        assert!(!registry.handles("rust", Some("python"), None));
+
+        fs::write(user.path().join(CONFIG_FILE), r#"{ "servers": { "bad": { "args": [] } } }"#).unwrap();
+        let err = LspRegistry::load(Some(user.path()), None).err().unwrap();
+        assert!(err.contains("server bad"), "{err}");
+    }
+
+    #[test]
+    fn test_finds_root_marker_within_workspace() {
+        let work = tempfile::tempdir().unwrap();
+        let krate = work.path().join("crates/core");
+        fs::create_dir_all(krate.join("src")).unwrap();
+        fs::write(krate.join("Cargo.toml"), "").unwrap();
+        fs::write(krate.join("src/lib.rs"), "").unwrap();
+        let markers = vec!["Cargo.toml".to_string()];
+
+        assert_eq!(find_root(&krate.join("src/lib.rs"), &markers, Some(work.path())), Some(krate.clone()));
+        fs::remove_file(krate.join("Cargo.toml")).unwrap();
+        assert_eq!(find_root(&krate.join("src/lib.rs"), &markers, Some(work.path())), None);
+    }
+}
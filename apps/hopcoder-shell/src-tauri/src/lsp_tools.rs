@@ -0,0 +1,692 @@
+use crate::encoding;
+use crate::ipc::{EditPosition, EditRange, HopResponse, LspDiagnostic, LspFileDiagnostics, LspLocation, LspSymbol};
+use crate::lsp::{LspManager, RequestError, DEFAULT_REQUEST_TIMEOUT_MS};
+use crate::lsp_registry::{self, LspRegistry, ServerConfig};
+use crate::sandbox::{self, Sandbox, SandboxError};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::AppHandle;
+use url::Url;
+
+/// How long `lsp.diagnostics` waits for a server to publish after a file
+/// was opened or changed.
+const DIAGNOSTICS_TIMEOUT_MS: u64 = 5_000;
+
+/// The unit servers count `character` offsets in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositionEncoding {
+    Utf8,
+    Utf16,
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// The encoding a server picked in its capabilities; UTF-16 unless it
+    /// says otherwise.
+    pub fn negotiated(capabilities: Option<&Value>) -> Self {
+        match capabilities.and_then(|c| c.get("positionEncoding")).and_then(Value::as_str) {
+            Some("utf-8") => PositionEncoding::Utf8,
+            Some("utf-32") => PositionEncoding::Utf32,
+            _ => PositionEncoding::Utf16,
+        }
+    }
+
+    fn len(self, c: char) -> usize {
+        match self {
+            PositionEncoding::Utf8 => c.len_utf8(),
+            PositionEncoding::Utf16 => c.len_utf16(),
+            PositionEncoding::Utf32 => 1,
+        }
+    }
+
+    /// Offset in this encoding of the `character`th scalar of `line`.
+    pub fn scalars_to_units(self, line: &str, character: usize) -> usize {
+        line.chars().take(character).map(|c| self.len(c)).sum()
+    }
+
+    /// Scalar index at `offset` in this encoding; offsets inside a
+    /// character round down, offsets past the end clamp to it.
+    pub fn units_to_scalars(self, line: &str, offset: usize) -> usize {
+        let mut units = 0;
+        for (i, c) in line.chars().enumerate() {
+            units += self.len(c);
+            if units > offset {
+                return i;
+            }
+        }
+        line.chars().count()
+    }
+}
+
+pub fn file_uri(path: &Path) -> Result<String, String> {
+    Url::from_file_path(path).map(String::from).map_err(|_| format!("Not an absolute path: {}", path.display()))
+}
+
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    Url::parse(uri).ok()?.to_file_path().ok()
+}
+
+/// Servers differ in how they escape file URIs (`c%3A` vs `c:`), so URIs
+/// used as keys go through this first.
+pub fn normalize_uri(uri: &str) -> String {
+    match uri_to_path(uri).and_then(|p| Url::from_file_path(p).ok()) {
+        Some(url) => url.into(),
+        None => uri.to_string(),
+    }
+}
+
+/// Language id for `didOpen`: the server's own language for the file's
+/// extension, else its only language.
+fn language_id(path: &Path, config: &ServerConfig) -> String {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    let known = match ext {
+        "rs" => "rust",
+        "ts" | "mts" | "cts" => "typescript",
+        "tsx" => "typescriptreact",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "javascriptreact",
+        "py" | "pyi" => "python",
+        "go" => "go",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" => "cpp",
+        _ => "",
+    };
+    if !known.is_empty() && (config.languages.is_empty() || config.languages.iter().any(|l| l == known)) {
+        return known.to_string();
+    }
+    config.languages.first().cloned().unwrap_or_else(|| ext.to_string())
+}
+
+/// Why a request failed: a typed error reported as-is, or a message for
+/// the request's own response.
+enum Failure {
+    Typed(Box<HopResponse>),
+    Message(String),
+}
+
+impl Failure {
+    fn typed(code: &str, error: String) -> Self {
+        Failure::Typed(Box::new(HopResponse::Error { ok: false, code: Some(code.into()), error }))
+    }
+}
+
+impl From<String> for Failure {
+    fn from(e: String) -> Self {
+        Failure::Message(e)
+    }
+}
+
+impl From<SandboxError> for Failure {
+    fn from(e: SandboxError) -> Self {
+        Failure::Typed(Box::new(e.into()))
+    }
+}
+
+impl From<RequestError> for Failure {
+    fn from(e: RequestError) -> Self {
+        match e {
+            RequestError::Failed(e) => Failure::Message(e),
+            timeout => Failure::typed("timeout", timeout.to_string()),
+        }
+    }
+}
+
+fn split_lines(text: &str) -> Vec<String> {
+    text.split('\n').map(|l| l.strip_suffix('\r').unwrap_or(l).to_string()).collect()
+}
+
+/// Converts positions in server results to scalar values and paths to
+/// root-relative ones, reading each file at most once.
+struct Converter {
+    encoding: PositionEncoding,
+    root: Option<PathBuf>,
+    files: HashMap<PathBuf, Option<Vec<String>>>,
+}
+
+impl Converter {
+    fn new(root: Option<&str>) -> Self {
+        let root = root.and_then(|r| Sandbox::new(r).ok()).map(|s| s.root().to_path_buf());
+        Self { encoding: PositionEncoding::Utf16, root, files: HashMap::new() }
+    }
+
+    fn lines(&mut self, path: &Path) -> Option<&Vec<String>> {
+        self.files
+            .entry(path.to_path_buf())
+            .or_insert_with(|| fs::read(path).ok().and_then(|bytes| encoding::decode_text(&bytes)).map(|text| split_lines(&text)))
+            .as_ref()
+    }
+
+    fn position(&mut self, path: &Path, position: &Value) -> Option<EditPosition> {
+        let line = position.get("line")?.as_u64()? as usize;
+        let offset = position.get("character")?.as_u64()? as usize;
+        let encoding = self.encoding;
+        let character = match self.lines(path).and_then(|lines| lines.get(line)) {
+            Some(text) => encoding.units_to_scalars(text, offset),
+            None => offset,
+        };
+        Some(EditPosition { line, character })
+    }
+
+    fn range(&mut self, path: &Path, range: &Value) -> Option<EditRange> {
+        Some(EditRange { start: self.position(path, range.get("start")?)?, end: self.position(path, range.get("end")?)? })
+    }
+
+    fn display(&self, path: &Path) -> String {
+        match self.root.as_deref().and_then(|root| path.strip_prefix(root).ok()) {
+            Some(rel) => rel.to_string_lossy().replace('\\', "/"),
+            None => path.to_string_lossy().to_string(),
+        }
+    }
+
+    /// A `Location` or `LocationLink`.
+    fn location(&mut self, value: &Value) -> Option<LspLocation> {
+        let (uri, range) = match value.get("targetUri") {
+            Some(uri) => (uri, value.get("targetSelectionRange").or_else(|| value.get("targetRange"))?),
+            None => (value.get("uri")?, value.get("range")?),
+        };
+        let path = uri_to_path(uri.as_str()?)?;
+        Some(LspLocation { range: self.range(&path, range)?, path: self.display(&path) })
+    }
+
+    /// `Location | Location[] | LocationLink[] | null`.
+    fn locations(&mut self, result: &Value) -> Vec<LspLocation> {
+        match result {
+            Value::Array(items) => items.iter().filter_map(|l| self.location(l)).collect(),
+            Value::Null => Vec::new(),
+            single => self.location(single).into_iter().collect(),
+        }
+    }
+
+    fn diagnostic(&mut self, path: &Path, server: &str, value: &Value) -> Option<LspDiagnostic> {
+        let severity = match value.get("severity").and_then(Value::as_u64) {
+            Some(1) => Some("error"),
+            Some(2) => Some("warning"),
+            Some(3) => Some("information"),
+            Some(4) => Some("hint"),
+            _ => None,
+        };
+        let code = match value.get("code") {
+            Some(Value::String(code)) => Some(code.clone()),
+            Some(Value::Number(code)) => Some(code.to_string()),
+            _ => None,
+        };
+        Some(LspDiagnostic {
+            range: self.range(path, value.get("range")?)?,
+            severity: severity.map(str::to_string),
+            message: value.get("message")?.as_str()?.to_string(),
+            source: value.get("source").and_then(Value::as_str).map(str::to_string),
+            code,
+            server: server.to_string(),
+        })
+    }
+
+    /// `DocumentSymbol[]` (flattened, children naming their parent as
+    /// container) or `SymbolInformation[]`/`WorkspaceSymbol[]`. `path` is
+    /// the document the symbols were asked for.
+    fn symbols(&mut self, path: Option<&Path>, result: &Value, container: Option<&str>, out: &mut Vec<LspSymbol>) {
+        for symbol in result.as_array().into_iter().flatten() {
+            let Some(name) = symbol.get("name").and_then(Value::as_str) else { continue };
+            let kind = symbol_kind(symbol.get("kind").and_then(Value::as_u64).unwrap_or(0)).to_string();
+            let detail = symbol.get("detail").and_then(Value::as_str).filter(|d| !d.is_empty()).map(str::to_string);
+            if let Some(location) = symbol.get("location") {
+                let Some(file) = location.get("uri").and_then(Value::as_str).and_then(uri_to_path) else { continue };
+                out.push(LspSymbol {
+                    name: name.to_string(),
+                    kind,
+                    detail,
+                    container_name: symbol.get("containerName").and_then(Value::as_str).map(str::to_string),
+                    range: location.get("range").and_then(|r| self.range(&file, r)),
+                    path: self.display(&file),
+                });
+            } else if let Some(file) = path {
+                out.push(LspSymbol {
+                    name: name.to_string(),
+                    kind,
+                    detail,
+                    container_name: container.map(str::to_string),
+                    range: symbol.get("range").and_then(|r| self.range(file, r)),
+                    path: self.display(file),
+                });
+                if let Some(children) = symbol.get("children") {
+                    self.symbols(path, children, Some(name), out);
+                }
+            }
+        }
+    }
+}
+
+/// `MarkedString | MarkedString[] | MarkupContent` as Markdown.
+fn hover_contents(contents: &Value) -> Option<String> {
+    let text = match contents {
+        Value::String(text) => text.clone(),
+        Value::Array(items) => items.iter().filter_map(hover_contents).collect::<Vec<_>>().join("\n\n"),
+        Value::Object(item) => {
+            let value = item.get("value").and_then(Value::as_str)?;
+            match item.get("language").and_then(Value::as_str) {
+                Some(language) => format!("```{}\n{}\n```", language, value),
+                None => value.to_string(),
+            }
+        }
+        _ => return None,
+    };
+    Some(text).filter(|t| !t.trim().is_empty())
+}
+
+fn symbol_kind(kind: u64) -> &'static str {
+    const KINDS: [&str; 26] = [
+        "file", "module", "namespace", "package", "class", "method", "property", "field", "constructor", "enum", "interface", "function",
+        "variable", "constant", "string", "number", "boolean", "array", "object", "key", "null", "enum_member", "struct", "event",
+        "operator", "type_parameter",
+    ];
+    kind.checked_sub(1).and_then(|i| KINDS.get(i as usize)).copied().unwrap_or("unknown")
+}
+
+/// The server for `file`: `explicit` if given, else the first handling it
+/// that is running or can be started. Returns the binary to start it with.
+fn pick_server(
+    registry: &LspRegistry,
+    explicit: Option<&str>,
+    file: &str,
+    running: &HashSet<String>,
+) -> Result<(String, ServerConfig, PathBuf), Failure> {
+    let candidates: Vec<(&str, &ServerConfig)> = match explicit {
+        Some(id) => match registry.get(id) {
+            Some(config) => vec![(id, config)],
+            None => return Err(Failure::typed("unsupported", format!("Unknown language server: {}", id))),
+        },
+        None => registry.iter().filter(|(id, _)| registry.handles(id, None, Some(file))).collect(),
+    };
+    if candidates.is_empty() {
+        return Err(Failure::typed("unsupported", format!("No language server handles {}", file)));
+    }
+    let found = candidates.iter().find_map(|(id, config)| {
+        let binary = lsp_registry::find_on_path(&config.command);
+        // A running server needs no binary; it may have been started from another PATH.
+        match binary {
+            Some(binary) => Some((id.to_string(), (*config).clone(), binary)),
+            None if running.contains(*id) => Some((id.to_string(), (*config).clone(), PathBuf::from(&config.command))),
+            None => None,
+        }
+    });
+    found.ok_or_else(|| {
+        let commands = candidates.iter().map(|(_, c)| c.command.as_str()).collect::<Vec<_>>().join(", ");
+        Failure::typed("unsupported", format!("{} not found on PATH", commands))
+    })
+}
+
+fn initialize_params(project: &Path, config: &ServerConfig) -> Value {
+    let uri = file_uri(project).ok();
+    let name = project.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    json!({
+        "processId": std::process::id(),
+        "clientInfo": { "name": "HopCoder" },
+        "rootUri": uri,
+        "workspaceFolders": uri.as_ref().map(|uri| json!([{ "uri": uri, "name": name }])),
+        "initializationOptions": config.initialization_options,
+        "capabilities": {
+            "general": { "positionEncodings": ["utf-32", "utf-16"] },
+            "workspace": { "workspaceFolders": true, "symbol": {} },
+            "textDocument": {
+                "synchronization": {},
+                "publishDiagnostics": {},
+                "definition": { "linkSupport": true },
+                "references": {},
+                "hover": { "contentFormat": ["markdown", "plaintext"] },
+                "documentSymbol": { "hierarchicalDocumentSymbolSupport": true },
+            },
+        },
+    })
+}
+
+/// A file opened in the server picked for it.
+struct Prepared {
+    server: String,
+    uri: String,
+    file: PathBuf,
+    text: String,
+    encoding: PositionEncoding,
+    capabilities: Value,
+    /// Whether the server was sent new contents just now
+    synced: bool,
+}
+
+impl Prepared {
+    fn require(&self, provider: &str, feature: &str) -> Result<(), Failure> {
+        match self.capabilities.get(provider) {
+            None | Some(Value::Null) | Some(Value::Bool(false)) => {
+                Err(Failure::typed("unsupported", format!("{} does not support {}", self.server, feature)))
+            }
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// `TextDocumentPositionParams` for `position`.
+    fn position_params(&self, position: &EditPosition) -> Value {
+        let line = self.text.split('\n').nth(position.line).unwrap_or_default();
+        let character = self.encoding.scalars_to_units(line, position.character);
+        json!({ "textDocument": { "uri": self.uri }, "position": { "line": position.line, "character": character } })
+    }
+
+    fn converter(&self, root: Option<&str>) -> Converter {
+        let mut converter = Converter::new(root);
+        converter.encoding = self.encoding;
+        converter.files.insert(self.file.clone(), Some(split_lines(&self.text)));
+        converter
+    }
+}
+
+/// Where a request is aimed.
+pub struct Target {
+    pub path: String,
+    pub root: Option<String>,
+    /// Server id; defaults to the first available server handling the file
+    pub server: Option<String>,
+}
+
+/// Starts (if needed) the server for the target file and opens the file in
+/// it, or sends its current contents if they changed on disk.
+async fn prepare(app: &AppHandle, manager: &LspManager, config_root: Option<&str>, target: &Target) -> Result<Prepared, Failure> {
+    let file = sandbox::resolve(&target.path, target.root.as_deref())?;
+    let config_dir = app.path_resolver().app_config_dir();
+    let config_root = config_root.map(str::to_string);
+    let stop_at = target.root.as_deref().and_then(|r| Sandbox::new(r).ok()).map(|s| s.root().to_path_buf());
+    let explicit = target.server.clone();
+    let running = manager.running();
+    let picked = tauri::async_runtime::spawn_blocking(move || {
+        let registry = LspRegistry::load(config_dir.as_deref(), config_root.as_deref())?;
+        let (server, config, binary) = pick_server(&registry, explicit.as_deref(), &file.to_string_lossy(), &running)?;
+        let bytes = match fs::read(&file) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(Failure::typed("not_found", format!("File not found: {}", file.display())));
+            }
+            Err(e) => return Err(Failure::typed("io", e.to_string())),
+        };
+        let Some(text) = encoding::decode_text(&bytes) else {
+            return Err(Failure::typed("unsupported", format!("Not a text file: {}", file.display())));
+        };
+        let project = lsp_registry::find_root(&file, &config.root_markers, stop_at.as_deref())
+            .or(stop_at)
+            .or_else(|| file.parent().map(Path::to_path_buf))
+            .unwrap_or_default();
+        Ok((server, config, binary, file, text, project))
+    })
+    .await
+    .map_err(|e| Failure::Message(e.to_string()))?;
+    let (server, config, binary, file, text, project) = picked?;
+
+    let init = manager.ensure_ready(app, &server, binary, &config, initialize_params(&project, &config)).await?;
+    let capabilities = init.get("capabilities").cloned().unwrap_or(Value::Null);
+    let uri = file_uri(&file)?;
+    let synced = manager.sync_document(&server, &uri, &language_id(&file, &config), &text).await?;
+    Ok(Prepared { encoding: PositionEncoding::negotiated(Some(&capabilities)), server, uri, file, text, capabilities, synced })
+}
+
+/// Sends a request and returns its result, or the server's error message.
+async fn call(manager: &LspManager, server: &str, method: &str, params: Value) -> Result<Value, Failure> {
+    let payload = json!({ "jsonrpc": "2.0", "id": 0, "method": method, "params": params });
+    let mut response = manager.request(server, payload, Duration::from_millis(DEFAULT_REQUEST_TIMEOUT_MS)).await?;
+    match response.get("error") {
+        Some(error) => Err(Failure::Message(error.get("message").and_then(Value::as_str).unwrap_or("Language server error").to_string())),
+        None => Ok(response["result"].take()),
+    }
+}
+
+/// Runs result conversion, which reads files, off the async runtime.
+async fn convert<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Result<T, Failure> {
+    tauri::async_runtime::spawn_blocking(f).await.map_err(|e| Failure::Message(e.to_string()))
+}
+
+pub async fn diagnostics(
+    app: &AppHandle,
+    manager: &LspManager,
+    config_root: Option<&str>,
+    target: Option<Target>,
+    root: Option<String>,
+    timeout_ms: Option<u64>,
+) -> HopResponse {
+    let res = async {
+        let uri = match &target {
+            Some(target) => {
+                let prepared = prepare(app, manager, config_root, target).await?;
+                let timeout = Duration::from_millis(timeout_ms.unwrap_or(DIAGNOSTICS_TIMEOUT_MS));
+                if prepared.synced || timeout_ms.is_some() {
+                    // Servers that find nothing may never publish; what's cached is then all there is.
+                    manager.wait_for_diagnostics(&prepared.server, &prepared.uri, timeout).await;
+                }
+                Some(prepared.uri)
+            }
+            None => None,
+        };
+        let cached = manager.diagnostics(uri.as_deref());
+        let encodings: HashMap<String, PositionEncoding> = cached
+            .iter()
+            .map(|(_, server, _)| (server.clone(), PositionEncoding::negotiated(manager.capabilities(server).as_ref())))
+            .collect();
+        convert(move || {
+            let mut converter = Converter::new(root.as_deref());
+            let mut files: Vec<LspFileDiagnostics> = Vec::new();
+            for (uri, server, diagnostics) in cached {
+                let Some(path) = uri_to_path(&uri) else { continue };
+                converter.encoding = encodings.get(&server).copied().unwrap_or(PositionEncoding::Utf16);
+                let display = converter.display(&path);
+                let diagnostics = diagnostics.iter().filter_map(|d| converter.diagnostic(&path, &server, d));
+                match files.iter_mut().find(|f| f.path == display) {
+                    Some(file) => file.diagnostics.extend(diagnostics),
+                    None => files.push(LspFileDiagnostics { diagnostics: diagnostics.collect(), path: display }),
+                }
+            }
+            files.retain(|f| !f.diagnostics.is_empty());
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+            // A clean file is still listed when asked for.
+            if let (true, Some(path)) = (files.is_empty(), uri.as_deref().and_then(uri_to_path)) {
+                files.push(LspFileDiagnostics { path: converter.display(&path), diagnostics: Vec::new() });
+            }
+            files
+        })
+        .await
+    }
+    .await;
+    match res {
+        Ok(files) => HopResponse::LspDiagnostics { ok: true, files: Some(files), error: None },
+        Err(Failure::Typed(resp)) => *resp,
+        Err(Failure::Message(e)) => HopResponse::LspDiagnostics { ok: false, files: None, error: Some(e) },
+    }
+}
+
+/// `textDocument/definition` or `textDocument/references`.
+async fn locations(
+    app: &AppHandle,
+    manager: &LspManager,
+    config_root: Option<&str>,
+    target: Target,
+    position: EditPosition,
+    include_declaration: Option<bool>,
+) -> Result<Vec<LspLocation>, Failure> {
+    let prepared = prepare(app, manager, config_root, &target).await?;
+    let mut params = prepared.position_params(&position);
+    let method = match include_declaration {
+        None => {
+            prepared.require("definitionProvider", "definitions")?;
+            "textDocument/definition"
+        }
+        Some(include) => {
+            prepared.require("referencesProvider", "references")?;
+            params["context"] = json!({ "includeDeclaration": include });
+            "textDocument/references"
+        }
+    };
+    let result = call(manager, &prepared.server, method, params).await?;
+    let mut converter = prepared.converter(target.root.as_deref());
+    convert(move || converter.locations(&result)).await
+}
+
+pub async fn definition(app: &AppHandle, manager: &LspManager, config_root: Option<&str>, target: Target, position: EditPosition) -> HopResponse {
+    match locations(app, manager, config_root, target, position, None).await {
+        Ok(locations) => HopResponse::LspDefinition { ok: true, locations: Some(locations), error: None },
+        Err(Failure::Typed(resp)) => *resp,
+        Err(Failure::Message(e)) => HopResponse::LspDefinition { ok: false, locations: None, error: Some(e) },
+    }
+}
+
+pub async fn references(
+    app: &AppHandle,
+    manager: &LspManager,
+    config_root: Option<&str>,
+    target: Target,
+    position: EditPosition,
+    include_declaration: bool,
+) -> HopResponse {
+    match locations(app, manager, config_root, target, position, Some(include_declaration)).await {
+        Ok(locations) => HopResponse::LspReferences { ok: true, locations: Some(locations), error: None },
+        Err(Failure::Typed(resp)) => *resp,
+        Err(Failure::Message(e)) => HopResponse::LspReferences { ok: false, locations: None, error: Some(e) },
+    }
+}
+
+pub async fn hover(app: &AppHandle, manager: &LspManager, config_root: Option<&str>, target: Target, position: EditPosition) -> HopResponse {
+    let res = async {
+        let prepared = prepare(app, manager, config_root, &target).await?;
+        prepared.require("hoverProvider", "hover")?;
+        let result = call(manager, &prepared.server, "textDocument/hover", prepared.position_params(&position)).await?;
+        let contents = result.get("contents").and_then(hover_contents);
+        let mut converter = prepared.converter(target.root.as_deref());
+        let file = prepared.file;
+        let range = convert(move || result.get("range").and_then(|r| converter.range(&file, r))).await?;
+        Ok((contents, range))
+    }
+    .await;
+    match res {
+        Ok((contents, range)) => HopResponse::LspHover { ok: true, contents, range, error: None },
+        Err(Failure::Typed(resp)) => *resp,
+        Err(Failure::Message(e)) => HopResponse::LspHover { ok: false, contents: None, range: None, error: Some(e) },
+    }
+}
+
+/// Document symbols for a `target` file, else workspace symbols matching
+/// `query` from `server` or every running server.
+pub async fn symbols(
+    app: &AppHandle,
+    manager: &LspManager,
+    config_root: Option<&str>,
+    target: Option<Target>,
+    query: Option<String>,
+    root: Option<String>,
+    server: Option<String>,
+) -> HopResponse {
+    let res = async {
+        if let Some(target) = target {
+            let prepared = prepare(app, manager, config_root, &target).await?;
+            prepared.require("documentSymbolProvider", "document symbols")?;
+            let params = json!({ "textDocument": { "uri": prepared.uri } });
+            let result = call(manager, &prepared.server, "textDocument/documentSymbol", params).await?;
+            let mut converter = prepared.converter(target.root.as_deref());
+            let file = prepared.file;
+            return convert(move || {
+                let mut out = Vec::new();
+                converter.symbols(Some(&file), &result, None, &mut out);
+                out
+            })
+            .await;
+        }
+
+        let servers: Vec<String> = match &server {
+            Some(server) => vec![server.clone()],
+            None => manager.running().into_iter().collect(),
+        };
+        let params = json!({ "query": query.unwrap_or_default() });
+        let mut results = Vec::new();
+        for id in servers {
+            let capabilities = manager.capabilities(&id);
+            let supported = capabilities.as_ref().and_then(|c| c.get("workspaceSymbolProvider")).is_some_and(|p| p != &json!(false));
+            let result = if supported {
+                call(manager, &id, "workspace/symbol", params.clone()).await
+            } else {
+                Err(Failure::typed("unsupported", format!("{} does not support workspace symbols", id)))
+            };
+            match result {
+                Ok(result) => results.push((PositionEncoding::negotiated(capabilities.as_ref()), result)),
+                // Asked for one server: its failure is the answer. Otherwise skip it.
+                Err(e) if server.is_some() => return Err(e),
+                Err(_) => {}
+            }
+        }
+        convert(move || {
+            let mut converter = Converter::new(root.as_deref());
+            let mut out = Vec::new();
+            for (encoding, result) in results {
+                converter.encoding = encoding;
+                converter.symbols(None, &result, None, &mut out);
+            }
+            out
+        })
+        .await
+    }
+    .await;
+    match res {
+        Ok(symbols) => HopResponse::LspSymbols { ok: true, symbols: Some(symbols), error: None },
+        Err(Failure::Typed(resp)) => *resp,
+        Err(Failure::Message(e)) => HopResponse::LspSymbols { ok: false, symbols: None, error: Some(e) },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_converts_positions_between_encodings() {
+        let line = "a😀é=1";
+        assert_eq!(PositionEncoding::Utf16.scalars_to_units(line, 2), 3);
+        assert_eq!(PositionEncoding::Utf8.scalars_to_units(line, 3), 7);
+        assert_eq!(PositionEncoding::Utf32.scalars_to_units(line, 3), 3);
+        assert_eq!(PositionEncoding::Utf16.units_to_scalars(line, 3), 2);
+        // Inside the surrogate pair rounds down; past the end clamps.
+        assert_eq!(PositionEncoding::Utf16.units_to_scalars(line, 2), 1);
+        assert_eq!(PositionEncoding::Utf8.units_to_scalars(line, 99), 5);
+        assert_eq!(PositionEncoding::negotiated(Some(&json!({ "positionEncoding": "utf-32" }))), PositionEncoding::Utf32);
+        assert_eq!(PositionEncoding::negotiated(None), PositionEncoding::Utf16);
+    }
+
+    #[test]
+    fn test_normalizes_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let file = root.join("lib.rs");
+        fs::write(&file, "mod 😀;\nfn main() {}\n").unwrap();
+        let uri = file_uri(&file).unwrap();
+        let mut converter = Converter::new(Some(&root.to_string_lossy()));
+
+        let link = json!([{ "targetUri": uri, "targetRange": {}, "targetSelectionRange": {
+            "start": { "line": 0, "character": 4 }, "end": { "line": 0, "character": 6 } } }]);
+        let locations = converter.locations(&link);
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0].path, "lib.rs");
+        assert_eq!((locations[0].range.start.character, locations[0].range.end.character), (4, 5));
+        let outside = json!({ "uri": "file:///usr/lib/std.rs", "range": {
+            "start": { "line": 1, "character": 0 }, "end": { "line": 1, "character": 3 } } });
+        assert_eq!(converter.locations(&outside)[0].path, "/usr/lib/std.rs");
+
+        let range = json!({ "start": { "line": 1, "character": 0 }, "end": { "line": 1, "character": 12 } });
+        let symbols = json!([{ "name": "main", "kind": 12, "range": range, "selectionRange": range,
+            "children": [{ "name": "x", "kind": 13, "range": range, "selectionRange": range }] }]);
+        let mut out = Vec::new();
+        converter.symbols(Some(&file), &symbols, None, &mut out);
+        assert_eq!(out.len(), 2);
+        assert_eq!((out[0].kind.as_str(), out[1].kind.as_str()), ("function", "variable"));
+        assert_eq!(out[1].container_name.as_deref(), Some("main"));
+
+        let diagnostic = json!({ "range": range, "severity": 2, "code": 101, "message": "unused" });
+        let diagnostic = converter.diagnostic(&file, "rust", &diagnostic).unwrap();
+        assert_eq!((diagnostic.severity.as_deref(), diagnostic.code.as_deref()), (Some("warning"), Some("101")));
+
+        let hover = json!([{ "language": "rust", "value": "fn main()" }, "Entry point"]);
+        assert_eq!(hover_contents(&hover).as_deref(), Some("```rust\nfn main()\n```\n\nEntry point"));
+        assert_eq!(hover_contents(&json!({ "kind": "markdown", "value": "" })), None);
+        assert_eq!(normalize_uri("file:///c%3A/x.rs"), normalize_uri("file:///c:/x.rs"));
+    }
+}
@@ -1,22 +1,45 @@
 mod ipc;
+mod atomic_write;
+mod encoding;
 mod fs_handlers;
+mod fs_ops;
+mod grep;
 mod terminal;
+mod trash;
 mod workspace;
+mod workspace_store;
 mod lsp;
+mod lsp_codec;
+mod lsp_registry;
+mod lsp_tools;
+mod memory_embedding;
+mod memory_migrations;
 mod memory_store;
+mod patch;
+mod process;
+mod sandbox;
+mod walker;
+mod watcher;
 
 use ipc::*;
-use memory_store::{MemoryItem, MemoryStore};
+use memory_store::{MemoryFilter, MemoryItem, MemorySearchHit, MemoryStats, MemoryStore, Scope};
+use workspace_store::WorkspaceStore;
 use serde::{Deserialize, Serialize};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use tauri::{Manager, State};
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn hop_ipc(
     app: tauri::AppHandle,
     message: HopRequestMessage,
     term_state: State<'_, terminal::TerminalManager>,
     lsp_state: State<'_, lsp::LspManager>,
+    workspace_state: State<'_, workspace::WorkspaceState>,
+    workspace_store: State<'_, workspace_store::WorkspaceStore>,
+    grep_state: State<'_, grep::GrepManager>,
+    watch_state: State<'_, watcher::WatchManager>,
+    trash: State<'_, trash::Trash>,
 ) -> Result<HopResponseMessage, String> {
     if message.v != HOP_IPC_VERSION {
         return Ok(HopResponseMessage {
@@ -30,20 +53,133 @@ async fn hop_ipc(
         });
     }
 
+    // Requests without an explicit root are sandboxed to the workspace root
+    // their path lies in; requests without a path use the primary root.
+    let workspace_root = workspace_state.root();
+    let root_for = |path: &str| workspace_state.root_for(path);
     let resp = match message.request {
-        HopRequest::FsRead { path, root } => fs_handlers::read(&path, root.as_deref()).await,
-        HopRequest::FsWrite { path, content, root } => fs_handlers::write(&path, content, root.as_deref()).await,
-        HopRequest::FsDelete { path, root } => fs_handlers::delete(&path, root.as_deref()).await,
-        HopRequest::FsSearch { query, root } => fs_handlers::search(&query, root.as_deref()).await,
-        HopRequest::WorkspaceOpen { root } => workspace::open(&root).await,
-        HopRequest::WorkspaceList { root } => workspace::list(&root).await,
-        HopRequest::TerminalSpawn { id, shell } => {
-            terminal::spawn(&app, &term_state, id, shell).await
+        HopRequest::FsRead { path, root, offset, length, max_bytes, encoding } => {
+            let options = fs_handlers::ReadOptions { offset, length, max_bytes, encoding };
+            fs_handlers::read(&path, root.or_else(|| root_for(&path)).as_deref(), options).await
+        }
+        HopRequest::FsWrite { path, content, root, encoding, create_if_missing, append, expected_hash, expected_mtime } => {
+            let precondition = atomic_write::Precondition { expected_hash, expected_mtime };
+            let options = fs_handlers::WriteOptions { encoding, create_if_missing, append, precondition };
+            fs_handlers::write(&path, content, root.or_else(|| root_for(&path)).as_deref(), options).await
+        }
+        HopRequest::FsDelete { path, root, recursive } => {
+            trash::delete(&trash, &path, root.or_else(|| root_for(&path)).as_deref(), recursive).await
+        }
+        HopRequest::FsRestore { id, root } => {
+            let roots = root.map(|r| vec![r]).unwrap_or_else(|| workspace_state.roots());
+            trash::restore(&trash, id, roots).await
+        }
+        HopRequest::FsTrashList { root } => {
+            let roots = root.map(|r| vec![r]).unwrap_or_else(|| workspace_state.roots());
+            trash::list(&trash, roots).await
+        }
+        HopRequest::FsRename { from, to, root, overwrite } => {
+            let root = root.or_else(|| root_for(&from));
+            fs_ops::rename(&trash, from, to, root, overwrite).await
+        }
+        HopRequest::FsCopy { from, to, root, on_conflict } => {
+            let root = root.or_else(|| root_for(&from));
+            fs_ops::copy(from, to, root, on_conflict).await
+        }
+        HopRequest::FsMkdir { path, root } => fs_ops::mkdir(&path, root.or_else(|| root_for(&path)).as_deref()).await,
+        HopRequest::FsStat { path, root } => fs_ops::stat(&path, root.or_else(|| root_for(&path)).as_deref()).await,
+        HopRequest::FsApplyEdits { root, files, patch, dry_run } => {
+            patch::apply_edits(root.or(workspace_root).as_deref(), files, patch, dry_run).await
+        }
+        HopRequest::FsSearch { query, root } => fs_handlers::search(&query, root.or(workspace_root).as_deref()).await,
+        HopRequest::FsGrep { query, root, path, regex, case_sensitive, whole_word, include_globs, exclude_globs, max_results, context_lines } => {
+            let root = root.or_else(|| path.as_deref().and_then(root_for)).or(workspace_root);
+            let options = grep::GrepOptions { query, path, regex, case_sensitive, whole_word, include_globs, exclude_globs, max_results, context_lines };
+            grep::grep(&app, &grep_state, message.id.clone(), root.as_deref(), options).await
+        }
+        HopRequest::FsGrepCancel { search_id } => grep::cancel(&grep_state, &search_id).await,
+        HopRequest::WorkspaceOpen { root, mut roots, id, name } => {
+            roots.splice(0..0, root);
+            let resp = workspace::open(&workspace_state, &workspace_store, roots, id, name).await;
+            watch_state.retain_within(&workspace_state.roots());
+            resp
+        }
+        HopRequest::WorkspaceClose => {
+            watch_state.retain_within(&[]);
+            workspace::close(&workspace_state).await
+        }
+        HopRequest::WorkspaceUpdateRoots { add, remove } => {
+            let resp = workspace::update_roots(&workspace_state, &workspace_store, add, remove).await;
+            watch_state.retain_within(&workspace_state.roots());
+            resp
+        }
+        HopRequest::WorkspaceRecent { limit } => workspace::recent(&workspace_store, limit).await,
+        HopRequest::WorkspaceForget { id } => workspace::forget(&workspace_store, &id).await,
+        HopRequest::WorkspaceSettings { id, update } => workspace::settings(&workspace_state, &workspace_store, id, update).await,
+        HopRequest::WorkspaceList { root, depth, cursor, limit, sort, include_ignored } => {
+            let options = workspace::ListOptions { depth, cursor, limit, sort, include_ignored };
+            workspace::list(&root, root_for(&root).as_deref(), options).await
+        }
+        HopRequest::WorkspaceWatch { root } => {
+            let workspace_root = root.as_deref().map(root_for).unwrap_or(workspace_root);
+            watcher::watch(&app, &watch_state, root.as_deref(), workspace_root.as_deref()).await
+        }
+        HopRequest::WorkspaceUnwatch { root } => {
+            let workspace_root = root.as_deref().map(root_for).unwrap_or(workspace_root);
+            watcher::unwatch(&watch_state, root.as_deref(), workspace_root.as_deref()).await
+        }
+        HopRequest::TerminalSpawn { id, shell, args, cwd, env, login_shell, cols, rows } => {
+            let workspace_root = cwd.as_deref().map(root_for).unwrap_or(workspace_root);
+            let options = terminal::SpawnOptions { shell, args, cwd, env, login_shell, cols, rows };
+            terminal::spawn(&app, &term_state, workspace_root, id, options).await
         }
         HopRequest::TerminalWrite { id, data } => terminal::write(&term_state, &id, &data).await,
         HopRequest::TerminalResize { id, cols, rows } => terminal::resize(&term_state, &id, cols, rows).await,
         HopRequest::TerminalKill { id, signal } => terminal::kill(&term_state, &id, signal).await,
-        HopRequest::LspRequest { server, payload } => lsp::dispatch(&app, &lsp_state, &server, payload).await,
+        HopRequest::TerminalAttach { id } => terminal::attach(&term_state, &id).await,
+        HopRequest::TerminalList => terminal::list(&term_state).await,
+        HopRequest::TerminalInfo { id } => terminal::info(&term_state, &id).await,
+        HopRequest::LspRequest { server, payload, timeout_ms } => {
+            lsp::dispatch(&app, &lsp_state, workspace_state.root().as_deref(), &server, payload, timeout_ms).await
+        }
+        HopRequest::LspStop { server } => lsp::stop(&lsp_state, &server).await,
+        HopRequest::LspRestart { server } => {
+            lsp::restart(&app, &lsp_state, workspace_state.root().as_deref(), &server).await
+        }
+        HopRequest::LspServers { language, path } => {
+            lsp::servers(&app, &lsp_state, workspace_state.root(), language, path).await
+        }
+        HopRequest::LspDiagnostics { path, root, server, timeout_ms } => {
+            let root = root.or_else(|| path.as_deref().map_or_else(|| workspace_root.clone(), root_for));
+            let target = path.map(|path| lsp_tools::Target { path, root: root.clone(), server });
+            lsp_tools::diagnostics(&app, &lsp_state, workspace_root.as_deref(), target, root, timeout_ms).await
+        }
+        HopRequest::LspDefinition { path, position, root, server } => {
+            let root = root.or_else(|| root_for(&path));
+            let target = lsp_tools::Target { path, root, server };
+            lsp_tools::definition(&app, &lsp_state, workspace_root.as_deref(), target, position).await
+        }
+        HopRequest::LspReferences { path, position, include_declaration, root, server } => {
+            let root = root.or_else(|| root_for(&path));
+            let target = lsp_tools::Target { path, root, server };
+            let include_declaration = include_declaration.unwrap_or(true);
+            lsp_tools::references(&app, &lsp_state, workspace_root.as_deref(), target, position, include_declaration).await
+        }
+        HopRequest::LspHover { path, position, root, server } => {
+            let root = root.or_else(|| root_for(&path));
+            let target = lsp_tools::Target { path, root, server };
+            lsp_tools::hover(&app, &lsp_state, workspace_root.as_deref(), target, position).await
+        }
+        HopRequest::LspSymbols { path, query, root, server } => {
+            let root = root.or_else(|| path.as_deref().map_or_else(|| workspace_root.clone(), root_for));
+            match path {
+                Some(path) => {
+                    let target = lsp_tools::Target { path, root: root.clone(), server };
+                    lsp_tools::symbols(&app, &lsp_state, workspace_root.as_deref(), Some(target), query, root, None).await
+                }
+                None => lsp_tools::symbols(&app, &lsp_state, workspace_root.as_deref(), None, query, root, server).await,
+            }
+        }
     };
 
     Ok(HopResponseMessage { v: HOP_IPC_VERSION, id: message.id, response: resp })
@@ -105,6 +241,174 @@ fn hop_memory_load_project(
     store.load_for_project(&args.project_id).map_err(|e| e.to_string())
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LoadSessionArgs {
+    session_id: String,
+}
+
+#[tauri::command]
+fn hop_memory_load_session(
+    state: State<MemoryState>,
+    args: LoadSessionArgs,
+) -> Result<Vec<MemoryItem>, String> {
+    let store = state.store.lock().map_err(|_| "Memory store poisoned".to_string())?;
+    store.load_for_session(&args.session_id).map_err(|e| e.to_string())
+}
+
+/// `kind` plus the project or session id it needs.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MemoryScopeArgs {
+    kind: String,
+    project_id: Option<String>,
+    session_id: Option<String>,
+}
+
+impl MemoryScopeArgs {
+    fn scope(&self) -> Result<Scope<'_>, String> {
+        Scope::new(&self.kind, self.project_id.as_deref(), self.session_id.as_deref())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MemoryKeyArgs {
+    #[serde(flatten)]
+    scope: MemoryScopeArgs,
+    key: String,
+}
+
+#[tauri::command]
+fn hop_memory_get(state: State<MemoryState>, args: MemoryKeyArgs) -> Result<Option<MemoryItem>, String> {
+    let store = state.store.lock().map_err(|_| "Memory store poisoned".to_string())?;
+    store.get(args.scope.scope()?, &args.key).map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpsertMemoryArgs {
+    #[serde(flatten)]
+    scope: MemoryScopeArgs,
+    key: String,
+    value: serde_json::Value,
+    ttl_seconds: Option<i64>,
+}
+
+#[tauri::command]
+fn hop_memory_upsert(state: State<MemoryState>, args: UpsertMemoryArgs) -> Result<SaveMemoryResult, String> {
+    let expires_at = args.ttl_seconds.map(|ttl| chrono::Utc::now().timestamp() + ttl);
+    let json = serde_json::to_string(&args.value).map_err(|e| e.to_string())?;
+    let mut store = state.store.lock().map_err(|_| "Memory store poisoned".to_string())?;
+    let id = store.upsert(args.scope.scope()?, &args.key, &json, expires_at).map_err(|e| e.to_string())?;
+    Ok(SaveMemoryResult { id })
+}
+
+#[derive(Debug, Serialize)]
+struct DeleteMemoryResult {
+    deleted: usize,
+}
+
+#[tauri::command]
+fn hop_memory_delete(state: State<MemoryState>, args: MemoryKeyArgs) -> Result<DeleteMemoryResult, String> {
+    let store = state.store.lock().map_err(|_| "Memory store poisoned".to_string())?;
+    let deleted = store.delete(args.scope.scope()?, &args.key).map_err(|e| e.to_string())?;
+    Ok(DeleteMemoryResult { deleted })
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryMemoryArgs {
+    #[serde(flatten)]
+    scope: MemoryScopeArgs,
+    prefix: Option<String>,
+    /// SQLite JSON path into the value, e.g. `$.tags[0]`
+    path: Option<String>,
+    /// Value the item must have at `path`
+    equals: Option<serde_json::Value>,
+}
+
+impl QueryMemoryArgs {
+    fn filter(&self) -> Result<MemoryFilter, String> {
+        if self.equals.is_some() && self.path.is_none() {
+            return Err("equals needs a path".into());
+        }
+        Ok(MemoryFilter {
+            key_prefix: self.prefix.clone(),
+            json_path: self.path.clone(),
+            equals: self.equals.as_ref().map(|v| v.to_string()),
+        })
+    }
+}
+
+#[tauri::command]
+fn hop_memory_query(state: State<MemoryState>, args: QueryMemoryArgs) -> Result<Vec<MemoryItem>, String> {
+    let store = state.store.lock().map_err(|_| "Memory store poisoned".to_string())?;
+    store.query(args.scope.scope()?, &args.filter()?).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn hop_memory_list_keys(state: State<MemoryState>, args: QueryMemoryArgs) -> Result<Vec<String>, String> {
+    let store = state.store.lock().map_err(|_| "Memory store poisoned".to_string())?;
+    store.list_keys(args.scope.scope()?, &args.filter()?).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn hop_memory_list_projects(state: State<MemoryState>) -> Result<Vec<String>, String> {
+    let store = state.store.lock().map_err(|_| "Memory store poisoned".to_string())?;
+    store.list_projects().map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchMemoryArgs {
+    #[serde(flatten)]
+    scope: MemoryScopeArgs,
+    query: String,
+    limit: Option<usize>,
+    /// `"text"` (the default) ranks by full-text match, `"semantic"` by
+    /// embedding similarity.
+    mode: Option<String>,
+}
+
+#[tauri::command]
+fn hop_memory_search(state: State<MemoryState>, args: SearchMemoryArgs) -> Result<Vec<MemorySearchHit>, String> {
+    let store = state.store.lock().map_err(|_| "Memory store poisoned".to_string())?;
+    let scope = args.scope.scope()?;
+    let limit = args.limit.unwrap_or(20);
+    match args.mode.as_deref().unwrap_or("text") {
+        "text" => store.search(scope, &args.query, limit).map_err(|e| e.to_string()),
+        "semantic" => store.semantic_search(scope, &args.query, limit),
+        other => Err(format!("Unknown search mode: {}", other)),
+    }
+}
+
+#[tauri::command]
+fn hop_memory_stats(state: State<MemoryState>) -> Result<MemoryStats, String> {
+    let store = state.store.lock().map_err(|_| "Memory store poisoned".to_string())?;
+    store.stats().map_err(|e| e.to_string())
+}
+
+/// Sweeps expired memory now and then, compacting the database every few
+/// sweeps. Runs for the life of the app.
+async fn sweep_memory(app: tauri::AppHandle) {
+    let mut interval = tokio::time::interval(memory_store::SWEEP_INTERVAL);
+    for sweep in 1u32.. {
+        interval.tick().await;
+        let handle = app.clone();
+        let res = tauri::async_runtime::spawn_blocking(move || {
+            let state = handle.state::<MemoryState>();
+            let store = state.store.lock().map_err(|_| "Memory store poisoned".to_string())?;
+            store.maintain(sweep % memory_store::COMPACT_EVERY == 0).map_err(|e| e.to_string())
+        })
+        .await;
+        if let Err(e) = res.map_err(|e| e.to_string()).and_then(|r| r) {
+            let event = HopEvent::Log { level: "error".into(), message: format!("Memory sweep failed: {}", e), scope: Some("memory".into()) };
+            let _ = app.emit_all(HOP_EVENT_CHANNEL, HopNotificationMessage { v: HOP_IPC_VERSION, event });
+        }
+    }
+}
+
 fn main() {
     println!("Starting HopCoder...");
     tauri::Builder::default()
@@ -120,21 +424,50 @@ fn main() {
                 .to_str()
                 .ok_or_else(|| "invalid db path")?;
             
-            let store = MemoryStore::new(db_path_str)?;
+            let mut store = MemoryStore::new(db_path_str)?;
+            store.set_embedder(Some(Arc::new(memory_embedding::HashEmbedding::default())));
 
             app.manage(MemoryState {
                 store: Mutex::new(store),
             });
+            tauri::async_runtime::spawn(sweep_memory(app.handle()));
+
+            let workspaces_path = app_dir.join("hopcoder_workspaces.sqlite3");
+            app.manage(WorkspaceStore::new(&workspaces_path.to_string_lossy())?);
+
+            let trash = trash::Trash::new(app_dir.join("trash"));
+            app.manage(trash.clone());
+            tauri::async_runtime::spawn_blocking(move || trash.purge(None));
 
             Ok(())
         })
         .manage(terminal::TerminalManager::default())
         .manage(lsp::LspManager::default())
+        .manage(workspace::WorkspaceState::default())
+        .manage(grep::GrepManager::default())
+        .manage(watcher::WatchManager::default())
         .invoke_handler(tauri::generate_handler![
             hop_ipc,
             hop_memory_save,
-            hop_memory_load_project
+            hop_memory_load_project,
+            hop_memory_load_session,
+            hop_memory_get,
+            hop_memory_upsert,
+            hop_memory_delete,
+            hop_memory_query,
+            hop_memory_list_keys,
+            hop_memory_list_projects,
+            hop_memory_search,
+            hop_memory_stats
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                // Let language servers save state and exit rather than
+                // leaving them orphaned.
+                let lsp = app.state::<lsp::LspManager>().inner().clone();
+                tauri::async_runtime::block_on(lsp.shutdown_all());
+            }
+        });
 }
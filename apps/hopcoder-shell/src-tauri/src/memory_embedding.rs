@@ -0,0 +1,136 @@
+use serde_json::Value;
+
+/// Turns text into a vector for semantic memory search. Implementations
+/// must run locally; memory never leaves the machine.
+pub trait EmbeddingProvider: Send + Sync {
+    /// Identifies the model. Stored with each vector, so vectors from a
+    /// different model are recomputed instead of compared.
+    fn model(&self) -> &str;
+    fn embed(&self, text: &str) -> Result<Vec<f32>, String>;
+}
+
+/// Feature-hashed bag of words: each word adds ±1 to one of `dims`
+/// buckets. Crude next to a learned model, but instant, dependency-free
+/// and good at matching memories that share vocabulary with the query.
+pub struct HashEmbedding {
+    model: String,
+    dims: usize,
+}
+
+impl HashEmbedding {
+    pub fn new(dims: usize) -> Self {
+        Self { model: format!("hash-{dims}-v1"), dims }
+    }
+}
+
+impl Default for HashEmbedding {
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+/// FNV-1a; unlike std's hashers it is guaranteed stable, which stored
+/// vectors rely on.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |h, b| (h ^ *b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+impl EmbeddingProvider for HashEmbedding {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>, String> {
+        let mut vector = vec![0.0; self.dims];
+        for word in words(text) {
+            let h = fnv1a(word.as_bytes());
+            let sign = if h >> 63 == 0 { 1.0 } else { -1.0 };
+            vector[(h % self.dims as u64) as usize] += sign;
+        }
+        Ok(vector)
+    }
+}
+
+/// Lowercased runs of letters and digits.
+pub fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).map(str::to_lowercase)
+}
+
+/// What gets embedded for an item: its key and the text in its JSON value
+/// (strings, numbers and object keys), without the JSON syntax.
+pub fn item_text(key: &str, value_json: &str) -> String {
+    fn collect(value: &Value, out: &mut String) {
+        match value {
+            Value::String(s) => {
+                out.push(' ');
+                out.push_str(s);
+            }
+            Value::Number(n) => {
+                out.push(' ');
+                out.push_str(&n.to_string());
+            }
+            Value::Array(items) => items.iter().for_each(|v| collect(v, out)),
+            Value::Object(map) => {
+                for (k, v) in map {
+                    out.push(' ');
+                    out.push_str(k);
+                    collect(v, out);
+                }
+            }
+            Value::Bool(_) | Value::Null => {}
+        }
+    }
+    let mut text = key.to_string();
+    match serde_json::from_str(value_json) {
+        Ok(value) => collect(&value, &mut text),
+        Err(_) => {
+            text.push(' ');
+            text.push_str(value_json);
+        }
+    }
+    text
+}
+
+pub fn to_blob(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+pub fn from_blob(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+}
+
+/// Cosine similarity; 0 for vectors of different length or zero length.
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let (mut dot, mut na, mut nb) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        na += x * x;
+        nb += y * y;
+    }
+    if na == 0.0 || nb == 0.0 {
+        0.0
+    } else {
+        dot / (na.sqrt() * nb.sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_embedding_matches_shared_words() {
+        let embedder = HashEmbedding::default();
+        let query = embedder.embed("tabs or spaces?").unwrap();
+        let style = embedder.embed(&item_text("conv.style", r#"{"indent":"spaces","width":4}"#)).unwrap();
+        let stack = embedder.embed(&item_text("stack", r#""rust, tauri and react""#)).unwrap();
+        assert!(cosine(&query, &style) > cosine(&query, &stack));
+        assert_eq!(embedder.embed("Spaces").unwrap(), embedder.embed("spaces").unwrap());
+        assert_eq!(from_blob(&to_blob(&style)), style);
+        assert_eq!(cosine(&query, &[0.0; 3]), 0.0);
+        assert_eq!(item_text("k", r#"{"a":["x",1,true]}"#), "k a x 1");
+    }
+}
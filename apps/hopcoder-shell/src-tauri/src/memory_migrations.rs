@@ -0,0 +1,225 @@
+use rusqlite::{Connection, Transaction};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// One schema change; migration `i` takes the database from version `i` to
+/// `i + 1`, as recorded in `PRAGMA user_version`.
+type Migration = fn(&Transaction) -> rusqlite::Result<()>;
+
+/// Every schema change, oldest first. Append only: released migrations have
+/// already run on users' databases and must not change.
+const MIGRATIONS: &[Migration] = &[create_memory_table, add_accessed_at, add_search];
+
+/// The schema version this build writes.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Version 1: the original table. Databases from before versioning already
+/// have it at version 0, hence `IF NOT EXISTS`.
+fn create_memory_table(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS memory (
+          id TEXT PRIMARY KEY,
+          kind TEXT NOT NULL CHECK (kind IN ('project', 'session')),
+          project_id TEXT,
+          session_id TEXT,
+          key TEXT NOT NULL,
+          value_json TEXT NOT NULL,
+          created_at INTEGER NOT NULL,
+          expires_at INTEGER
+        );
+        CREATE INDEX IF NOT EXISTS idx_memory_project_kind_key
+          ON memory (project_id, kind, key);
+        CREATE INDEX IF NOT EXISTS idx_memory_session_kind_key
+          ON memory (session_id, kind, key);
+        CREATE INDEX IF NOT EXISTS idx_memory_expires_at
+          ON memory (expires_at);
+        "#,
+    )
+}
+
+/// Version 2: last access time, for LRU eviction. Unversioned databases
+/// may have it already, from a build that added it without migrations.
+fn add_accessed_at(tx: &Transaction) -> rusqlite::Result<()> {
+    if !has_column(tx, "memory", "accessed_at")? {
+        tx.execute_batch("ALTER TABLE memory ADD COLUMN accessed_at INTEGER")?;
+    }
+    Ok(())
+}
+
+/// Version 3: an FTS5 index over keys and values, kept in sync by
+/// triggers, and a slot for each item's embedding and the model behind it.
+fn add_search(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        r#"
+        ALTER TABLE memory ADD COLUMN embedding BLOB;
+        ALTER TABLE memory ADD COLUMN embedding_model TEXT;
+        CREATE VIRTUAL TABLE memory_fts USING fts5(
+          key, value_json,
+          content = 'memory', content_rowid = 'rowid',
+          tokenize = 'unicode61 remove_diacritics 2'
+        );
+        CREATE TRIGGER memory_fts_insert AFTER INSERT ON memory BEGIN
+          INSERT INTO memory_fts (rowid, key, value_json) VALUES (new.rowid, new.key, new.value_json);
+        END;
+        CREATE TRIGGER memory_fts_delete AFTER DELETE ON memory BEGIN
+          INSERT INTO memory_fts (memory_fts, rowid, key, value_json) VALUES ('delete', old.rowid, old.key, old.value_json);
+        END;
+        CREATE TRIGGER memory_fts_update AFTER UPDATE OF key, value_json ON memory BEGIN
+          INSERT INTO memory_fts (memory_fts, rowid, key, value_json) VALUES ('delete', old.rowid, old.key, old.value_json);
+          INSERT INTO memory_fts (rowid, key, value_json) VALUES (new.rowid, new.key, new.value_json);
+        END;
+        INSERT INTO memory_fts (memory_fts) VALUES ('rebuild');
+        "#,
+    )
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> rusqlite::Result<bool> {
+    conn.prepare("SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2")?.exists([table, column])
+}
+
+#[derive(Debug)]
+pub enum MigrationError {
+    /// The database was written by a newer HopCoder; this one might
+    /// misread or damage it.
+    TooNew { version: u32, supported: u32 },
+    /// Copying the database aside before upgrading failed; it was left as is.
+    Backup(PathBuf, String),
+    Sqlite(rusqlite::Error),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::TooNew { version, supported } => write!(
+                f,
+                "Memory database is at schema version {version} but this build supports up to {supported}; please update HopCoder"
+            ),
+            MigrationError::Backup(path, e) => write!(f, "Failed to back up memory database to {}: {e}", path.display()),
+            MigrationError::Sqlite(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<rusqlite::Error> for MigrationError {
+    fn from(e: rusqlite::Error) -> Self {
+        MigrationError::Sqlite(e)
+    }
+}
+
+pub fn user_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Where the database at `db_path` is copied before upgrading from `version`.
+pub fn backup_path(db_path: &str, version: u32) -> PathBuf {
+    PathBuf::from(format!("{db_path}.v{version}.bak"))
+}
+
+/// Brings the database at `db_path` up to [`SCHEMA_VERSION`], one
+/// transaction per migration. A database with data is first copied to
+/// [`backup_path`]; a newer one is refused untouched. Returns the version
+/// it was at.
+pub fn migrate(conn: &mut Connection, db_path: &str) -> Result<u32, MigrationError> {
+    let version = user_version(conn)?;
+    if version > SCHEMA_VERSION {
+        return Err(MigrationError::TooNew { version, supported: SCHEMA_VERSION });
+    }
+    if version == SCHEMA_VERSION {
+        return Ok(version);
+    }
+
+    let in_memory = db_path.is_empty() || db_path == ":memory:";
+    let has_data = conn.prepare("SELECT 1 FROM sqlite_master WHERE type = 'table'")?.exists([])?;
+    if has_data && !in_memory {
+        let backup = backup_path(db_path, version);
+        if Path::new(&backup).exists() {
+            // Left by an earlier attempt; VACUUM INTO won't overwrite it.
+            std::fs::remove_file(&backup).map_err(|e| MigrationError::Backup(backup.clone(), e.to_string()))?;
+        }
+        // VACUUM INTO takes a consistent copy, including what is still in the WAL.
+        conn.execute("VACUUM INTO ?1", [backup.to_string_lossy()]).map_err(|e| MigrationError::Backup(backup, e.to_string()))?;
+    }
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let tx = conn.transaction()?;
+        migration(&tx)?;
+        tx.pragma_update(None, "user_version", from as u32 + 1)?;
+        tx.commit()?;
+    }
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::params;
+
+    /// The schema as shipped before versioning, with one row in it.
+    fn legacy_db(path: &str) {
+        let conn = Connection::open(path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE memory (id TEXT PRIMARY KEY, kind TEXT NOT NULL CHECK (kind IN ('project', 'session')), \
+             project_id TEXT, session_id TEXT, key TEXT NOT NULL, value_json TEXT NOT NULL, created_at INTEGER NOT NULL, \
+             expires_at INTEGER)",
+        )
+        .unwrap();
+        conn.execute("INSERT INTO memory VALUES ('1', 'project', 'p', NULL, 'notes', '\"hi\"', 0, NULL)", params![]).unwrap();
+    }
+
+    #[test]
+    fn test_migrates_each_step_and_backs_up_legacy_db() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("memory.sqlite3").to_string_lossy().to_string();
+        legacy_db(&path);
+
+        let mut conn = Connection::open(&path).unwrap();
+        assert_eq!(migrate(&mut conn, &path).unwrap(), 0);
+        assert_eq!(user_version(&conn).unwrap(), SCHEMA_VERSION);
+        assert!(has_column(&conn, "memory", "accessed_at").unwrap());
+        let value: String = conn.query_row("SELECT value_json FROM memory WHERE id = '1'", [], |r| r.get(0)).unwrap();
+        assert_eq!(value, "\"hi\"");
+        // Existing rows are indexed for search.
+        let hits: i64 = conn.query_row("SELECT COUNT(*) FROM memory_fts WHERE memory_fts MATCH 'notes'", [], |r| r.get(0)).unwrap();
+        assert_eq!(hits, 1);
+        // Up to date: nothing to do, no new backup.
+        assert_eq!(migrate(&mut conn, &path).unwrap(), SCHEMA_VERSION);
+        assert!(!Path::new(&backup_path(&path, SCHEMA_VERSION)).exists());
+
+        let backup = Connection::open(backup_path(&path, 0)).unwrap();
+        assert_eq!(user_version(&backup).unwrap(), 0);
+        assert!(!has_column(&backup, "memory", "accessed_at").unwrap());
+
+        // A fresh database has nothing to back up.
+        let fresh_path = dir.path().join("fresh.sqlite3").to_string_lossy().to_string();
+        let mut fresh = Connection::open(&fresh_path).unwrap();
+        assert_eq!(migrate(&mut fresh, &fresh_path).unwrap(), 0);
+        assert!(!Path::new(&backup_path(&fresh_path, 0)).exists());
+
+        // Each step on its own.
+        let mut steps = Connection::open_in_memory().unwrap();
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            let tx = steps.transaction().unwrap();
+            migration(&tx).unwrap();
+            tx.commit().unwrap();
+            assert_eq!(has_column(&steps, "memory", "accessed_at").unwrap(), i >= 1);
+            assert_eq!(has_column(&steps, "memory", "embedding").unwrap(), i >= 2);
+        }
+    }
+
+    #[test]
+    fn test_refuses_newer_db() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("memory.sqlite3").to_string_lossy().to_string();
+        legacy_db(&path);
+        let mut conn = Connection::open(&path).unwrap();
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1).unwrap();
+
+        let err = migrate(&mut conn, &path).unwrap_err();
+        assert!(matches!(err, MigrationError::TooNew { version, .. } if version == SCHEMA_VERSION + 1));
+        assert!(!has_column(&conn, "memory", "accessed_at").unwrap());
+        assert!(!Path::new(&backup_path(&path, SCHEMA_VERSION + 1)).exists());
+    }
+}
@@ -1,7 +1,23 @@
-use rusqlite::{params, Connection};
+use crate::memory_embedding::{self, EmbeddingProvider};
+use crate::memory_migrations::{self, MigrationError};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row, ToSql};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
 use uuid::Uuid;
 
+const COLUMNS: &str = "id, kind, project_id, session_id, key, value_json, created_at, expires_at";
+const NOT_EXPIRED: &str = "(expires_at IS NULL OR expires_at > strftime('%s','now'))";
+/// Bytes an item counts against its quota.
+const ITEM_BYTES: &str = "length(CAST(key AS BLOB)) + length(CAST(value_json AS BLOB))";
+/// How often expired items are swept; every [`COMPACT_EVERY`]th sweep
+/// also compacts the database.
+pub const SWEEP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+pub const COMPACT_EVERY: u32 = 6;
+/// Free pages beyond this share of the file make [`MemoryStore::maintain`]
+/// VACUUM.
+const VACUUM_FREE_RATIO: f64 = 0.25;
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MemoryItem {
@@ -15,34 +31,202 @@ pub struct MemoryItem {
     pub expires_at: Option<i64>,
 }
 
+/// Whose memory a call addresses. A key is unique within a scope for
+/// [`MemoryStore::upsert`]; [`MemoryStore::save`] may add duplicates.
+#[derive(Debug, Clone, Copy)]
+pub enum Scope<'a> {
+    Project(&'a str),
+    Session(&'a str),
+}
+
+impl<'a> Scope<'a> {
+    /// Builds a scope from a `kind` and the matching id.
+    pub fn new(kind: &str, project_id: Option<&'a str>, session_id: Option<&'a str>) -> Result<Self, String> {
+        match (kind, project_id, session_id) {
+            ("project", Some(id), _) => Ok(Scope::Project(id)),
+            ("session", _, Some(id)) => Ok(Scope::Session(id)),
+            ("project", None, _) => Err("projectId is required for project memory".into()),
+            ("session", _, None) => Err("sessionId is required for session memory".into()),
+            _ => Err(format!("Unknown memory kind: {kind}")),
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Scope::Project(_) => "project",
+            Scope::Session(_) => "session",
+        }
+    }
+
+    fn id(&self) -> &'a str {
+        match self {
+            Scope::Project(id) | Scope::Session(id) => id,
+        }
+    }
+
+    /// SQL condition selecting the scope's rows, binding `?1` (kind) and
+    /// `?2` (id) in the column order of its index.
+    fn condition(&self) -> &'static str {
+        match self {
+            Scope::Project(_) => "project_id = ?2 AND kind = ?1",
+            Scope::Session(_) => "session_id = ?2 AND kind = ?1",
+        }
+    }
+}
+
+/// Narrows [`MemoryStore::query`] and [`MemoryStore::list_keys`]; empty
+/// fields match everything.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryFilter {
+    pub key_prefix: Option<String>,
+    /// SQLite JSON path into the value, e.g. `$.tags[0]`; items without
+    /// anything at the path are left out
+    pub json_path: Option<String>,
+    /// JSON the value at `json_path` must equal
+    pub equals: Option<String>,
+}
+
+impl MemoryFilter {
+    /// Appends the filter's conditions to `sql`, binding after `params`.
+    fn apply(&self, sql: &mut String, params: &mut Vec<Box<dyn ToSql>>) {
+        if let Some(prefix) = &self.key_prefix {
+            params.push(Box::new(prefix.clone()));
+            sql.push_str(&format!(" AND substr(key, 1, length(?{n})) = ?{n}", n = params.len()));
+        }
+        if let Some(path) = &self.json_path {
+            params.push(Box::new(path.clone()));
+            let path = params.len();
+            match &self.equals {
+                Some(value) => {
+                    params.push(Box::new(value.clone()));
+                    sql.push_str(&format!(
+                        " AND json_extract(value_json, ?{path}) IS json_extract(?{value}, '$')",
+                        value = params.len()
+                    ));
+                }
+                None => sql.push_str(&format!(" AND json_type(value_json, ?{path}) IS NOT NULL")),
+            }
+        }
+    }
+}
+
+/// Limits on one project's or session's memory. Past either, the least
+/// recently used items are evicted.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Quota {
+    pub max_rows: u64,
+    /// Key plus JSON value, in bytes
+    pub max_bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Quotas {
+    pub project: Quota,
+    pub session: Quota,
+}
+
+impl Default for Quotas {
+    fn default() -> Self {
+        Self {
+            project: Quota { max_rows: 2_000, max_bytes: 8 * 1024 * 1024 },
+            session: Quota { max_rows: 500, max_bytes: 2 * 1024 * 1024 },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScopeUsage {
+    pub id: String,
+    pub rows: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryStats {
+    /// Live items and their size against the quotas
+    pub rows: u64,
+    pub bytes: u64,
+    /// Expired items the next sweep will delete
+    pub expired_rows: u64,
+    /// Size of the database file, of its free pages, and of the WAL
+    pub file_bytes: u64,
+    pub free_bytes: u64,
+    pub wal_bytes: u64,
+    pub projects: Vec<ScopeUsage>,
+    pub sessions: Vec<ScopeUsage>,
+    pub quotas: Quotas,
+}
+
+/// A search result; higher `score` is more relevant. Scores are only
+/// comparable within one search.
+#[derive(Debug, Serialize)]
+pub struct MemorySearchHit {
+    #[serde(flatten)]
+    pub item: MemoryItem,
+    pub score: f64,
+}
+
+/// Turns free text into an FTS5 query matching any of its words, as a
+/// prefix so "conv" finds "conventions". None if it has no words.
+fn fts_query(text: &str) -> Option<String> {
+    let terms: Vec<String> = memory_embedding::words(text).map(|w| format!("\"{w}\"*")).collect();
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" OR "))
+    }
+}
+
+fn item_from_row(row: &Row) -> rusqlite::Result<MemoryItem> {
+    Ok(MemoryItem {
+        id: row.get(0)?,
+        kind: row.get(1)?,
+        project_id: row.get(2)?,
+        session_id: row.get(3)?,
+        key: row.get(4)?,
+        value_json: row.get(5)?,
+        created_at: row.get(6)?,
+        expires_at: row.get(7)?,
+    })
+}
+
 pub struct MemoryStore {
     conn: Connection,
+    path: String,
+    quotas: Quotas,
+    embedder: Option<Arc<dyn EmbeddingProvider>>,
 }
 
 impl MemoryStore {
-    pub fn new(db_path: &str) -> rusqlite::Result<Self> {
-        let conn = Connection::open(db_path)?;
-        conn.execute_batch(
-            r#"
-            CREATE TABLE IF NOT EXISTS memory (
-              id TEXT PRIMARY KEY,
-              kind TEXT NOT NULL CHECK (kind IN ('project', 'session')),
-              project_id TEXT,
-              session_id TEXT,
-              key TEXT NOT NULL,
-              value_json TEXT NOT NULL,
-              created_at INTEGER NOT NULL,
-              expires_at INTEGER
-            );
-            CREATE INDEX IF NOT EXISTS idx_memory_project_kind_key
-              ON memory (project_id, kind, key);
-            CREATE INDEX IF NOT EXISTS idx_memory_session_kind_key
-              ON memory (session_id, kind, key);
-            CREATE INDEX IF NOT EXISTS idx_memory_expires_at
-              ON memory (expires_at);
-            "#,
-        )?;
-        Ok(Self { conn })
+    /// Opens the database, migrating it to the current schema first.
+    pub fn new(db_path: &str) -> Result<Self, MigrationError> {
+        let mut conn = Connection::open(db_path)?;
+        memory_migrations::migrate(&mut conn, db_path)?;
+        // WAL keeps the periodic sweeps from blocking readers.
+        conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+        Ok(Self { conn, path: db_path.to_string(), quotas: Quotas::default(), embedder: None })
+    }
+
+    /// Sets the provider used to embed items for semantic search. Items
+    /// written without one, or with a different model, are embedded on the
+    /// next semantic search.
+    pub fn set_embedder(&mut self, embedder: Option<Arc<dyn EmbeddingProvider>>) {
+        self.embedder = embedder;
+    }
+
+    /// Stores the item's embedding, or clears it if there is no provider or
+    /// it fails; a missing embedding never fails a write.
+    fn embed_item(&self, id: &str, key: &str, value_json: &str) -> rusqlite::Result<()> {
+        let embedded = self.embedder.as_ref().and_then(|embedder| {
+            let vector = embedder.embed(&memory_embedding::item_text(key, value_json)).ok()?;
+            Some((memory_embedding::to_blob(&vector), embedder.model().to_string()))
+        });
+        let (blob, model) = embedded.unzip();
+        self.conn.execute("UPDATE memory SET embedding = ?2, embedding_model = ?3 WHERE id = ?1", params![id, blob, model])?;
+        Ok(())
     }
 
     pub fn save(
@@ -72,34 +256,393 @@ impl MemoryStore {
                 expires_at
             ],
         )?;
+        self.embed_item(&id, key, value_json)?;
+        if let Ok(scope) = Scope::new(kind, project_id, session_id) {
+            self.evict(scope, &id)?;
+        }
         Ok(id)
     }
 
     pub fn load_for_project(&self, project_id: &str) -> rusqlite::Result<Vec<MemoryItem>> {
-        let mut stmt = self.conn.prepare(
-            r#"
-            SELECT id, kind, project_id, session_id, key, value_json, created_at, expires_at
-            FROM memory
-            WHERE kind = 'project'
-              AND project_id = ?1
-              AND (expires_at IS NULL OR expires_at > strftime('%s','now'))
-            ORDER BY created_at ASC
-            "#,
-        )?;
+        self.query(Scope::Project(project_id), &MemoryFilter::default())
+    }
+
+    pub fn load_for_session(&self, session_id: &str) -> rusqlite::Result<Vec<MemoryItem>> {
+        self.query(Scope::Session(session_id), &MemoryFilter::default())
+    }
 
-        let rows = stmt.query_map([project_id], |row| {
-            Ok(MemoryItem {
-                id: row.get(0)?,
-                kind: row.get(1)?,
-                project_id: row.get(2)?,
-                session_id: row.get(3)?,
-                key: row.get(4)?,
-                value_json: row.get(5)?,
-                created_at: row.get(6)?,
-                expires_at: row.get(7)?,
+    /// Live items of a scope matching `filter`, oldest first.
+    pub fn query(&self, scope: Scope, filter: &MemoryFilter) -> rusqlite::Result<Vec<MemoryItem>> {
+        let mut sql = format!("SELECT {COLUMNS} FROM memory WHERE {} AND {NOT_EXPIRED}", scope.condition());
+        let mut params: Vec<Box<dyn ToSql>> = vec![Box::new(scope.kind()), Box::new(scope.id().to_string())];
+        filter.apply(&mut sql, &mut params);
+        sql.push_str(" ORDER BY created_at ASC, rowid ASC");
+        let mut stmt = self.conn.prepare(&sql)?;
+        let items = stmt.query_map(params_from_iter(params.iter()), item_from_row)?.collect::<rusqlite::Result<Vec<_>>>()?;
+        self.touch(items.iter().map(|item| item.id.as_str()))?;
+        Ok(items)
+    }
+
+    /// Distinct keys of a scope's live items matching `filter`, sorted.
+    pub fn list_keys(&self, scope: Scope, filter: &MemoryFilter) -> rusqlite::Result<Vec<String>> {
+        let mut sql = format!("SELECT DISTINCT key FROM memory WHERE {} AND {NOT_EXPIRED}", scope.condition());
+        let mut params: Vec<Box<dyn ToSql>> = vec![Box::new(scope.kind()), Box::new(scope.id().to_string())];
+        filter.apply(&mut sql, &mut params);
+        sql.push_str(" ORDER BY key ASC");
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(params.iter()), |row| row.get(0))?;
+        rows.collect()
+    }
+
+    /// Project ids with live memory, sorted.
+    pub fn list_projects(&self) -> rusqlite::Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT DISTINCT project_id FROM memory WHERE project_id IS NOT NULL AND {NOT_EXPIRED} ORDER BY project_id ASC"
+        ))?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect()
+    }
+
+    /// The live item stored under `key`; the newest if [`save`](Self::save)
+    /// added several.
+    pub fn get(&self, scope: Scope, key: &str) -> rusqlite::Result<Option<MemoryItem>> {
+        let sql = format!(
+            "SELECT {COLUMNS} FROM memory WHERE {} AND key = ?3 AND {NOT_EXPIRED} ORDER BY created_at DESC, rowid DESC LIMIT 1",
+            scope.condition()
+        );
+        let item = self.conn.query_row(&sql, params![scope.kind(), scope.id(), key], item_from_row).optional()?;
+        self.touch(item.iter().map(|item| item.id.as_str()))?;
+        Ok(item)
+    }
+
+    /// Full-text search over a scope's live items, best match first. Words
+    /// in keys weigh twice as much as words in values.
+    pub fn search(&self, scope: Scope, text: &str, limit: usize) -> rusqlite::Result<Vec<MemorySearchHit>> {
+        let Some(query) = fts_query(text) else {
+            return Ok(Vec::new());
+        };
+        let sql = format!(
+            "SELECT {COLUMNS}, score FROM memory \
+             JOIN (SELECT rowid AS hit, bm25(memory_fts, 2.0, 1.0) AS score FROM memory_fts WHERE memory_fts MATCH ?3) \
+             ON memory.rowid = hit WHERE {} AND {NOT_EXPIRED} ORDER BY score ASC LIMIT ?4",
+            scope.condition()
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let hits = stmt
+            // bm25 is lower for better matches.
+            .query_map(params![scope.kind(), scope.id(), query, limit as i64], |row| {
+                Ok(MemorySearchHit { item: item_from_row(row)?, score: -row.get::<_, f64>(8)? })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        self.touch(hits.iter().map(|hit| hit.item.id.as_str()))?;
+        Ok(hits)
+    }
+
+    /// The `k` live items of a scope whose embeddings are closest (by
+    /// cosine) to the text's, embedding any items that lack one first.
+    /// Fails if there is no embedding provider or it fails on `text`.
+    pub fn semantic_search(&self, scope: Scope, text: &str, k: usize) -> Result<Vec<MemorySearchHit>, String> {
+        let embedder = self.embedder.as_ref().ok_or("No embedding provider is configured")?;
+        let model = embedder.model();
+        let query = embedder.embed(text)?;
+
+        let stale = format!(
+            "SELECT id, key, value_json FROM memory WHERE {} AND {NOT_EXPIRED} \
+             AND (embedding IS NULL OR embedding_model IS NOT ?3)",
+            scope.condition()
+        );
+        let stale = self
+            .conn
+            .prepare(&stale)
+            .and_then(|mut stmt| {
+                let rows = stmt.query_map(params![scope.kind(), scope.id(), model], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+                })?;
+                rows.collect::<rusqlite::Result<Vec<_>>>()
+            })
+            .map_err(|e| e.to_string())?;
+        for (id, key, value_json) in stale {
+            self.embed_item(&id, &key, &value_json).map_err(|e| e.to_string())?;
+        }
+
+        let sql = format!(
+            "SELECT {COLUMNS}, embedding FROM memory WHERE {} AND {NOT_EXPIRED} AND embedding_model = ?3",
+            scope.condition()
+        );
+        let mut hits = self
+            .conn
+            .prepare(&sql)
+            .and_then(|mut stmt| {
+                let rows = stmt.query_map(params![scope.kind(), scope.id(), model], |row| {
+                    let vector = memory_embedding::from_blob(&row.get::<_, Vec<u8>>(8)?);
+                    Ok(MemorySearchHit { item: item_from_row(row)?, score: memory_embedding::cosine(&query, &vector) as f64 })
+                })?;
+                rows.collect::<rusqlite::Result<Vec<_>>>()
             })
-        })?;
+            .map_err(|e| e.to_string())?;
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(k);
+        self.touch(hits.iter().map(|hit| hit.item.id.as_str())).map_err(|e| e.to_string())?;
+        Ok(hits)
+    }
+
+    /// Marks items as used just now, for LRU eviction.
+    fn touch<'a>(&self, ids: impl Iterator<Item = &'a str>) -> rusqlite::Result<()> {
+        let now = chrono::Utc::now().timestamp();
+        let mut stmt = self.conn.prepare_cached("UPDATE memory SET accessed_at = ?2 WHERE id = ?1")?;
+        for id in ids {
+            stmt.execute(params![id, now])?;
+        }
+        Ok(())
+    }
+
+    /// Brings a scope back under its quota after a write, deleting its
+    /// expired items and then the least recently used ones. The item just
+    /// written (`keep`) always stays, even if it alone is over the quota.
+    fn evict(&self, scope: Scope, keep: &str) -> rusqlite::Result<usize> {
+        let quota = match scope {
+            Scope::Project(_) => self.quotas.project,
+            Scope::Session(_) => self.quotas.session,
+        };
+        let expired = format!("DELETE FROM memory WHERE {} AND NOT {NOT_EXPIRED}", scope.condition());
+        let mut evicted = self.conn.execute(&expired, params![scope.kind(), scope.id()])?;
+
+        let sql = format!(
+            "SELECT id, {ITEM_BYTES} FROM memory WHERE {} ORDER BY id = ?3 DESC, COALESCE(accessed_at, created_at) DESC, rowid DESC",
+            scope.condition()
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let items = stmt
+            .query_map(params![scope.kind(), scope.id(), keep], |row| Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let (mut rows, mut bytes) = (0, 0);
+        let mut over = false;
+        for (id, size) in items {
+            rows += 1;
+            bytes += size;
+            over = over || (id != keep && (rows > quota.max_rows || bytes > quota.max_bytes));
+            if over {
+                evicted += self.conn.execute("DELETE FROM memory WHERE id = ?1", [&id])?;
+            }
+        }
+        Ok(evicted)
+    }
+
+    /// Deletes expired items. Returns how many there were.
+    pub fn sweep_expired(&self) -> rusqlite::Result<usize> {
+        self.conn.execute(&format!("DELETE FROM memory WHERE NOT {NOT_EXPIRED}"), [])
+    }
+
+    /// Sweeps expired items and, with `compact`, checkpoints the WAL into
+    /// the database and VACUUMs it if much of the file is free pages.
+    /// Returns how many items were swept.
+    pub fn maintain(&self, compact: bool) -> rusqlite::Result<usize> {
+        let swept = self.sweep_expired()?;
+        if compact {
+            let (pages, free): (i64, i64) = self.conn.query_row(
+                "SELECT (SELECT page_count FROM pragma_page_count), (SELECT freelist_count FROM pragma_freelist_count)",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            if pages > 0 && free as f64 / pages as f64 > VACUUM_FREE_RATIO {
+                // VACUUM may renumber rowids, which the search index refers to.
+                self.conn.execute_batch("VACUUM; INSERT INTO memory_fts (memory_fts) VALUES ('rebuild');")?;
+            }
+            self.conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        }
+        Ok(swept)
+    }
+
+    pub fn stats(&self) -> rusqlite::Result<MemoryStats> {
+        let (rows, bytes, expired_rows) = self.conn.query_row(
+            &format!(
+                "SELECT COUNT(*) FILTER (WHERE {NOT_EXPIRED}), COALESCE(SUM({ITEM_BYTES}) FILTER (WHERE {NOT_EXPIRED}), 0), \
+                 COUNT(*) FILTER (WHERE NOT {NOT_EXPIRED}) FROM memory"
+            ),
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        let (page_size, pages, free): (u64, u64, u64) = self.conn.query_row(
+            "SELECT (SELECT page_size FROM pragma_page_size), (SELECT page_count FROM pragma_page_count), \
+             (SELECT freelist_count FROM pragma_freelist_count)",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        Ok(MemoryStats {
+            rows,
+            bytes,
+            expired_rows,
+            file_bytes: page_size * pages,
+            free_bytes: page_size * free,
+            wal_bytes: std::fs::metadata(format!("{}-wal", self.path)).map(|m| m.len()).unwrap_or(0),
+            projects: self.usage("project_id")?,
+            sessions: self.usage("session_id")?,
+            quotas: self.quotas,
+        })
+    }
+
+    /// Live usage per project or session, largest first.
+    fn usage(&self, column: &str) -> rusqlite::Result<Vec<ScopeUsage>> {
+        let kind = if column == "project_id" { "project" } else { "session" };
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {column}, COUNT(*), SUM({ITEM_BYTES}) FROM memory \
+             WHERE kind = ?1 AND {column} IS NOT NULL AND {NOT_EXPIRED} GROUP BY {column} ORDER BY 3 DESC"
+        ))?;
+        let rows = stmt.query_map([kind], |row| Ok(ScopeUsage { id: row.get(0)?, rows: row.get(1)?, bytes: row.get(2)? }))?;
+        rows.collect()
+    }
+
+    /// Stores `value_json` under `key`, replacing whatever the key held
+    /// (expired or not). The item keeps its id if it existed. Returns the id.
+    pub fn upsert(&mut self, scope: Scope, key: &str, value_json: &str, expires_at: Option<i64>) -> rusqlite::Result<String> {
+        let tx = self.conn.transaction()?;
+        let sql = format!("SELECT id FROM memory WHERE {} AND key = ?3 ORDER BY created_at DESC, rowid DESC", scope.condition());
+        let ids = {
+            let mut stmt = tx.prepare(&sql)?;
+            let rows = stmt.query_map(params![scope.kind(), scope.id(), key], |row| row.get::<_, String>(0))?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        let now = chrono::Utc::now().timestamp();
+        let id = match ids.split_first() {
+            Some((id, stale)) => {
+                tx.execute(
+                    "UPDATE memory SET value_json = ?2, created_at = ?3, expires_at = ?4 WHERE id = ?1",
+                    params![id, value_json, now, expires_at],
+                )?;
+                for stale in stale {
+                    tx.execute("DELETE FROM memory WHERE id = ?1", [stale])?;
+                }
+                id.clone()
+            }
+            None => {
+                let id = Uuid::new_v4().to_string();
+                let (project_id, session_id) = match scope {
+                    Scope::Project(p) => (Some(p), None),
+                    Scope::Session(s) => (None, Some(s)),
+                };
+                tx.execute(
+                    &format!("INSERT INTO memory ({COLUMNS}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"),
+                    params![id, scope.kind(), project_id, session_id, key, value_json, now, expires_at],
+                )?;
+                id
+            }
+        };
+        tx.commit()?;
+        self.embed_item(&id, key, value_json)?;
+        self.evict(scope, &id)?;
+        Ok(id)
+    }
+
+    /// Deletes every item stored under `key`. Returns how many there were.
+    pub fn delete(&self, scope: Scope, key: &str) -> rusqlite::Result<usize> {
+        let sql = format!("DELETE FROM memory WHERE {} AND key = ?3", scope.condition());
+        self.conn.execute(&sql, params![scope.kind(), scope.id(), key])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upsert_replaces_and_scopes_stay_apart() {
+        let mut store = MemoryStore::new(":memory:").unwrap();
+        store.save("project", Some("p"), None, "notes", "\"old\"", None).unwrap();
+        store.save("project", Some("p"), None, "notes", "\"older\"", None).unwrap();
+        let id = store.upsert(Scope::Project("p"), "notes", "\"new\"", None).unwrap();
+        store.upsert(Scope::Session("s"), "notes", "\"session\"", None).unwrap();
+
+        let items = store.load_for_project("p").unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!((items[0].id.as_str(), items[0].value_json.as_str()), (id.as_str(), "\"new\""));
+        assert_eq!(store.get(Scope::Session("s"), "notes").unwrap().unwrap().value_json, "\"session\"");
+        assert_eq!(store.upsert(Scope::Project("p"), "notes", "1", None).unwrap(), id);
+
+        store.upsert(Scope::Project("p"), "gone", "1", Some(0)).unwrap();
+        assert!(store.get(Scope::Project("p"), "gone").unwrap().is_none());
+        assert_eq!(store.delete(Scope::Project("p"), "notes").unwrap(), 1);
+        assert!(store.load_for_project("p").unwrap().is_empty());
+        assert_eq!(store.load_for_session("s").unwrap().len(), 1);
+        assert!(Scope::new("session", Some("p"), None).is_err());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used_over_quota() {
+        let mut store = MemoryStore::new(":memory:").unwrap();
+        store.quotas.session = Quota { max_rows: 3, max_bytes: 64 };
+        let scope = Scope::Session("s");
+        for key in ["a", "b", "c"] {
+            store.upsert(scope, key, "1", None).unwrap();
+        }
+        // Make "a" the most recently used, so "b" goes first.
+        store.conn.execute("UPDATE memory SET accessed_at = created_at - 10", []).unwrap();
+        store.get(scope, "a").unwrap();
+        store.upsert(scope, "d", "1", None).unwrap();
+        assert_eq!(store.list_keys(scope, &MemoryFilter::default()).unwrap(), vec!["a", "c", "d"]);
+
+        // Over the byte quota on its own: everything else goes, it stays.
+        let big = format!("\"{}\"", "x".repeat(100));
+        store.upsert(scope, "big", &big, None).unwrap();
+        assert_eq!(store.list_keys(scope, &MemoryFilter::default()).unwrap(), vec!["big"]);
+        store.upsert(Scope::Project("p"), "kept", "1", None).unwrap();
+
+        store.upsert(Scope::Project("p"), "old", "1", None).unwrap();
+        store.conn.execute("UPDATE memory SET expires_at = 0 WHERE key = 'old'", []).unwrap();
+        let stats = store.stats().unwrap();
+        assert_eq!((stats.rows, stats.expired_rows), (2, 1));
+        assert_eq!(stats.projects[0].id, "p");
+        assert_eq!(stats.sessions[0].bytes, 3 + 102);
+        assert_eq!(store.maintain(true).unwrap(), 1);
+        assert_eq!(store.stats().unwrap().expired_rows, 0);
+    }
+
+    #[test]
+    fn test_filters_by_prefix_and_json_path() {
+        let mut store = MemoryStore::new(":memory:").unwrap();
+        let scope = Scope::Project("p");
+        store.upsert(scope, "conv.style", r#"{"tabs":false,"tags":["fmt"]}"#, None).unwrap();
+        store.upsert(scope, "conv.tests", r#"{"tabs":true}"#, None).unwrap();
+        store.upsert(scope, "stack", r#""rust""#, None).unwrap();
+        store.upsert(Scope::Project("other"), "conv.x", "{}", None).unwrap();
+
+        let prefix = MemoryFilter { key_prefix: Some("conv.".into()), ..Default::default() };
+        assert_eq!(store.list_keys(scope, &prefix).unwrap(), vec!["conv.style", "conv.tests"]);
+        let tagged = MemoryFilter { json_path: Some("$.tags[0]".into()), ..Default::default() };
+        assert_eq!(store.list_keys(scope, &tagged).unwrap(), vec!["conv.style"]);
+        let tabs = MemoryFilter { json_path: Some("$.tabs".into()), equals: Some("true".into()), ..prefix };
+        assert_eq!(store.query(scope, &tabs).unwrap()[0].key, "conv.tests");
+        let bad = MemoryFilter { json_path: Some("tabs".into()), ..Default::default() };
+        assert!(store.query(scope, &bad).is_err());
+        assert_eq!(store.list_projects().unwrap(), vec!["other", "p"]);
+    }
+
+    #[test]
+    fn test_searches_text_and_embeddings() {
+        let mut store = MemoryStore::new(":memory:").unwrap();
+        let scope = Scope::Project("p");
+        store.upsert(scope, "conv.indent", r#""use spaces, never tabs""#, None).unwrap();
+        store.upsert(scope, "notes", r#""the build uses spaces in paths""#, None).unwrap();
+        store.upsert(scope, "stack", r#""rust and react""#, None).unwrap();
+        store.upsert(Scope::Project("other"), "indent", r#""tabs""#, None).unwrap();
+
+        let keys = |hits: Vec<MemorySearchHit>| hits.into_iter().map(|hit| hit.item.key).collect::<Vec<_>>();
+        assert_eq!(keys(store.search(scope, "indent spaces", 10).unwrap()), vec!["conv.indent", "notes"]);
+        assert_eq!(keys(store.search(scope, "Rea", 10).unwrap()), vec!["stack"]);
+        assert!(store.search(scope, "?!", 10).unwrap().is_empty());
+        // The index follows updates and deletes.
+        store.upsert(scope, "stack", r#""go""#, None).unwrap();
+        assert!(store.search(scope, "react", 10).unwrap().is_empty());
+        store.delete(scope, "notes").unwrap();
+        assert_eq!(keys(store.search(scope, "spaces", 10).unwrap()), vec!["conv.indent"]);
 
-        Ok(rows.filter_map(Result::ok).collect())
+        assert!(store.semantic_search(scope, "tabs", 1).is_err());
+        // Items written before there was a provider are embedded on demand.
+        store.set_embedder(Some(Arc::new(memory_embedding::HashEmbedding::default())));
+        store.upsert(scope, "testing", r#""cargo test before pushing""#, None).unwrap();
+        let hits = store.semantic_search(scope, "which test command?", 2).unwrap();
+        assert_eq!(hits[0].item.key, "testing");
+        assert_eq!(keys(store.semantic_search(scope, "tabs or spaces", 1).unwrap()), vec!["conv.indent"]);
+        let missing: i64 = store.conn.query_row("SELECT COUNT(*) FROM memory WHERE project_id = 'p' AND embedding IS NULL", [], |r| r.get(0)).unwrap();
+        assert_eq!(missing, 0);
     }
 }
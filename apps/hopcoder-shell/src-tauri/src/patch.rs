@@ -0,0 +1,556 @@
+use crate::atomic_write::{self, Precondition, WriteError};
+use crate::ipc::{EditPosition, FileEditResult, FileEdits, HopResponse, HunkResult, TextEdit};
+use crate::sandbox::{self, SandboxError};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tauri::async_runtime;
+
+/// One `@@ -a,b +c,d @@` block of a unified diff.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Hunk {
+    /// Index of the first old line; for pure insertions, the index to insert at.
+    old_start: usize,
+    old_lines: Vec<String>,
+    new_lines: Vec<String>,
+    /// `\ No newline at end of file` followed the last old/new line.
+    old_no_eol: bool,
+    new_no_eol: bool,
+}
+
+#[derive(Debug, Default)]
+struct FilePatch {
+    /// `None` for `/dev/null`, i.e. the file is being created.
+    old_path: Option<String>,
+    /// `None` for `/dev/null`, i.e. the file is being deleted.
+    new_path: Option<String>,
+    hunks: Vec<Hunk>,
+}
+
+fn header_path(raw: &str) -> Option<String> {
+    // Drop a trailing timestamp as written by `diff -u`.
+    let raw = raw.split('\t').next().unwrap_or(raw).trim();
+    if raw == "/dev/null" {
+        return None;
+    }
+    let path = raw.strip_prefix("a/").or_else(|| raw.strip_prefix("b/")).unwrap_or(raw);
+    Some(path.to_string())
+}
+
+/// Parses `start[,count]` from a hunk header.
+fn hunk_range(spec: &str) -> Result<(usize, usize), String> {
+    let (start, count) = spec.split_once(',').unwrap_or((spec, "1"));
+    let start = start.parse().map_err(|_| format!("bad hunk range: {spec}"))?;
+    let count = count.parse().map_err(|_| format!("bad hunk range: {spec}"))?;
+    Ok((start, count))
+}
+
+fn parse_hunk_header(line: &str) -> Result<(usize, usize, usize), String> {
+    let mut parts = line.split_whitespace().skip(1);
+    let old = parts.next().and_then(|p| p.strip_prefix('-')).ok_or_else(|| format!("bad hunk header: {line}"))?;
+    let new = parts.next().and_then(|p| p.strip_prefix('+')).ok_or_else(|| format!("bad hunk header: {line}"))?;
+    let (old_start, old_count) = hunk_range(old)?;
+    let (_, new_count) = hunk_range(new)?;
+    // `-0,0` inserts at the top; `-5,0` inserts after line 5.
+    let index = if old_count == 0 { old_start } else { old_start.saturating_sub(1) };
+    Ok((index, old_count, new_count))
+}
+
+/// Parses a unified diff. `git diff` extras (`diff --git`, `index`, mode
+/// lines) are skipped. Hunks that appear before any `---`/`+++` header are
+/// collected into a single path-less patch, which is how per-file diffs
+/// arrive.
+fn parse_patch(text: &str) -> Result<Vec<FilePatch>, String> {
+    let mut patches: Vec<FilePatch> = Vec::new();
+    let mut lines = text.lines().peekable();
+    while let Some(line) = lines.next() {
+        if let Some(old) = line.strip_prefix("--- ") {
+            let new = lines
+                .next()
+                .and_then(|l| l.strip_prefix("+++ "))
+                .ok_or_else(|| format!("expected +++ after {line}"))?;
+            patches.push(FilePatch { old_path: header_path(old), new_path: header_path(new), hunks: Vec::new() });
+        } else if line.starts_with("@@") {
+            let (old_start, mut old_left, mut new_left) = parse_hunk_header(line)?;
+            let mut hunk = Hunk { old_start, ..Hunk::default() };
+            let mut last = ' ';
+            while old_left > 0 || new_left > 0 || lines.peek().map(|l| l.starts_with('\\')).unwrap_or(false) {
+                let body = lines.next().ok_or("hunk is shorter than its header says")?;
+                // Some tools strip the single space off empty context lines.
+                let mut chars = body.chars();
+                let marker = chars.next().unwrap_or(' ');
+                let rest = chars.as_str();
+                match marker {
+                    ' ' if old_left > 0 && new_left > 0 => {
+                        hunk.old_lines.push(rest.to_string());
+                        hunk.new_lines.push(rest.to_string());
+                        old_left -= 1;
+                        new_left -= 1;
+                    }
+                    '-' if old_left > 0 => {
+                        hunk.old_lines.push(rest.to_string());
+                        old_left -= 1;
+                    }
+                    '+' if new_left > 0 => {
+                        hunk.new_lines.push(rest.to_string());
+                        new_left -= 1;
+                    }
+                    '\\' => match last {
+                        '-' => hunk.old_no_eol = true,
+                        '+' => hunk.new_no_eol = true,
+                        _ => {
+                            hunk.old_no_eol = true;
+                            hunk.new_no_eol = true;
+                        }
+                    },
+                    _ => return Err(format!("unexpected line in hunk: {body}")),
+                }
+                last = marker;
+            }
+            if patches.is_empty() {
+                patches.push(FilePatch::default());
+            }
+            if let Some(patch) = patches.last_mut() {
+                patch.hunks.push(hunk);
+            }
+        }
+    }
+    Ok(patches)
+}
+
+/// A line of a file with its original terminator, so lines a patch does not
+/// touch keep their line endings even in mixed files.
+struct Line {
+    text: String,
+    eol: &'static str,
+}
+
+fn split_lines(content: &str) -> (Vec<Line>, &'static str) {
+    let mut crlf = 0;
+    let mut lf = 0;
+    let lines = content
+        .split_inclusive('\n')
+        .map(|raw| {
+            if let Some(text) = raw.strip_suffix("\r\n") {
+                crlf += 1;
+                Line { text: text.to_string(), eol: "\r\n" }
+            } else if let Some(text) = raw.strip_suffix('\n') {
+                lf += 1;
+                Line { text: text.to_string(), eol: "\n" }
+            } else {
+                Line { text: raw.to_string(), eol: "" }
+            }
+        })
+        .collect();
+    (lines, if crlf > lf { "\r\n" } else { "\n" })
+}
+
+fn join_lines(mut lines: Vec<Line>, eol: &'static str) -> String {
+    let last = lines.len().saturating_sub(1);
+    for line in lines.iter_mut().take(last) {
+        if line.eol.is_empty() {
+            line.eol = eol;
+        }
+    }
+    lines.iter().flat_map(|l| [l.text.as_str(), l.eol]).collect()
+}
+
+fn matches_at(lines: &[Line], at: usize, old: &[String]) -> bool {
+    at + old.len() <= lines.len() && lines[at..at + old.len()].iter().zip(old).all(|(l, o)| l.text == *o)
+}
+
+/// Finds where `hunk` applies, trying its stated position first and then
+/// moving outwards, but never before `min` so hunks can't overlap.
+fn locate(lines: &[Line], hunk: &Hunk, expected: usize, min: usize) -> Option<usize> {
+    if hunk.old_lines.is_empty() {
+        return Some(expected.clamp(min, lines.len()));
+    }
+    let expected = expected.max(min);
+    let max = lines.len().checked_sub(hunk.old_lines.len())?;
+    (0..=lines.len())
+        .flat_map(|d| [expected.checked_add(d), expected.checked_sub(d).filter(|_| d > 0)])
+        .flatten()
+        .filter(|at| (min..=max).contains(at))
+        .find(|at| matches_at(lines, *at, &hunk.old_lines))
+}
+
+/// Applies unified-diff hunks in order. Hunks that no longer match are
+/// reported and skipped; the content is only usable if every hunk applied.
+fn apply_hunks(content: &str, hunks: &[Hunk]) -> (String, Vec<HunkResult>) {
+    let (mut lines, eol) = split_lines(content);
+    let mut shift: isize = 0;
+    let mut min = 0;
+    let mut results = Vec::new();
+
+    for (index, hunk) in hunks.iter().enumerate() {
+        let expected = (hunk.old_start as isize + shift).max(0) as usize;
+        let at = match locate(&lines, hunk, expected, min) {
+            Some(at) => at,
+            None => {
+                results.push(HunkResult { index, ok: false, line: None, error: Some("context does not match the file".into()) });
+                continue;
+            }
+        };
+        let new: Vec<Line> = hunk.new_lines.iter().map(|text| Line { text: text.clone(), eol }).collect();
+        let new_len = new.len();
+        lines.splice(at..at + hunk.old_lines.len(), new);
+
+        let end = at + new_len;
+        if end == lines.len() && end > 0 {
+            if hunk.new_no_eol {
+                lines[end - 1].eol = "";
+            } else if hunk.old_no_eol {
+                lines[end - 1].eol = eol;
+            }
+        }
+        shift = at as isize - hunk.old_start as isize + new_len as isize - hunk.old_lines.len() as isize;
+        min = end;
+        results.push(HunkResult { index, ok: true, line: Some(at), error: None });
+    }
+    (join_lines(lines, eol), results)
+}
+
+/// Byte offset of a line/character position; characters are Unicode scalar
+/// values, like grep's columns.
+fn byte_offset(content: &str, pos: &EditPosition) -> Option<usize> {
+    let mut start = 0;
+    for _ in 0..pos.line {
+        start += content[start..].find('\n')? + 1;
+    }
+    let line = &content[start..];
+    let line = line.split('\n').next().unwrap_or(line);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    if pos.character == line.chars().count() {
+        return Some(start + line.len());
+    }
+    line.char_indices().nth(pos.character).map(|(i, _)| start + i)
+}
+
+/// Applies range edits, all of which refer to the original content. Each
+/// edit is reported as one hunk; overlapping or out-of-range edits fail.
+fn apply_text_edits(content: &str, edits: &[TextEdit]) -> (String, Vec<HunkResult>) {
+    let mut resolved = Vec::new();
+    let mut results: Vec<HunkResult> = (0..edits.len()).map(|index| HunkResult { index, ok: true, line: None, error: None }).collect();
+    for (index, edit) in edits.iter().enumerate() {
+        match (byte_offset(content, &edit.range.start), byte_offset(content, &edit.range.end)) {
+            (Some(start), Some(end)) if start <= end => {
+                results[index].line = Some(edit.range.start.line);
+                resolved.push((start, end, index));
+            }
+            _ => {
+                results[index].ok = false;
+                results[index].error = Some("range is outside the file".into());
+            }
+        }
+    }
+
+    // Edits are checked in request order, so the later of two overlapping
+    // edits is the one reported.
+    let mut kept: Vec<(usize, usize, usize)> = Vec::new();
+    for (start, end, index) in resolved {
+        if kept.iter().any(|&(s, e, _)| start < e && s < end) {
+            results[index].ok = false;
+            results[index].error = Some("overlaps an earlier edit".into());
+        } else {
+            kept.push((start, end, index));
+        }
+    }
+    kept.sort();
+
+    let mut output = content.to_string();
+    for (start, end, index) in kept.into_iter().rev() {
+        output.replace_range(start..end, &edits[index].new_text);
+    }
+    (output, results)
+}
+
+enum Change {
+    Edits(Vec<TextEdit>),
+    Hunks { hunks: Vec<Hunk>, create: bool },
+}
+
+struct Job {
+    /// Path as the caller gave it, echoed back in the result.
+    requested: String,
+    path: PathBuf,
+    expected_hash: Option<String>,
+    change: Change,
+}
+
+/// Result of planning one file, before anything touches the disk.
+struct Planned {
+    result: FileEditResult,
+    original: Option<Vec<u8>>,
+    updated: Option<String>,
+}
+
+fn failed(job: &Job, code: &str, error: String) -> Planned {
+    Planned {
+        result: FileEditResult { path: job.requested.clone(), ok: false, hash: None, hunks: Vec::new(), code: Some(code.into()), error: Some(error) },
+        original: None,
+        updated: None,
+    }
+}
+
+fn plan(job: &Job) -> Planned {
+    let original = match std::fs::read(&job.path) {
+        Ok(bytes) => Some(bytes),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return failed(job, "io", e.to_string()),
+    };
+    match (&original, &job.change) {
+        (Some(_), Change::Hunks { create: true, .. }) => return failed(job, "conflict", "file already exists".into()),
+        (None, Change::Hunks { create: false, .. } | Change::Edits(_)) => return failed(job, "not_found", "file does not exist".into()),
+        _ => {}
+    }
+    if let Some(expected) = &job.expected_hash {
+        let actual = original.as_deref().map(atomic_write::content_hash);
+        if actual.as_deref().map(|a| !a.eq_ignore_ascii_case(expected)).unwrap_or(true) {
+            return failed(job, "conflict", "file changed since the edits were made".into());
+        }
+    }
+    let text = match original.as_deref().map(std::str::from_utf8).transpose() {
+        Ok(t) => t.unwrap_or(""),
+        Err(_) => return failed(job, "io", "not a UTF-8 text file".into()),
+    };
+
+    let (updated, hunks) = match &job.change {
+        Change::Edits(edits) => apply_text_edits(text, edits),
+        Change::Hunks { hunks, .. } => apply_hunks(text, hunks),
+    };
+    let ok = hunks.iter().all(|h| h.ok);
+    let result = FileEditResult {
+        path: job.requested.clone(),
+        ok,
+        hash: ok.then(|| atomic_write::content_hash(updated.as_bytes())),
+        hunks,
+        code: (!ok).then(|| "conflict".into()),
+        error: (!ok).then(|| "some hunks did not apply".into()),
+    };
+    Planned { result, original, updated: ok.then_some(updated) }
+}
+
+/// Ancestors of `path` that don't exist yet, outermost first; writing
+/// `path` creates them.
+fn missing_parents(path: &Path) -> Vec<PathBuf> {
+    let mut missing: Vec<PathBuf> = path
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| !dir.as_os_str().is_empty() && dir.symlink_metadata().is_err())
+        .map(Path::to_path_buf)
+        .collect();
+    missing.reverse();
+    missing
+}
+
+/// Puts already-written files back the way they were after a later write
+/// failed, then removes the directories the writes created, deepest first.
+/// Returns whatever could not be undone.
+fn roll_back(jobs: &[Job], planned: &[Planned], created_dirs: &[PathBuf]) -> Vec<String> {
+    let mut failures = Vec::new();
+    for (job, p) in jobs.iter().zip(planned) {
+        let undone = match &p.original {
+            Some(bytes) => atomic_write::write(&job.path, bytes, false, &Precondition::default()).map(|_| ()),
+            None => std::fs::remove_file(&job.path).map_err(WriteError::from),
+        };
+        if let Err(e) = undone {
+            failures.push(format!("{}: {e}", job.requested));
+        }
+    }
+    for dir in created_dirs.iter().rev() {
+        match std::fs::remove_dir(dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => failures.push(format!("{}: {e}", dir.display())),
+            _ => {}
+        }
+    }
+    failures
+}
+
+/// Plans every file, and only if all of them apply cleanly writes them one
+/// by one, guarding each write with the hash of the content it was planned
+/// against. A failed write rolls back the files written before it and the
+/// directories created for them.
+fn apply_jobs(jobs: Vec<Job>, dry_run: bool) -> (bool, Vec<FileEditResult>, Option<String>) {
+    let mut planned: Vec<Planned> = jobs.iter().map(plan).collect();
+    if !planned.iter().all(|p| p.result.ok) {
+        let results = planned.into_iter().map(|p| p.result).collect();
+        return (false, results, Some("Edits were not applied; see per-file results".into()));
+    }
+    if dry_run {
+        return (true, planned.into_iter().map(|p| p.result).collect(), None);
+    }
+
+    let mut created_dirs = Vec::new();
+    for i in 0..jobs.len() {
+        let p = &planned[i];
+        let precondition = Precondition { expected_hash: p.original.as_deref().map(atomic_write::content_hash), expected_mtime: None };
+        let updated = p.updated.as_deref().unwrap_or("");
+        created_dirs.extend(missing_parents(&jobs[i].path));
+        if let Err(e) = atomic_write::write(&jobs[i].path, updated.as_bytes(), true, &precondition) {
+            let failures = roll_back(&jobs[..i], &planned[..i], &created_dirs);
+            let mut error = format!("{}: {e}", jobs[i].requested);
+            if !failures.is_empty() {
+                error = format!("{error}; rolling back failed for {}", failures.join(", "));
+            }
+            let p = &mut planned[i];
+            p.result.ok = false;
+            p.result.hash = None;
+            p.result.code = Some(e.code().into());
+            p.result.error = Some(e.to_string());
+            return (false, planned.into_iter().map(|p| p.result).collect(), Some(error));
+        }
+    }
+    (true, planned.into_iter().map(|p| p.result).collect(), None)
+}
+
+/// Turns the request into one change per file, validating its shape before
+/// any path is resolved.
+fn collect_changes(files: Vec<FileEdits>, patch: Option<String>) -> Result<Vec<(String, Option<String>, Change)>, String> {
+    let mut changes = Vec::new();
+    for file in files {
+        let change = match (file.edits.is_empty(), file.diff) {
+            (false, None) => Change::Edits(file.edits),
+            (true, Some(diff)) => {
+                let mut patches = parse_patch(&diff).map_err(|e| format!("{}: {e}", file.path))?;
+                if patches.len() != 1 {
+                    return Err(format!("{}: diff must describe exactly one file", file.path));
+                }
+                let patch = patches.remove(0);
+                Change::Hunks { create: patch.old_path.is_none() && patch.new_path.is_some(), hunks: patch.hunks }
+            }
+            _ => return Err(format!("{}: give either edits or a diff", file.path)),
+        };
+        changes.push((file.path, file.expected_hash, change));
+    }
+    if let Some(patch) = patch {
+        for file in parse_patch(&patch)? {
+            let path = match (&file.old_path, file.new_path) {
+                (_, Some(path)) => path,
+                (Some(path), None) => return Err(format!("{path}: deleting files is not supported here; use fs.delete")),
+                (None, None) => return Err("patch hunks must follow ---/+++ headers".into()),
+            };
+            if file.old_path.as_deref().map(|old| old != path).unwrap_or(false) {
+                return Err(format!("{path}: renames are not supported here"));
+            }
+            changes.push((path, None, Change::Hunks { create: file.old_path.is_none(), hunks: file.hunks }));
+        }
+    }
+    if changes.is_empty() {
+        return Err("No edits given".into());
+    }
+    Ok(changes)
+}
+
+/// Applies text edits and/or unified diffs to several files as one unit:
+/// either every hunk of every file applies and all files are written, or
+/// nothing is. Results are reported per file and per hunk; with `dry_run`
+/// nothing is written at all.
+pub async fn apply_edits(root: Option<&str>, files: Vec<FileEdits>, patch: Option<String>, dry_run: bool) -> HopResponse {
+    let changes = match collect_changes(files, patch) {
+        Ok(c) => c,
+        Err(e) => return HopResponse::FsApplyEdits { ok: false, files: None, error: Some(e) },
+    };
+    let mut seen = HashSet::new();
+    let mut jobs = Vec::new();
+    for (requested, expected_hash, change) in changes {
+        let path = match sandbox::resolve(&requested, root) {
+            Ok(p) => p,
+            Err(e) => return e.into(),
+        };
+        if !seen.insert(path.clone()) {
+            return SandboxError::InvalidPath(format!("{requested} is edited more than once")).into();
+        }
+        jobs.push(Job { requested, path, expected_hash, change });
+    }
+
+    match async_runtime::spawn_blocking(move || apply_jobs(jobs, dry_run)).await {
+        Ok((ok, files, error)) => HopResponse::FsApplyEdits { ok, files: Some(files), error },
+        Err(e) => HopResponse::FsApplyEdits { ok: false, files: None, error: Some(e.to_string()) },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc::EditRange;
+
+    const DIFF: &str = "\
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,3 +1,3 @@
+ fn a() {}
+-fn b() {}
++fn b() { todo!() }
+ fn c() {}
+@@ -6,2 +6,3 @@
+ fn f() {}
+ fn g() {}
++fn h() {}
+";
+
+    #[test]
+    fn test_parses_unified_diff() {
+        let patches = parse_patch(DIFF).unwrap();
+        assert_eq!(patches.len(), 1);
+        assert_eq!(patches[0].old_path.as_deref(), Some("src/lib.rs"));
+        assert_eq!(patches[0].hunks.len(), 2);
+        assert_eq!(patches[0].hunks[1].old_start, 5);
+        assert_eq!(patches[0].hunks[1].new_lines.len(), 3);
+    }
+
+    #[test]
+    fn test_applies_hunks_with_drift_and_crlf() {
+        // Two extra lines at the top push every hunk down.
+        let content = "// a\r\n// b\r\nfn a() {}\r\nfn b() {}\r\nfn c() {}\r\nfn d() {}\r\nfn e() {}\r\nfn f() {}\r\nfn g() {}\r\n";
+        let (updated, results) = apply_hunks(content, &parse_patch(DIFF).unwrap()[0].hunks);
+        assert!(results.iter().all(|r| r.ok));
+        assert_eq!(results[0].line, Some(2));
+        assert!(updated.contains("fn b() { todo!() }\r\n"));
+        assert!(updated.ends_with("fn g() {}\r\nfn h() {}\r\n"));
+    }
+
+    #[test]
+    fn test_reports_stale_hunk() {
+        let (_, results) = apply_hunks("fn a() {}\nfn x() {}\nfn c() {}\n", &parse_patch(DIFF).unwrap()[0].hunks[..1]);
+        assert!(!results[0].ok);
+    }
+
+    #[test]
+    fn test_no_newline_at_end_of_file() {
+        let diff = "@@ -1 +1 @@\n-old\n\\ No newline at end of file\n+new\n";
+        let (updated, _) = apply_hunks("old", &parse_patch(diff).unwrap()[0].hunks);
+        assert_eq!(updated, "new\n");
+    }
+
+    #[test]
+    fn test_applies_text_edits() {
+        let edit = |sl, sc, el, ec, text: &str| TextEdit {
+            range: EditRange { start: EditPosition { line: sl, character: sc }, end: EditPosition { line: el, character: ec } },
+            new_text: text.into(),
+        };
+        let content = "héllo\nworld\n";
+        let (updated, results) = apply_text_edits(content, &[edit(1, 0, 1, 5, "there"), edit(0, 1, 0, 2, "e"), edit(0, 0, 0, 3, "x")]);
+        assert_eq!(updated, "hello\nthere\n");
+        assert!(results[0].ok && results[1].ok && !results[2].ok);
+        assert!(!apply_text_edits(content, &[edit(5, 0, 5, 0, "x")]).1[0].ok);
+    }
+
+    #[test]
+    fn test_rolls_back_files_and_created_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("lib.rs");
+        std::fs::write(&existing, "old\n").unwrap();
+        let job = |path: PathBuf, change: Change| Job { requested: path.to_string_lossy().to_string(), path, expected_hash: None, change };
+        let create = || Change::Hunks { hunks: parse_patch("@@ -0,0 +1 @@\n+new\n").unwrap().remove(0).hunks, create: true };
+        let replace = || Change::Hunks { hunks: parse_patch("@@ -1 +1 @@\n-old\n+new\n").unwrap().remove(0).hunks, create: false };
+
+        // Both edits to lib.rs are planned against the same content, so the
+        // second write fails its hash check after the first went through.
+        let jobs = vec![job(dir.path().join("a/b/new.rs"), create()), job(existing.clone(), replace()), job(existing.clone(), replace())];
+        let (ok, results, error) = apply_jobs(jobs, false);
+        assert!(!ok);
+        assert_eq!(results[2].code.as_deref(), Some("conflict"));
+        assert!(!error.unwrap().contains("rolling back failed"));
+        assert_eq!(std::fs::read_to_string(&existing).unwrap(), "old\n");
+        assert!(!dir.path().join("a").exists());
+    }
+}
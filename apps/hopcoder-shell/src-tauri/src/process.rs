@@ -0,0 +1,183 @@
+#[cfg(unix)]
+use std::collections::HashSet;
+use tokio::process::Command;
+
+/// Signals the frontend may ask us to deliver to a terminal or server process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Int,
+    Term,
+    Hup,
+    Kill,
+}
+
+impl Signal {
+    /// Accepts `SIGTERM`, `TERM` and `term` style names.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let upper = name.trim().to_ascii_uppercase();
+        match upper.strip_prefix("SIG").unwrap_or(&upper) {
+            "INT" => Ok(Signal::Int),
+            "TERM" => Ok(Signal::Term),
+            "HUP" => Ok(Signal::Hup),
+            "KILL" => Ok(Signal::Kill),
+            _ => Err(format!("Unsupported signal: {name}")),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Signal::Int => "SIGINT",
+            Signal::Term => "SIGTERM",
+            Signal::Hup => "SIGHUP",
+            Signal::Kill => "SIGKILL",
+        }
+    }
+
+    #[cfg(unix)]
+    fn as_raw(self) -> libc::c_int {
+        match self {
+            Signal::Int => libc::SIGINT,
+            Signal::Term => libc::SIGTERM,
+            Signal::Hup => libc::SIGHUP,
+            Signal::Kill => libc::SIGKILL,
+        }
+    }
+}
+
+/// Name of a raw signal number, in the same `SIGTERM` style as
+/// [`Signal::name`].
+#[cfg(unix)]
+pub fn signal_name(raw: libc::c_int) -> String {
+    if let Some(signal) = [Signal::Int, Signal::Term, Signal::Hup, Signal::Kill].into_iter().find(|s| s.as_raw() == raw) {
+        return signal.name().to_string();
+    }
+    let name = match raw {
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGILL => "SIGILL",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGALRM => "SIGALRM",
+        libc::SIGUSR1 => "SIGUSR1",
+        libc::SIGUSR2 => "SIGUSR2",
+        _ => return format!("SIG{raw}"),
+    };
+    name.to_string()
+}
+
+/// Blocks until `pid`, a child of this process, exits and reaps it.
+#[cfg(unix)]
+pub fn wait(pid: u32) -> Result<std::process::ExitStatus, String> {
+    use std::os::unix::process::ExitStatusExt;
+    let mut status = 0;
+    loop {
+        if unsafe { libc::waitpid(pid as libc::pid_t, &mut status, 0) } == pid as libc::pid_t {
+            return Ok(std::process::ExitStatus::from_raw(status));
+        }
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EINTR) {
+            return Err(err.to_string());
+        }
+    }
+}
+
+/// Sends `signal` to every process in the group led by `pgid`.
+#[cfg(unix)]
+pub fn signal_group(pgid: u32, signal: Signal) -> Result<(), String> {
+    if unsafe { libc::killpg(pgid as libc::pid_t, signal.as_raw()) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error().to_string())
+    }
+}
+
+/// Returns the direct children of `pid`, using the same `pgrep -P` approach
+/// as the CLI's `util::command::kill_tree`.
+#[cfg(unix)]
+async fn children_of(pid: u32) -> Vec<u32> {
+    let output = match Command::new("pgrep").arg("-P").arg(pid.to_string()).output().await {
+        Ok(o) => o,
+        Err(_) => return Vec::new(),
+    };
+    String::from_utf8_lossy(&output.stdout).lines().filter_map(|l| l.trim().parse().ok()).collect()
+}
+
+/// Delivers `signal` to `pid` and all of its descendants. Processes that
+/// moved into their own process group (shell jobs, dev servers) are reached
+/// through the parent/child tree, and each distinct group is signalled too so
+/// children forked between the walk and the kill are not missed.
+#[cfg(unix)]
+pub async fn kill_tree(pid: u32, signal: Signal) -> Result<(), String> {
+    let mut tree = vec![pid];
+    let mut seen: HashSet<u32> = HashSet::from([pid]);
+    let mut i = 0;
+    while i < tree.len() {
+        for child in children_of(tree[i]).await {
+            if seen.insert(child) {
+                tree.push(child);
+            }
+        }
+        i += 1;
+    }
+
+    let mut groups = HashSet::new();
+    for p in &tree {
+        let pgid = unsafe { libc::getpgid(*p as libc::pid_t) };
+        if pgid > 0 {
+            groups.insert(pgid);
+        }
+    }
+    let own_group = unsafe { libc::getpgrp() };
+    for pgid in groups {
+        if pgid != own_group {
+            unsafe { libc::killpg(pgid, signal.as_raw()) };
+        }
+    }
+
+    // Walk leaves first so a parent can't reap and replace a child mid-kill.
+    let mut root_result = Ok(());
+    for p in tree.iter().rev() {
+        if unsafe { libc::kill(*p as libc::pid_t, signal.as_raw()) } != 0 && *p == pid {
+            let err = std::io::Error::last_os_error();
+            // The root may already be gone because its group was signalled above.
+            if err.raw_os_error() != Some(libc::ESRCH) {
+                root_result = Err(err.to_string());
+            }
+        }
+    }
+    root_result
+}
+
+#[cfg(windows)]
+pub async fn kill_tree(pid: u32, signal: Signal) -> Result<(), String> {
+    let mut cmd = Command::new("taskkill");
+    cmd.arg("/t").arg("/pid").arg(pid.to_string());
+    if signal == Signal::Kill {
+        cmd.arg("/f");
+    }
+    let output = cmd.output().await.map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+
+    #[test]
+    fn test_wait_reports_exit_code_or_signal() {
+        // Reaped by `wait` rather than through the `Child`.
+        let spawn = |script: &str| std::process::Command::new("sh").args(["-c", script]).spawn().unwrap().id();
+        assert_eq!(wait(spawn("exit 3")).unwrap().code(), Some(3));
+
+        let status = wait(spawn("kill -HUP $$")).unwrap();
+        assert_eq!(status.signal().map(signal_name).as_deref(), Some("SIGHUP"));
+        assert_eq!(signal_name(libc::SIGSEGV), "SIGSEGV");
+    }
+}
@@ -0,0 +1,238 @@
+use crate::ipc::HopResponse;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+/// Symlink hops allowed while resolving a single path, matching Linux's
+/// `MAXSYMLINKS`.
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+#[derive(Debug)]
+pub enum SandboxError {
+    /// The workspace root itself does not exist or is not a directory.
+    InvalidRoot(String),
+    /// The path is malformed, relative without a root, or loops through symlinks.
+    InvalidPath(String),
+    /// The resolved path (after following symlinks) lies outside the root.
+    OutsideRoot(String),
+}
+
+impl SandboxError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            SandboxError::InvalidRoot(_) => "invalid_root",
+            SandboxError::InvalidPath(_) => "invalid_path",
+            SandboxError::OutsideRoot(_) => "outside_root",
+        }
+    }
+}
+
+impl fmt::Display for SandboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SandboxError::InvalidRoot(root) => write!(f, "Invalid workspace root: {root}"),
+            SandboxError::InvalidPath(reason) => write!(f, "Invalid path: {reason}"),
+            SandboxError::OutsideRoot(path) => write!(f, "Access denied: {path} is outside workspace root"),
+        }
+    }
+}
+
+impl From<SandboxError> for HopResponse {
+    fn from(e: SandboxError) -> Self {
+        HopResponse::Error { ok: false, code: Some(e.code().into()), error: e.to_string() }
+    }
+}
+
+/// Confines paths to a canonicalized workspace root. Relative paths are taken
+/// relative to the root, `..` and symlinks are resolved the way the kernel
+/// would, and the result is compared component-wise so `/work2` is not
+/// considered to be inside `/work`.
+pub struct Sandbox {
+    root: PathBuf,
+}
+
+impl Sandbox {
+    pub fn new(root: &str) -> Result<Self, SandboxError> {
+        let root = std::fs::canonicalize(root).map_err(|_| SandboxError::InvalidRoot(root.to_string()))?;
+        if !root.is_dir() {
+            return Err(SandboxError::InvalidRoot(root.to_string_lossy().to_string()));
+        }
+        Ok(Self { root })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Resolves `path` to an absolute, symlink-free path inside the root. The
+    /// final component does not need to exist, so this also works for paths
+    /// that are about to be created.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, SandboxError> {
+        if path.is_empty() {
+            return Err(SandboxError::InvalidPath("empty path".into()));
+        }
+        let resolved = normalize_existing_prefix(resolve_symlinks(self.root.clone(), Path::new(path), 0)?);
+        self.check(resolved, path)
+    }
+
+    /// Resolves the directory containing `path` but keeps its last component
+    /// as given, so operations like delete or rename act on a symlink itself
+    /// rather than on what it points to. The root itself is rejected.
+    pub fn resolve_entry(&self, path: &str) -> Result<PathBuf, SandboxError> {
+        let (parent, name) = split_entry(path)?;
+        let parent = match parent {
+            Some(parent) => self.resolve(parent)?,
+            None => self.root.clone(),
+        };
+        self.check(parent.join(name), path)
+    }
+
+    fn check(&self, resolved: PathBuf, requested: &str) -> Result<PathBuf, SandboxError> {
+        if resolved.starts_with(&self.root) {
+            Ok(resolved)
+        } else {
+            Err(SandboxError::OutsideRoot(requested.to_string()))
+        }
+    }
+}
+
+/// Resolves `path` against `root` when a root is known. Without one the path
+/// must already be absolute and is only normalized.
+pub fn resolve(path: &str, root: Option<&str>) -> Result<PathBuf, SandboxError> {
+    match root {
+        Some(root) => Sandbox::new(root)?.resolve(path),
+        None => Ok(normalize_existing_prefix(resolve_symlinks(PathBuf::new(), absolute(path)?, 0)?)),
+    }
+}
+
+/// [`Sandbox::resolve_entry`] counterpart of [`resolve`].
+pub fn resolve_entry(path: &str, root: Option<&str>) -> Result<PathBuf, SandboxError> {
+    match root {
+        Some(root) => Sandbox::new(root)?.resolve_entry(path),
+        None => {
+            absolute(path)?;
+            let (parent, name) = split_entry(path)?;
+            let parent = resolve(parent.unwrap_or("/"), None)?;
+            Ok(parent.join(name))
+        }
+    }
+}
+
+fn absolute(path: &str) -> Result<&Path, SandboxError> {
+    let p = Path::new(path);
+    if p.is_absolute() {
+        Ok(p)
+    } else {
+        Err(SandboxError::InvalidPath(format!("{path} is relative and no workspace root is set")))
+    }
+}
+
+/// Splits `path` into its parent (if any) and a final name that must be a
+/// plain file name, not `.`, `..` or a root.
+fn split_entry(path: &str) -> Result<(Option<&str>, &std::ffi::OsStr), SandboxError> {
+    let p = Path::new(path);
+    match p.components().next_back() {
+        Some(Component::Normal(name)) => {
+            let parent = p.parent().and_then(|p| p.to_str()).filter(|p| !p.is_empty());
+            Ok((parent, name))
+        }
+        _ => Err(SandboxError::InvalidPath(format!("{path} does not name an entry inside the workspace"))),
+    }
+}
+
+/// Walks `path` one component at a time starting from `base`, expanding every
+/// symlink it meets so that a following `..` pops the link's target rather
+/// than the link itself. Dangling links are followed too, so a write through
+/// one cannot create a file outside the root.
+fn resolve_symlinks(base: PathBuf, path: &Path, depth: usize) -> Result<PathBuf, SandboxError> {
+    let mut resolved = base;
+    for comp in path.components() {
+        match comp {
+            Component::Prefix(_) => resolved = PathBuf::from(comp.as_os_str()),
+            Component::RootDir => resolved.push(comp.as_os_str()),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::Normal(name) => {
+                resolved.push(name);
+                let is_link = std::fs::symlink_metadata(&resolved).map(|m| m.file_type().is_symlink()).unwrap_or(false);
+                if is_link {
+                    if depth >= MAX_SYMLINK_DEPTH {
+                        return Err(SandboxError::InvalidPath("too many levels of symbolic links".into()));
+                    }
+                    let target = std::fs::read_link(&resolved).map_err(|e| SandboxError::InvalidPath(e.to_string()))?;
+                    resolved.pop();
+                    resolved = resolve_symlinks(resolved, &target, depth + 1)?;
+                }
+            }
+        }
+    }
+    Ok(resolved)
+}
+
+/// Canonicalizes the longest existing ancestor of `path` and re-appends the
+/// rest. Symlinks are already expanded at this point; this only brings the
+/// path into the same form as the canonical root (drive prefix, case on
+/// case-insensitive file systems).
+fn normalize_existing_prefix(path: PathBuf) -> PathBuf {
+    let mut existing = path.as_path();
+    let mut rest = Vec::new();
+    loop {
+        if let Ok(canonical) = std::fs::canonicalize(existing) {
+            return rest.iter().rev().fold(canonical, |acc: PathBuf, name| acc.join(name));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name.to_os_string());
+                existing = parent;
+            }
+            _ => return path,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace() -> (tempfile::TempDir, Sandbox) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("work")).unwrap();
+        std::fs::create_dir(dir.path().join("work2")).unwrap();
+        std::fs::write(dir.path().join("work").join("a..b"), "").unwrap();
+        let sandbox = Sandbox::new(dir.path().join("work").to_str().unwrap()).unwrap();
+        (dir, sandbox)
+    }
+
+    #[test]
+    fn test_resolves_relative_and_dotted_names() {
+        let (_dir, sb) = workspace();
+        assert_eq!(sb.resolve("a..b").unwrap(), sb.root().join("a..b"));
+        assert_eq!(sb.resolve("./src/../a..b").unwrap(), sb.root().join("a..b"));
+        assert_eq!(sb.resolve("new/file.txt").unwrap(), sb.root().join("new").join("file.txt"));
+    }
+
+    #[test]
+    fn test_rejects_sibling_prefix_and_parent_escape() {
+        let (dir, sb) = workspace();
+        let sibling = dir.path().join("work2").join("x");
+        assert_eq!(sb.resolve(sibling.to_str().unwrap()).unwrap_err().code(), "outside_root");
+        assert_eq!(sb.resolve("../work2/x").unwrap_err().code(), "outside_root");
+        assert_eq!(sb.resolve_entry("..").unwrap_err().code(), "invalid_path");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follows_symlinks() {
+        let (dir, sb) = workspace();
+        std::os::unix::fs::symlink(dir.path().join("work2"), sb.root().join("escape")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("work2").join("missing"), sb.root().join("dangling")).unwrap();
+        std::os::unix::fs::symlink("a..b", sb.root().join("inside")).unwrap();
+
+        assert_eq!(sb.resolve("escape/x").unwrap_err().code(), "outside_root");
+        assert_eq!(sb.resolve("dangling").unwrap_err().code(), "outside_root");
+        assert_eq!(sb.resolve("inside").unwrap(), sb.root().join("a..b"));
+        // The link itself lives inside the root, so it can still be removed.
+        assert_eq!(sb.resolve_entry("escape").unwrap(), sb.root().join("escape"));
+    }
+}
@@ -1,99 +1,338 @@
-use crate::ipc::{HopEvent, HopNotificationMessage, HopResponse, HOP_EVENT_CHANNEL, HOP_IPC_VERSION};
+use crate::ipc::{HopEvent, HopNotificationMessage, HopResponse, TerminalInfo, HOP_EVENT_CHANNEL, HOP_IPC_VERSION};
+use crate::process::{self, Signal};
+use crate::sandbox::{self, Sandbox, SandboxError};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use dashmap::DashMap;
-use std::process::Stdio;
-use std::sync::Arc;
+use portable_pty::{native_pty_system, Child, ChildKiller, CommandBuilder, MasterPty, PtySize};
+use std::collections::{HashMap, VecDeque};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tauri::{async_runtime, Manager};
-use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-    process::{Child, Command},
-    sync::Mutex,
-};
+use tokio::sync::mpsc;
 
+const DEFAULT_COLS: u16 = 80;
+const DEFAULT_ROWS: u16 = 24;
+const READ_CHUNK_SIZE: usize = 8192;
+const OUTPUT_CHANNEL_CAPACITY: usize = 64;
+const MAX_EVENT_BYTES: usize = 64 * 1024;
+const COALESCE_WINDOW: Duration = Duration::from_millis(4);
+const SCROLLBACK_BYTES: usize = 1024 * 1024;
+const LOGIN_ENV_TIMEOUT: Duration = Duration::from_secs(5);
+const EXITED_RETENTION: Duration = Duration::from_secs(60);
+
+/// A shell running on the slave side of a pseudo-terminal. We keep the master
+/// end so the window size can be changed and the writer so input reaches the
+/// shell's line discipline exactly like a real terminal emulator would.
+pub struct TerminalSession {
+    master: Box<dyn MasterPty + Send>,
+    writer: Box<dyn Write + Send>,
+    killer: Box<dyn ChildKiller + Send + Sync>,
+    scrollback: Arc<Mutex<Scrollback>>,
+    pid: u32,
+    info: TerminalInfo,
+}
+
+/// Bounded history of everything the terminal printed, used to repaint a
+/// terminal after the frontend reloads. `total` counts every byte ever
+/// written so clients can tell replayed output from live events.
+pub struct Scrollback {
+    buffer: VecDeque<u8>,
+    capacity: usize,
+    total: u64,
+}
+
+impl Default for Scrollback {
+    fn default() -> Self {
+        Self::with_capacity(SCROLLBACK_BYTES)
+    }
+}
+
+impl Scrollback {
+    fn with_capacity(capacity: usize) -> Self {
+        Self { buffer: VecDeque::with_capacity(capacity), capacity, total: 0 }
+    }
+
+    /// Appends `data` and returns the stream offset of its first byte.
+    fn append(&mut self, data: &[u8]) -> u64 {
+        let offset = self.total;
+        let keep = &data[data.len().saturating_sub(self.capacity)..];
+        let excess = (self.buffer.len() + keep.len()).saturating_sub(self.capacity);
+        self.buffer.drain(..excess);
+        self.buffer.extend(keep);
+        self.total += data.len() as u64;
+        offset
+    }
+
+    /// Returns the retained bytes and the stream offset they start at.
+    fn snapshot(&self) -> (Vec<u8>, u64) {
+        let data: Vec<u8> = self.buffer.iter().copied().collect();
+        let start = self.total - data.len() as u64;
+        (data, start)
+    }
+}
+
+/// Terminals by client-side id. Sessions stay listed for a short while after
+/// the shell exits so a reloading frontend can still see how it ended, then
+/// the exit watcher reaps them.
 #[derive(Default)]
 pub struct TerminalManager {
-    processes: DashMap<String, Arc<Mutex<Child>>>,
+    sessions: Arc<DashMap<String, Arc<Mutex<TerminalSession>>>>,
+}
+
+impl TerminalManager {
+    fn is_running(&self, id: &str) -> bool {
+        self.sessions
+            .get(id)
+            .map(|h| h.value().lock().map(|s| s.info.state == "running").unwrap_or(false))
+            .unwrap_or(false)
+    }
+}
+
+fn pty_size(cols: u32, rows: u32) -> Result<PtySize, String> {
+    let cols = u16::try_from(cols).map_err(|_| format!("Invalid terminal width: {cols}"))?;
+    let rows = u16::try_from(rows).map_err(|_| format!("Invalid terminal height: {rows}"))?;
+    if cols == 0 || rows == 0 {
+        return Err("Terminal size must be non-zero".into());
+    }
+    Ok(PtySize { rows, cols, pixel_width: 0, pixel_height: 0 })
+}
+
+/// Coalesces raw output chunks into larger events, records them in the
+/// scrollback and emits them base64-encoded so no byte is lost or rewritten.
+async fn pump_output(
+    app: tauri::AppHandle,
+    id: String,
+    mut rx: mpsc::Receiver<Vec<u8>>,
+    scrollback: Arc<Mutex<Scrollback>>,
+) {
+    while let Some(first) = rx.recv().await {
+        let mut chunk = first;
+        while chunk.len() < MAX_EVENT_BYTES {
+            match tokio::time::timeout(COALESCE_WINDOW, rx.recv()).await {
+                Ok(Some(more)) => chunk.extend_from_slice(&more),
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        let offset = match scrollback.lock() {
+            Ok(mut sb) => sb.append(&chunk),
+            Err(_) => break,
+        };
+        emit(&app, HopEvent::TerminalData { id: id.clone(), data: BASE64.encode(&chunk), offset });
+    }
+}
+
+fn emit(app: &tauri::AppHandle, event: HopEvent) {
+    let _ = app.emit_all(HOP_EVENT_CHANNEL, HopNotificationMessage { v: HOP_IPC_VERSION, event });
+}
+
+/// Everything `terminal.spawn` can customise about the new shell.
+#[derive(Default)]
+pub struct SpawnOptions {
+    pub shell: Option<String>,
+    pub args: Option<Vec<String>>,
+    pub cwd: Option<String>,
+    pub env: Option<HashMap<String, String>>,
+    pub login_shell: bool,
+    pub cols: Option<u32>,
+    pub rows: Option<u32>,
+}
+
+/// Picks the working directory for a new terminal: the requested `cwd`
+/// (relative paths resolve against the workspace root), else the workspace
+/// root, else the app's own directory. Requested directories go through the
+/// same sandbox as fs requests so a terminal cannot be opened outside the
+/// workspace.
+fn resolve_cwd(requested: Option<String>, workspace_root: Option<String>) -> Result<Option<PathBuf>, SandboxError> {
+    let cwd = match (requested, workspace_root.as_deref()) {
+        (Some(cwd), root) => sandbox::resolve(&cwd, root)?,
+        (None, Some(root)) => Sandbox::new(root)?.root().to_path_buf(),
+        (None, None) => return Ok(std::env::current_dir().ok()),
+    };
+
+    if !cwd.is_dir() {
+        return Err(SandboxError::InvalidPath(format!("working directory {} does not exist", cwd.display())));
+    }
+    Ok(Some(cwd))
+}
+
+/// Captures the environment a login shell would set up (profile scripts,
+/// PATH additions from version managers, ...). Apps launched from a desktop
+/// launcher usually don't inherit it.
+#[cfg(unix)]
+async fn login_shell_env(shell: &str) -> Result<Vec<(String, String)>, String> {
+    let output = tokio::time::timeout(
+        LOGIN_ENV_TIMEOUT,
+        tokio::process::Command::new(shell)
+            .args(["-l", "-c", "env"])
+            .stdin(std::process::Stdio::null())
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .map_err(|_| "Timed out reading login shell environment".to_string())?
+    .map_err(|e| e.to_string())?;
+
+    let mut vars: Vec<(String, String)> = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        match line.split_once('=') {
+            Some((key, value)) if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') => {
+                vars.push((key.to_string(), value.to_string()));
+            }
+            // Continuation of a multi-line value
+            _ => {
+                if let Some((_, value)) = vars.last_mut() {
+                    value.push('\n');
+                    value.push_str(line);
+                }
+            }
+        }
+    }
+    Ok(vars)
+}
+
+#[cfg(windows)]
+async fn login_shell_env(_shell: &str) -> Result<Vec<(String, String)>, String> {
+    Ok(Vec::new())
 }
 
 pub async fn spawn(
     app: &tauri::AppHandle,
     manager: &TerminalManager,
+    workspace_root: Option<String>,
     id: String,
-    shell: Option<String>,
+    options: SpawnOptions,
 ) -> HopResponse {
-    let sh = shell.unwrap_or_else(|| if cfg!(windows) { "powershell.exe".into() } else { "/bin/bash".into() });
+    let sh = options.shell.unwrap_or_else(|| if cfg!(windows) { "powershell.exe".into() } else { "/bin/bash".into() });
 
-    let mut child = match Command::new(sh)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-    {
+    if manager.is_running(&id) {
+        return HopResponse::TerminalSpawn { ok: false, pid: None, error: Some("terminal already exists".into()) };
+    }
+
+    let size = match pty_size(options.cols.unwrap_or(DEFAULT_COLS.into()), options.rows.unwrap_or(DEFAULT_ROWS.into())) {
+        Ok(s) => s,
+        Err(e) => return HopResponse::TerminalSpawn { ok: false, pid: None, error: Some(e) },
+    };
+
+    let cwd = match resolve_cwd(options.cwd, workspace_root) {
         Ok(c) => c,
-        Err(e) => return HopResponse::TerminalSpawn { ok: false, pid: None, error: Some(e.to_string()) },
+        Err(e) => return e.into(),
     };
 
-    let pid = child.id().unwrap_or_default();
-    let stdout = child.stdout.take();
-    let stderr = child.stderr.take();
-    let handle = Arc::new(Mutex::new(child));
-    manager.processes.insert(id.clone(), handle.clone());
+    let login_env = if options.login_shell {
+        match login_shell_env(&sh).await {
+            Ok(vars) => vars,
+            Err(e) => return HopResponse::TerminalSpawn { ok: false, pid: None, error: Some(e) },
+        }
+    } else {
+        Vec::new()
+    };
 
-    // Stream stdout
-    if let Some(out) = stdout {
-        let app_clone = app.clone();
-        let id_clone = id.clone();
-        async_runtime::spawn(async move {
-            let reader = BufReader::new(out);
-            let mut lines = reader.lines();
-            while let Ok(Some(line)) = lines.next_line().await {
-                let _ = app_clone.emit_all(
-                    HOP_EVENT_CHANNEL,
-                    HopNotificationMessage {
-                        v: HOP_IPC_VERSION,
-                        event: HopEvent::TerminalData { id: id_clone.clone(), data: format!("{line}\n") },
-                    },
-                );
-            }
-        });
+    let pair = match native_pty_system().openpty(size) {
+        Ok(p) => p,
+        Err(e) => return HopResponse::TerminalSpawn { ok: false, pid: None, error: Some(e.to_string()) },
+    };
+
+    let sh_name = sh.clone();
+    let mut cmd = CommandBuilder::new(sh);
+    cmd.args(options.args.unwrap_or_default());
+    for (key, value) in login_env {
+        cmd.env(key, value);
+    }
+    cmd.env("TERM", "xterm-256color");
+    cmd.env("COLORTERM", "truecolor");
+    for (key, value) in options.env.unwrap_or_default() {
+        cmd.env(key, value);
+    }
+    if let Some(cwd) = &cwd {
+        cmd.cwd(cwd);
     }
 
-    // Stream stderr
-    if let Some(err) = stderr {
-        let app_clone = app.clone();
-        let id_clone = id.clone();
-        async_runtime::spawn(async move {
-            let reader = BufReader::new(err);
-            let mut lines = reader.lines();
-            while let Ok(Some(line)) = lines.next_line().await {
-                let _ = app_clone.emit_all(
-                    HOP_EVENT_CHANNEL,
-                    HopNotificationMessage {
-                        v: HOP_IPC_VERSION,
-                        event: HopEvent::TerminalData { id: id_clone.clone(), data: format!("{line}\n") },
-                    },
-                );
+    let mut child = match pair.slave.spawn_command(cmd) {
+        Ok(c) => c,
+        Err(e) => return HopResponse::TerminalSpawn { ok: false, pid: None, error: Some(e.to_string()) },
+    };
+    // The slave end belongs to the child now; holding it open would keep the
+    // reader from seeing EOF once the shell exits.
+    drop(pair.slave);
+
+    let pid = child.process_id().unwrap_or_default();
+    let (reader, writer) = match (pair.master.try_clone_reader(), pair.master.take_writer()) {
+        (Ok(r), Ok(w)) => (r, w),
+        (Err(e), _) | (_, Err(e)) => {
+            let _ = child.kill();
+            return HopResponse::TerminalSpawn { ok: false, pid: None, error: Some(e.to_string()) };
+        }
+    };
+
+    let scrollback = Arc::new(Mutex::new(Scrollback::default()));
+    let info = TerminalInfo {
+        id: id.clone(),
+        pid: child.process_id(),
+        shell: sh_name,
+        cwd: cwd.map(|c| c.to_string_lossy().to_string()),
+        started_at_ms: chrono::Utc::now().timestamp_millis(),
+        state: "running".into(),
+        exit_code: None,
+        signal: None,
+    };
+    let session = TerminalSession {
+        master: pair.master,
+        writer,
+        killer: child.clone_killer(),
+        scrollback: scrollback.clone(),
+        pid,
+        info,
+    };
+    let handle = Arc::new(Mutex::new(session));
+    manager.sessions.insert(id.clone(), handle.clone());
+
+    // Reads are blocking, so they live on their own thread and hand chunks to
+    // the emitter through a bounded channel. That only bounds what sits
+    // between the two threads: `emit_all` doesn't wait for the webview, so a
+    // slow frontend does not slow the child down.
+    let (tx, rx) = mpsc::channel::<Vec<u8>>(OUTPUT_CHANNEL_CAPACITY);
+    std::thread::spawn(move || {
+        let mut reader = reader;
+        let mut buf = vec![0u8; READ_CHUNK_SIZE];
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            if tx.blocking_send(buf[..n].to_vec()).is_err() {
+                break;
             }
-        });
-    }
+        }
+    });
+    async_runtime::spawn(pump_output(app.clone(), id.clone(), rx, scrollback));
 
     // Exit watcher
     {
         let app_clone = app.clone();
         let id_clone = id.clone();
-        let handle_clone = handle.clone();
-        async_runtime::spawn(async move {
-            let status = {
-                let mut child = handle_clone.lock().await;
-                child.wait().await.ok()
+        let sessions = manager.sessions.clone();
+        std::thread::spawn(move || {
+            let (code, signal) = wait_for_exit(&mut child, pid);
+            let (code, signal) = match handle.lock() {
+                Ok(mut session) => {
+                    session.info.state = "exited".into();
+                    session.info.exit_code = code;
+                    session.info.signal = signal.clone();
+                    (code, signal)
+                }
+                Err(_) => (None, None),
             };
-            let code = status.and_then(|s| s.code());
-            let _ = app_clone.emit_all(
-                HOP_EVENT_CHANNEL,
-                HopNotificationMessage {
-                    v: HOP_IPC_VERSION,
-                    event: HopEvent::TerminalExit { id: id_clone, code, signal: None },
-                },
-            );
+            emit(&app_clone, HopEvent::TerminalExit { id: id_clone.clone(), code, signal });
+
+            async_runtime::spawn(async move {
+                tokio::time::sleep(EXITED_RETENTION).await;
+                // A new terminal may have been spawned under the same id meanwhile.
+                sessions.remove_if(&id_clone, |_, current| Arc::ptr_eq(current, &handle));
+            });
         });
     }
 
@@ -101,32 +340,168 @@ pub async fn spawn(
 }
 
 pub async fn write(manager: &TerminalManager, id: &str, data: &str) -> HopResponse {
-    if let Some(handle) = manager.processes.get(id) {
-        let mut child = handle.value().lock().await;
-        if let Some(stdin) = child.stdin.as_mut() {
-            if let Err(e) = stdin.write_all(data.as_bytes()).await {
-                return HopResponse::TerminalWrite { ok: false, error: Some(e.to_string()) };
-            }
-            let _ = stdin.flush().await;
-            return HopResponse::TerminalWrite { ok: true, error: None };
+    if let Some(handle) = manager.sessions.get(id) {
+        let mut session = match handle.value().lock() {
+            Ok(s) => s,
+            Err(_) => return HopResponse::TerminalWrite { ok: false, error: Some("terminal poisoned".into()) },
+        };
+        if let Err(e) = session.writer.write_all(data.as_bytes()) {
+            return HopResponse::TerminalWrite { ok: false, error: Some(e.to_string()) };
         }
+        let _ = session.writer.flush();
+        return HopResponse::TerminalWrite { ok: true, error: None };
     }
     HopResponse::TerminalWrite { ok: false, error: Some("terminal not found".into()) }
 }
 
-pub async fn resize(_manager: &TerminalManager, _id: &str, _cols: u32, _rows: u32) -> HopResponse {
-    // TODO: add PTY support and propagate resize
-    HopResponse::TerminalResize { ok: true, error: None }
+pub async fn resize(manager: &TerminalManager, id: &str, cols: u32, rows: u32) -> HopResponse {
+    let size = match pty_size(cols, rows) {
+        Ok(s) => s,
+        Err(e) => return HopResponse::TerminalResize { ok: false, error: Some(e) },
+    };
+
+    if let Some(handle) = manager.sessions.get(id) {
+        let session = match handle.value().lock() {
+            Ok(s) => s,
+            Err(_) => return HopResponse::TerminalResize { ok: false, error: Some("terminal poisoned".into()) },
+        };
+        return match session.master.resize(size) {
+            Ok(_) => HopResponse::TerminalResize { ok: true, error: None },
+            Err(e) => HopResponse::TerminalResize { ok: false, error: Some(e.to_string()) },
+        };
+    }
+    HopResponse::TerminalResize { ok: false, error: Some("terminal not found".into()) }
 }
 
-pub async fn kill(manager: &TerminalManager, id: &str, _signal: Option<String>) -> HopResponse {
-    if let Some(handle) = manager.processes.remove(id) {
-        let mut child = handle.1.lock().await;
-        match child.kill().await {
-            Ok(_) => HopResponse::TerminalKill { ok: true, error: None },
-            Err(e) => HopResponse::TerminalKill { ok: false, error: Some(e.to_string()) },
+pub async fn attach(manager: &TerminalManager, id: &str) -> HopResponse {
+    let scrollback = match manager.sessions.get(id) {
+        Some(handle) => match handle.value().lock() {
+            Ok(session) => session.scrollback.clone(),
+            Err(_) => return HopResponse::TerminalAttach { ok: false, data: None, offset: None, error: Some("terminal poisoned".into()) },
+        },
+        None => return HopResponse::TerminalAttach { ok: false, data: None, offset: None, error: Some("terminal not found".into()) },
+    };
+
+    let snapshot = scrollback.lock().map(|sb| sb.snapshot());
+    match snapshot {
+        Ok((data, offset)) => HopResponse::TerminalAttach { ok: true, data: Some(BASE64.encode(data)), offset: Some(offset), error: None },
+        Err(_) => HopResponse::TerminalAttach { ok: false, data: None, offset: None, error: Some("terminal poisoned".into()) },
+    }
+}
+
+/// Running terminals and those that exited within `EXITED_RETENTION`,
+/// oldest first.
+pub async fn list(manager: &TerminalManager) -> HopResponse {
+    let mut terminals: Vec<TerminalInfo> = manager
+        .sessions
+        .iter()
+        .filter_map(|entry| entry.value().lock().ok().map(|s| s.info.clone()))
+        .collect();
+    terminals.sort_by_key(|t| t.started_at_ms);
+    HopResponse::TerminalList { ok: true, terminals: Some(terminals), error: None }
+}
+
+pub async fn info(manager: &TerminalManager, id: &str) -> HopResponse {
+    match manager.sessions.get(id) {
+        Some(handle) => match handle.value().lock() {
+            Ok(session) => HopResponse::TerminalInfo { ok: true, terminal: Some(session.info.clone()), error: None },
+            Err(_) => HopResponse::TerminalInfo { ok: false, terminal: None, error: Some("terminal poisoned".into()) },
+        },
+        None => HopResponse::TerminalInfo { ok: false, terminal: None, error: Some("terminal not found".into()) },
+    }
+}
+
+/// Delivers `signal` (SIGHUP when omitted, like closing a terminal window).
+/// SIGINT goes to the foreground job only, the same as pressing Ctrl-C; the
+/// other signals take down the shell together with every job it started. The
+/// session is reaped by its exit watcher once the shell is actually gone; a
+/// terminal that has already exited is never signalled.
+pub async fn kill(manager: &TerminalManager, id: &str, signal: Option<String>) -> HopResponse {
+    let signal = match signal.as_deref().map(Signal::parse).transpose() {
+        Ok(s) => s.unwrap_or(Signal::Hup),
+        Err(e) => return HopResponse::TerminalKill { ok: false, error: Some(e) },
+    };
+
+    let handle = match manager.sessions.get(id) {
+        Some(h) => h.value().clone(),
+        None => return HopResponse::TerminalKill { ok: false, error: Some("terminal not found".into()) },
+    };
+
+    let pid = match handle.lock() {
+        Ok(session) => {
+            // Once the shell is reaped its pid (and group) may belong to an
+            // unrelated process.
+            if session.info.state != "running" {
+                return HopResponse::TerminalKill { ok: false, error: Some("terminal has exited".into()) };
+            }
+            session.pid
         }
+        Err(_) => return HopResponse::TerminalKill { ok: false, error: Some("terminal poisoned".into()) },
+    };
+
+    let result = if signal == Signal::Int {
+        interrupt(&handle)
+    } else if pid != 0 {
+        process::kill_tree(pid, signal).await
     } else {
-        HopResponse::TerminalKill { ok: false, error: Some("terminal not found".into()) }
+        handle.lock().map_err(|_| "terminal poisoned".to_string()).and_then(|mut s| s.killer.kill().map_err(|e| e.to_string()))
+    };
+
+    match result {
+        Ok(_) => HopResponse::TerminalKill { ok: true, error: None },
+        Err(e) => HopResponse::TerminalKill { ok: false, error: Some(e) },
+    }
+}
+
+/// Waits for the shell to exit and returns its exit code, or the name of the
+/// signal that killed it. portable-pty's status doesn't say which signal it
+/// was, so the shell is reaped directly.
+#[cfg(unix)]
+fn wait_for_exit(child: &mut Box<dyn Child + Send + Sync>, pid: u32) -> (Option<i32>, Option<String>) {
+    use std::os::unix::process::ExitStatusExt;
+    if pid == 0 {
+        return (child.wait().ok().map(|s| s.exit_code() as i32), None);
+    }
+    match process::wait(pid) {
+        Ok(status) => match status.signal() {
+            Some(raw) => (None, Some(process::signal_name(raw))),
+            None => (status.code(), None),
+        },
+        Err(_) => (None, None),
+    }
+}
+
+#[cfg(windows)]
+fn wait_for_exit(child: &mut Box<dyn Child + Send + Sync>, _pid: u32) -> (Option<i32>, Option<String>) {
+    (child.wait().ok().map(|s| s.exit_code() as i32), None)
+}
+
+#[cfg(unix)]
+fn interrupt(handle: &Mutex<TerminalSession>) -> Result<(), String> {
+    let session = handle.lock().map_err(|_| "terminal poisoned".to_string())?;
+    let pgid = session.master.process_group_leader().map(|p| p as u32).unwrap_or(session.pid);
+    process::signal_group(pgid, Signal::Int)
+}
+
+/// ConPTY turns ETX into a console Ctrl-C event for the attached processes.
+#[cfg(windows)]
+fn interrupt(handle: &Mutex<TerminalSession>) -> Result<(), String> {
+    let mut session = handle.lock().map_err(|_| "terminal poisoned".to_string())?;
+    session.writer.write_all(b"\x03").and_then(|_| session.writer.flush()).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scrollback_keeps_the_tail() {
+        let mut sb = Scrollback::with_capacity(4);
+        assert_eq!(sb.append(b"ab"), 0);
+        assert_eq!(sb.snapshot(), (b"ab".to_vec(), 0));
+        assert_eq!(sb.append(b"cde"), 2);
+        assert_eq!(sb.snapshot(), (b"bcde".to_vec(), 1));
+        assert_eq!(sb.append(b"0123456"), 5);
+        assert_eq!(sb.snapshot(), (b"3456".to_vec(), 8));
     }
 }
@@ -0,0 +1,256 @@
+use crate::fs_ops::{self, FsError};
+use crate::ipc::{HopResponse, TrashEntry};
+use crate::sandbox::{self, Sandbox};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tauri::async_runtime;
+use uuid::Uuid;
+
+/// Trashed items older than this are purged for good.
+const RETENTION_DAYS: i64 = 30;
+/// Once the trash grows past this, the oldest items are purged first.
+const MAX_TRASH_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+const META_FILE: &str = "meta.json";
+
+/// Sidecar stored next to each trashed item.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Meta {
+    path: String,
+    kind: String,
+    size: u64,
+    deleted_at_ms: i64,
+}
+
+/// HopCoder-managed trash under the app data dir. Every deleted entry gets
+/// its own `<id>/` directory holding the entry under its original name plus
+/// a `meta.json` recording where it came from.
+#[derive(Clone)]
+pub struct Trash {
+    dir: PathBuf,
+    /// Serializes moves in and out of the trash with purging.
+    lock: Arc<Mutex<()>>,
+}
+
+impl Trash {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir, lock: Arc::default() }
+    }
+
+    /// Moves `path` into the trash and returns its id. Blocking.
+    pub fn put(&self, path: &Path, recursive: bool) -> Result<String, FsError> {
+        let meta = fs::symlink_metadata(path).map_err(|_| FsError::NotFound(path.display().to_string()))?;
+        let kind = fs_ops::kind_of(&meta);
+        if kind == "dir" && !recursive && fs::read_dir(path)?.next().is_some() {
+            return Err(FsError::NotEmpty(path.display().to_string()));
+        }
+        let name = path.file_name().ok_or_else(|| FsError::Io(format!("cannot delete {}", path.display())))?;
+
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let id = Uuid::new_v4().simple().to_string();
+        let slot = self.dir.join(&id);
+        fs::create_dir_all(&slot)?;
+        let meta = Meta {
+            path: path.to_string_lossy().to_string(),
+            kind: kind.into(),
+            size: disk_size(path),
+            deleted_at_ms: chrono::Utc::now().timestamp_millis(),
+        };
+        let result = fs::write(slot.join(META_FILE), serde_json::to_vec(&meta).unwrap_or_default())
+            .map_err(FsError::from)
+            .and_then(|_| fs_ops::move_entry(path, &slot.join(name)));
+        if let Err(e) = result {
+            let _ = fs::remove_dir_all(&slot);
+            return Err(e);
+        }
+        Ok(id)
+    }
+
+    /// Moves an item back to where it was deleted from, refusing to
+    /// overwrite anything that has appeared there since. Blocking.
+    fn restore(&self, id: &str, roots: &[String]) -> Result<String, FsError> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let (slot, meta) = self.entry(id)?;
+        // The item was sandboxed when deleted; check again in case the
+        // caller is now confined to different roots.
+        let root = roots.iter().find(|r| Path::new(&meta.path).starts_with(r)).or(roots.first());
+        let target = sandbox::resolve_entry(&meta.path, root.map(String::as_str))?;
+        if fs::symlink_metadata(&target).is_ok() {
+            return Err(FsError::Conflict(meta.path));
+        }
+        let name = target.file_name().ok_or_else(|| FsError::Io(format!("cannot restore to {}", meta.path)))?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs_ops::move_entry(&slot.join(name), &target)?;
+        let _ = fs::remove_dir_all(&slot);
+        Ok(target.to_string_lossy().to_string())
+    }
+
+    fn entry(&self, id: &str) -> Result<(PathBuf, Meta), FsError> {
+        // Ids are generated by `put`; anything else could escape the trash dir.
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(FsError::NotFound(id.into()));
+        }
+        let slot = self.dir.join(id);
+        let meta = fs::read(slot.join(META_FILE)).map_err(|_| FsError::NotFound(id.into()))?;
+        let meta = serde_json::from_slice(&meta).map_err(|e| FsError::Io(e.to_string()))?;
+        Ok((slot, meta))
+    }
+
+    /// All trashed items, newest first. Blocking.
+    fn list(&self) -> Vec<TrashEntry> {
+        let mut entries: Vec<TrashEntry> = fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|dir| {
+                let id = dir.file_name().to_string_lossy().to_string();
+                let (_, meta) = self.entry(&id).ok()?;
+                Some(TrashEntry { id, path: meta.path, kind: meta.kind, size: meta.size, deleted_at_ms: meta.deleted_at_ms })
+            })
+            .collect();
+        entries.sort_by(|a, b| b.deleted_at_ms.cmp(&a.deleted_at_ms).then_with(|| a.id.cmp(&b.id)));
+        entries
+    }
+
+    /// Applies the retention policy: drops items older than
+    /// `RETENTION_DAYS`, then the oldest items until the trash fits in
+    /// `MAX_TRASH_BYTES`. `keep` is never purged, so a single huge delete
+    /// stays recoverable until the next one. Blocking.
+    pub fn purge(&self, keep: Option<&str>) {
+        let entries = self.list();
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let cutoff = chrono::Utc::now().timestamp_millis() - RETENTION_DAYS * 24 * 60 * 60 * 1000;
+        let mut total: u64 = entries.iter().map(|e| e.size).sum();
+        // Oldest first.
+        for entry in entries.iter().rev() {
+            if Some(entry.id.as_str()) == keep {
+                continue;
+            }
+            let expired = entry.deleted_at_ms < cutoff || total > MAX_TRASH_BYTES;
+            if expired && fs::remove_dir_all(self.dir.join(&entry.id)).is_ok() {
+                total = total.saturating_sub(entry.size);
+            }
+        }
+    }
+}
+
+/// Total size of the files under `path`, not following symlinks.
+fn disk_size(path: &Path) -> u64 {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => fs::read_dir(path)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| disk_size(&entry.path()))
+            .sum(),
+        Ok(meta) => meta.len(),
+        Err(_) => 0,
+    }
+}
+
+/// Moves `path` into the trash instead of deleting it. Directories with
+/// contents are only taken with `recursive`.
+pub async fn delete(trash: &Trash, path: &str, root: Option<&str>, recursive: bool) -> HopResponse {
+    // Address the entry itself so deleting a symlink never trashes its target.
+    let path = match sandbox::resolve_entry(path, root) {
+        Ok(p) => p,
+        Err(e) => return e.into(),
+    };
+
+    let trash = trash.clone();
+    let res = async_runtime::spawn_blocking(move || {
+        let id = trash.put(&path, recursive)?;
+        trash.purge(Some(&id));
+        Ok::<_, FsError>(id)
+    })
+    .await;
+    match res {
+        Ok(Ok(id)) => HopResponse::FsDelete { ok: true, trash_id: Some(id), error: None },
+        Ok(Err(e)) => e.into(),
+        Err(e) => HopResponse::FsDelete { ok: false, trash_id: None, error: Some(e.to_string()) },
+    }
+}
+
+/// Restores an item into whichever of `roots` it was deleted from; with no
+/// roots the original path is used as is.
+pub async fn restore(trash: &Trash, id: String, roots: Vec<String>) -> HopResponse {
+    let trash = trash.clone();
+    match async_runtime::spawn_blocking(move || trash.restore(&id, &roots)).await {
+        Ok(Ok(path)) => HopResponse::FsRestore { ok: true, path: Some(path), error: None },
+        Ok(Err(e)) => e.into(),
+        Err(e) => HopResponse::FsRestore { ok: false, path: None, error: Some(e.to_string()) },
+    }
+}
+
+/// Lists trashed items, limited to those deleted from under one of `roots`
+/// when any are given.
+pub async fn list(trash: &Trash, roots: Vec<String>) -> HopResponse {
+    let roots = match roots.iter().map(|r| Sandbox::new(r).map(|s| s.root().to_path_buf())).collect::<Result<Vec<_>, _>>() {
+        Ok(r) => r,
+        Err(e) => return e.into(),
+    };
+    let trash = trash.clone();
+    let entries = async_runtime::spawn_blocking(move || {
+        let mut entries = trash.list();
+        if !roots.is_empty() {
+            entries.retain(|e| roots.iter().any(|root| Path::new(&e.path).starts_with(root)));
+        }
+        entries
+    })
+    .await;
+    match entries {
+        Ok(entries) => HopResponse::FsTrashList { ok: true, entries: Some(entries), error: None },
+        Err(e) => HopResponse::FsTrashList { ok: false, entries: None, error: Some(e.to_string()) },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trashes_and_restores() {
+        let work = tempfile::tempdir().unwrap();
+        let bin = tempfile::tempdir().unwrap();
+        let trash = Trash::new(bin.path().to_path_buf());
+        let dir = work.path().join("src");
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("lib.rs"), "fn a() {}").unwrap();
+
+        assert_eq!(trash.put(&dir, false).unwrap_err().code(), "not_empty");
+        let id = trash.put(&dir, true).unwrap();
+        assert!(!dir.exists());
+        let listed = trash.list();
+        assert_eq!((listed[0].id.as_str(), listed[0].kind.as_str(), listed[0].size), (id.as_str(), "dir", 9));
+
+        fs::create_dir(&dir).unwrap();
+        assert_eq!(trash.restore(&id, &[]).unwrap_err().code(), "conflict");
+        fs::remove_dir(&dir).unwrap();
+        trash.restore(&id, &[]).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("lib.rs")).unwrap(), "fn a() {}");
+        assert!(trash.list().is_empty());
+        assert_eq!(trash.restore("../x", &[]).unwrap_err().code(), "not_found");
+    }
+
+    #[test]
+    fn test_purges_expired_items() {
+        let work = tempfile::tempdir().unwrap();
+        let bin = tempfile::tempdir().unwrap();
+        let trash = Trash::new(bin.path().to_path_buf());
+        let file = work.path().join("old.txt");
+        fs::write(&file, "old").unwrap();
+        let old = trash.put(&file, false).unwrap();
+        let (slot, mut meta) = trash.entry(&old).unwrap();
+        meta.deleted_at_ms -= (RETENTION_DAYS + 1) * 24 * 60 * 60 * 1000;
+        fs::write(slot.join(META_FILE), serde_json::to_vec(&meta).unwrap()).unwrap();
+
+        fs::write(&file, "new").unwrap();
+        let new = trash.put(&file, false).unwrap();
+        trash.purge(None);
+        let ids: Vec<String> = trash.list().into_iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![new]);
+    }
+}
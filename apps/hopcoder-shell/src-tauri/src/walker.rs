@@ -0,0 +1,237 @@
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::{DirEntry, Match, WalkBuilder};
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// HopCoder-specific ignore file, read with the same syntax as `.gitignore`.
+pub const HOPCODER_IGNORE_FILE: &str = ".hopcoderignore";
+
+/// Ignore files honored in every directory of a walk.
+pub const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore", HOPCODER_IGNORE_FILE];
+
+/// Directories skipped even when no ignore file mentions them.
+const ALWAYS_SKIP: &[&str] = &[".git"];
+
+/// True if any component of `path` is a directory that is always skipped.
+pub fn is_always_skipped(path: &Path) -> bool {
+    path.components().any(|c| ALWAYS_SKIP.iter().any(|skip| c.as_os_str() == *skip))
+}
+
+/// Builds a matcher from the ignore files directly inside `root`, for
+/// callers that need to test individual paths rather than walk a tree.
+/// Unreadable or malformed files are skipped.
+pub fn root_ignore(root: &Path) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(root);
+    for name in IGNORE_FILES {
+        let path = root.join(name);
+        if path.is_file() {
+            builder.add(path);
+        }
+    }
+    builder.build().unwrap_or_else(|_| Gitignore::empty())
+}
+
+/// Tests individual paths against the ignore files a walk of `root` honors:
+/// those of every directory from `root` down to the path, the deepest taking
+/// precedence, and nothing below an ignored directory. Matchers are built on
+/// first use and cached per directory.
+pub struct IgnoreStack {
+    root: PathBuf,
+    matchers: HashMap<PathBuf, Gitignore>,
+}
+
+impl IgnoreStack {
+    pub fn new(root: &Path) -> Self {
+        Self { root: root.to_path_buf(), matchers: HashMap::new() }
+    }
+
+    /// Paths outside the root count as ignored.
+    pub fn is_ignored(&mut self, path: &Path, is_dir: bool) -> bool {
+        let rel = match path.strip_prefix(&self.root) {
+            Ok(rel) => rel.to_path_buf(),
+            Err(_) => return true,
+        };
+        if is_always_skipped(&rel) {
+            return true;
+        }
+        let mut current = self.root.clone();
+        let mut components = rel.components().peekable();
+        while let Some(component) = components.next() {
+            current.push(component);
+            let last = components.peek().is_none();
+            if self.matched(&current, is_dir || !last) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Forgets the matcher of `dir`, after one of its ignore files changed.
+    pub fn invalidate(&mut self, dir: &Path) {
+        self.matchers.remove(dir);
+    }
+
+    fn matched(&mut self, path: &Path, is_dir: bool) -> bool {
+        for dir in path.ancestors().skip(1) {
+            let matcher = self.matchers.entry(dir.to_path_buf()).or_insert_with(|| root_ignore(dir));
+            match matcher.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None if dir == self.root => break,
+                Match::None => {}
+            }
+        }
+        false
+    }
+}
+
+/// Shared flag used to stop a long-running walk from another task.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Decides whether the walk reports an entry and descends into it.
+pub type EntryFilter = Arc<dyn Fn(&DirEntry) -> bool + Send + Sync>;
+
+pub struct WalkOptions {
+    /// Depth relative to the start directory; `Some(1)` lists direct children only.
+    pub max_depth: Option<usize>,
+    /// Stop after visiting this many entries.
+    pub max_entries: Option<usize>,
+    /// Honor `.gitignore`, `.ignore` and `.hopcoderignore`.
+    pub respect_ignore: bool,
+    pub include_hidden: bool,
+    /// Entries this rejects are neither reported nor descended into.
+    pub filter: Option<EntryFilter>,
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        Self { max_depth: None, max_entries: None, respect_ignore: true, include_hidden: true, filter: None }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct WalkOutcome {
+    pub visited: usize,
+    /// The walk stopped because it hit `max_entries`.
+    pub truncated: bool,
+    pub cancelled: bool,
+}
+
+fn builder(start: &Path, options: &WalkOptions) -> WalkBuilder {
+    let filter = options.filter.clone();
+    let mut builder = WalkBuilder::new(start);
+    builder
+        .max_depth(options.max_depth)
+        .follow_links(false)
+        .hidden(!options.include_hidden)
+        .git_ignore(options.respect_ignore)
+        .git_exclude(options.respect_ignore)
+        .git_global(options.respect_ignore)
+        .ignore(options.respect_ignore)
+        .parents(options.respect_ignore)
+        // Plenty of projects keep a .gitignore without being a git checkout.
+        .require_git(false)
+        .filter_entry(move |e| {
+            !ALWAYS_SKIP.iter().any(|skip| e.file_name() == *skip) && filter.as_ref().map(|f| f(e)).unwrap_or(true)
+        });
+    if options.respect_ignore {
+        builder.add_custom_ignore_filename(HOPCODER_IGNORE_FILE);
+    }
+    builder
+}
+
+/// Walks `start` depth-first, calling `visit` for every entry below it (the
+/// start directory itself is not reported). Symlinks are reported but never
+/// followed, so the walk cannot leave the tree it was started in.
+pub fn walk<F>(start: &Path, options: &WalkOptions, cancel: &CancelToken, mut visit: F) -> WalkOutcome
+where
+    F: FnMut(&DirEntry) -> ControlFlow<()>,
+{
+    let mut outcome = WalkOutcome::default();
+    for entry in builder(start, options).build() {
+        if cancel.is_cancelled() {
+            outcome.cancelled = true;
+            break;
+        }
+        let entry = match entry {
+            Ok(e) if e.depth() > 0 => e,
+            _ => continue,
+        };
+        if options.max_entries.map(|max| outcome.visited >= max).unwrap_or(false) {
+            outcome.truncated = true;
+            break;
+        }
+        outcome.visited += 1;
+        if visit(&entry).is_break() {
+            break;
+        }
+    }
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_honors_ignore_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join(".git")).unwrap();
+        std::fs::create_dir_all(dir.path().join("target")).unwrap();
+        std::fs::write(dir.path().join(".gitignore"), "target/\n").unwrap();
+        std::fs::write(dir.path().join(HOPCODER_IGNORE_FILE), "*.log\n").unwrap();
+        std::fs::write(dir.path().join("main.rs"), "").unwrap();
+        std::fs::write(dir.path().join("debug.log"), "").unwrap();
+        std::fs::write(dir.path().join("target").join("out"), "").unwrap();
+
+        let mut names = Vec::new();
+        walk(dir.path(), &WalkOptions::default(), &CancelToken::default(), |e| {
+            names.push(e.file_name().to_string_lossy().to_string());
+            ControlFlow::Continue(())
+        });
+        names.sort();
+        assert_eq!(names, vec![".gitignore", HOPCODER_IGNORE_FILE, "main.rs"]);
+
+        let options = WalkOptions { respect_ignore: false, ..WalkOptions::default() };
+        let outcome = walk(dir.path(), &options, &CancelToken::default(), |_| ControlFlow::Continue(()));
+        // Everything but .git: both ignore files, main.rs, debug.log, target and target/out.
+        assert_eq!(outcome.visited, 6);
+    }
+
+    #[test]
+    fn test_ignore_stack_reads_nested_ignore_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("web").join("dist")).unwrap();
+        std::fs::write(root.join(".gitignore"), "*.log\nbuild/\n").unwrap();
+        std::fs::write(root.join("web").join(".gitignore"), "dist/\n!keep.log\n").unwrap();
+
+        let mut stack = IgnoreStack::new(root);
+        assert!(stack.is_ignored(&root.join("debug.log"), false));
+        assert!(stack.is_ignored(&root.join("web").join("dist"), true));
+        assert!(stack.is_ignored(&root.join("web").join("dist").join("app.js"), false));
+        assert!(stack.is_ignored(&root.join("build").join("out"), false));
+        assert!(!stack.is_ignored(&root.join("web").join("keep.log"), false));
+        assert!(!stack.is_ignored(&root.join("web").join("index.ts"), false));
+        assert!(stack.is_ignored(&root.join(".git").join("HEAD"), false));
+        assert!(stack.is_ignored(Path::new("/elsewhere"), false));
+
+        std::fs::write(root.join("web").join(".gitignore"), "").unwrap();
+        stack.invalidate(&root.join("web"));
+        assert!(!stack.is_ignored(&root.join("web").join("dist"), true));
+    }
+}
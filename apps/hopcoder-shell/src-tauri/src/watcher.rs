@@ -0,0 +1,429 @@
+use crate::atomic_write;
+use crate::ipc::{FsRename, HopEvent, HopNotificationMessage, HopResponse, HOP_EVENT_CHANNEL, HOP_IPC_VERSION};
+use crate::sandbox::{self, SandboxError};
+use crate::walker::{self, CancelToken, IgnoreStack, WalkOptions};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+/// A batch is flushed once the disk has been quiet for this long...
+const DEBOUNCE: Duration = Duration::from_millis(100);
+/// ...or once it has been collecting for this long, whichever comes first,
+/// so a steady stream of writes (a build, `git checkout`) still shows up.
+const MAX_BATCH_DELAY: Duration = Duration::from_secs(1);
+
+/// inotify and kqueue need a watch per directory, and node_modules or target
+/// alone can exhaust the per-user limit, so there only the directories a walk
+/// would enter are watched. FSEvents and ReadDirectoryChangesW cover a whole
+/// tree with one handle; there the root is watched recursively and ignored
+/// paths are dropped by the filter.
+const PER_DIRECTORY: bool = !cfg!(any(target_os = "macos", target_os = "windows"));
+
+/// Active watchers keyed by their canonical root. Dropping a watcher closes
+/// its event channel, which ends the matching debounce thread; that thread
+/// only keeps a weak handle for adding watches to new directories.
+#[derive(Default)]
+pub struct WatchManager {
+    watchers: Mutex<HashMap<String, Arc<Mutex<RecommendedWatcher>>>>,
+}
+
+impl WatchManager {
+    /// Stops every watcher whose root is not inside one of `workspace_roots`,
+    /// e.g. after a different workspace has been opened.
+    pub fn retain_within(&self, workspace_roots: &[String]) {
+        if let Ok(mut watchers) = self.watchers.lock() {
+            watchers.retain(|root, _| workspace_roots.iter().any(|r| Path::new(root).starts_with(r)));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Created,
+    Modified,
+    Deleted,
+}
+
+/// Changes collected during one debounce window, coalesced per path so an
+/// editor's write-rename-chmod dance turns into a single event.
+#[derive(Default)]
+struct Batch {
+    changes: BTreeMap<PathBuf, Change>,
+    renames: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Batch {
+    fn record(&mut self, path: PathBuf, change: Change) {
+        let merged = match (self.changes.get(&path).copied(), change) {
+            (None, c) => Some(c),
+            // A file that appeared and vanished again within the window never
+            // needs to reach the frontend.
+            (Some(Change::Created), Change::Deleted) => None,
+            (Some(Change::Created), Change::Modified) => Some(Change::Created),
+            (Some(Change::Deleted), Change::Created) => Some(Change::Modified),
+            (Some(_), c) => Some(c),
+        };
+        match merged {
+            Some(c) => self.changes.insert(path, c),
+            None => self.changes.remove(&path),
+        };
+    }
+
+    fn add(&mut self, event: Event) {
+        let mut paths = event.paths.into_iter();
+        match event.kind {
+            EventKind::Create(_) => paths.for_each(|p| self.record(p, Change::Created)),
+            EventKind::Remove(_) => paths.for_each(|p| self.record(p, Change::Deleted)),
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                if let (Some(from), Some(to)) = (paths.next(), paths.next()) {
+                    // inotify reports the two halves before the paired event;
+                    // drop them so the rename is not also a delete + create.
+                    if self.changes.get(&from) == Some(&Change::Deleted) {
+                        self.changes.remove(&from);
+                    }
+                    if self.changes.get(&to) == Some(&Change::Created) {
+                        self.changes.remove(&to);
+                    }
+                    self.renames.push((from, to));
+                }
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => paths.for_each(|p| self.record(p, Change::Deleted)),
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => paths.for_each(|p| self.record(p, Change::Created)),
+            // Backends that can't pair renames (FSEvents) only say a name
+            // changed; whether the path still exists tells us which side it was.
+            EventKind::Modify(ModifyKind::Name(_)) => {
+                for p in paths {
+                    let change = if p.symlink_metadata().is_ok() { Change::Created } else { Change::Deleted };
+                    self.record(p, change);
+                }
+            }
+            EventKind::Modify(_) | EventKind::Any => paths.for_each(|p| self.record(p, Change::Modified)),
+            EventKind::Access(_) | EventKind::Other => {}
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.changes.is_empty() && self.renames.is_empty()
+    }
+}
+
+/// Filters paths through the same ignore rules the walker applies.
+struct Filter {
+    ignore: IgnoreStack,
+}
+
+impl Filter {
+    fn new(root: &Path) -> Self {
+        Self { ignore: IgnoreStack::new(root) }
+    }
+
+    fn is_ignored(&mut self, path: &Path) -> bool {
+        self.ignore.is_ignored(path, path.is_dir())
+    }
+
+    /// Forgets the matchers of directories whose ignore files are part of
+    /// the batch, so edits to `.gitignore` take effect immediately. Returns
+    /// those directories.
+    fn refresh(&mut self, batch: &Batch) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = batch
+            .changes
+            .keys()
+            .chain(batch.renames.iter().flat_map(|(from, to)| [from, to]))
+            .filter(|p| walker::IGNORE_FILES.iter().any(|name| p.file_name() == Some(name.as_ref())))
+            .filter_map(|p| p.parent().map(Path::to_path_buf))
+            .collect();
+        dirs.dedup();
+        for dir in &dirs {
+            self.ignore.invalidate(dir);
+        }
+        dirs
+    }
+}
+
+/// Watches `dir` and, where watches are per directory, every directory below
+/// it that a walk would enter.
+fn watch_tree<W: Watcher>(watcher: &mut W, dir: &Path) -> notify::Result<()> {
+    if !PER_DIRECTORY {
+        return watcher.watch(dir, RecursiveMode::Recursive);
+    }
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    let mut result = Ok(());
+    walker::walk(dir, &WalkOptions::default(), &CancelToken::default(), |entry| {
+        if entry.file_type().is_some_and(|t| t.is_dir()) {
+            if let Err(e) = watcher.watch(entry.path(), RecursiveMode::NonRecursive) {
+                result = Err(e);
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    });
+    result
+}
+
+/// Directories an event brings into the tree, which need watches of their
+/// own. Symlinks are left alone, as the walker never follows them.
+fn new_dirs(event: &Event) -> Vec<&Path> {
+    let paths = match event.kind {
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => &event.paths[..],
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => event.paths.get(1..).unwrap_or_default(),
+        _ => &[],
+    };
+    paths.iter().filter(|p| p.symlink_metadata().is_ok_and(|m| m.is_dir())).map(PathBuf::as_path).collect()
+}
+
+fn emit(app: &AppHandle, event: HopEvent) {
+    let _ = app.emit_all(HOP_EVENT_CHANNEL, HopNotificationMessage { v: HOP_IPC_VERSION, event });
+}
+
+fn path_string(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+fn flush(app: &AppHandle, root: &str, filter: &mut Filter, watcher: &Weak<Mutex<RecommendedWatcher>>, batch: Batch) {
+    // A directory that an ignore file no longer excludes needs watches.
+    for dir in filter.refresh(&batch) {
+        if PER_DIRECTORY && !filter.is_ignored(&dir) {
+            add_watches(app, watcher, &dir);
+        }
+    }
+
+    let mut renames = Vec::new();
+    let mut created = Vec::new();
+    let mut modified = Vec::new();
+    let mut deleted = Vec::new();
+    for (from, to) in batch.renames {
+        // An atomic write lands as a rename from its temp file; report it as
+        // the edit it is.
+        if atomic_write::is_temp_path(&from) {
+            if !filter.is_ignored(&to) {
+                modified.push(path_string(&to));
+            }
+            continue;
+        }
+        // A rename across the ignore boundary looks like a plain create or
+        // delete from the frontend's point of view.
+        match (filter.is_ignored(&from), filter.is_ignored(&to)) {
+            (false, false) => renames.push(FsRename { from: path_string(&from), to: path_string(&to) }),
+            (true, false) => created.push(path_string(&to)),
+            (false, true) => deleted.push(path_string(&from)),
+            (true, true) => {}
+        }
+    }
+    for (path, change) in batch.changes {
+        if filter.is_ignored(&path) || atomic_write::is_temp_path(&path) {
+            continue;
+        }
+        match change {
+            Change::Created => created.push(path_string(&path)),
+            Change::Modified => modified.push(path_string(&path)),
+            Change::Deleted => deleted.push(path_string(&path)),
+        }
+    }
+
+    let root = root.to_string();
+    if !renames.is_empty() {
+        emit(app, HopEvent::FsRenamed { root: root.clone(), renames });
+    }
+    if !deleted.is_empty() {
+        emit(app, HopEvent::FsDeleted { root: root.clone(), paths: deleted });
+    }
+    if !created.is_empty() {
+        emit(app, HopEvent::FsCreated { root: root.clone(), paths: created });
+    }
+    if !modified.is_empty() {
+        emit(app, HopEvent::FsModified { root, paths: modified });
+    }
+}
+
+fn warn(app: &AppHandle, message: String) {
+    emit(app, HopEvent::Log { level: "warn".into(), message, scope: Some("workspace.watch".into()) });
+}
+
+/// Watches a directory that appeared after the initial walk, unless the
+/// watcher has been dropped in the meantime.
+fn add_watches(app: &AppHandle, watcher: &Weak<Mutex<RecommendedWatcher>>, dir: &Path) {
+    let Some(watcher) = watcher.upgrade() else {
+        return;
+    };
+    let result = match watcher.lock() {
+        Ok(mut w) => watch_tree(&mut *w, dir),
+        Err(_) => return,
+    };
+    if let Err(e) = result {
+        warn(app, format!("Cannot watch {}: {e}", dir.display()));
+    }
+}
+
+/// Collects raw notify events into debounced batches until the watcher is
+/// dropped.
+fn run(app: AppHandle, root: PathBuf, rx: Receiver<notify::Result<Event>>, watcher: Weak<Mutex<RecommendedWatcher>>) {
+    let root_str = path_string(&root);
+    let mut filter = Filter::new(&root);
+    let mut batch = Batch::default();
+    let mut started = None::<Instant>;
+
+    loop {
+        let received = match started {
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            Some(start) => {
+                let remaining = MAX_BATCH_DELAY.saturating_sub(start.elapsed());
+                rx.recv_timeout(DEBOUNCE.min(remaining))
+            }
+        };
+        match received {
+            Ok(Ok(event)) => {
+                if PER_DIRECTORY {
+                    for dir in new_dirs(&event).into_iter().filter(|d| !filter.is_ignored(d)) {
+                        add_watches(&app, &watcher, dir);
+                    }
+                }
+                batch.add(event);
+                started.get_or_insert_with(Instant::now);
+                continue;
+            }
+            Ok(Err(e)) => warn(&app, format!("File watcher error: {e}")),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if !batch.is_empty() {
+            flush(&app, &root_str, &mut filter, &watcher, std::mem::take(&mut batch));
+        }
+        started = None;
+    }
+}
+
+/// Resolves the root to watch: `root` if given, otherwise the open workspace.
+/// Either way it has to lie inside the open workspace.
+fn resolve_root(root: Option<&str>, workspace_root: Option<&str>) -> Result<PathBuf, SandboxError> {
+    let workspace_root = workspace_root.ok_or_else(|| SandboxError::InvalidRoot("no workspace open".into()))?;
+    let path = sandbox::resolve(root.unwrap_or(workspace_root), Some(workspace_root))?;
+    if !path.is_dir() {
+        return Err(SandboxError::InvalidRoot(path_string(&path)));
+    }
+    Ok(path)
+}
+
+/// Starts watching `root` and everything below it that is not ignored.
+/// Changes arrive as batched `fs.created`, `fs.modified`, `fs.deleted` and
+/// `fs.renamed` events. Watching a root that is already watched is a no-op.
+pub async fn watch(app: &AppHandle, manager: &WatchManager, root: Option<&str>, workspace_root: Option<&str>) -> HopResponse {
+    let root = match resolve_root(root, workspace_root) {
+        Ok(r) => r,
+        Err(e) => return e.into(),
+    };
+    let key = path_string(&root);
+
+    let mut watchers = match manager.watchers.lock() {
+        Ok(w) => w,
+        Err(_) => return HopResponse::WorkspaceWatch { ok: false, root: None, error: Some("Watcher state poisoned".into()) },
+    };
+    if watchers.contains_key(&key) {
+        return HopResponse::WorkspaceWatch { ok: true, root: Some(key), error: None };
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = match notify::recommended_watcher(tx) {
+        Ok(w) => w,
+        Err(e) => return HopResponse::WorkspaceWatch { ok: false, root: None, error: Some(e.to_string()) },
+    };
+    if let Err(e) = watch_tree(&mut watcher, &root) {
+        return HopResponse::WorkspaceWatch { ok: false, root: None, error: Some(e.to_string()) };
+    }
+
+    let watcher = Arc::new(Mutex::new(watcher));
+    let handle = Arc::downgrade(&watcher);
+    let app = app.clone();
+    std::thread::spawn(move || run(app, root, rx, handle));
+    watchers.insert(key.clone(), watcher);
+
+    HopResponse::WorkspaceWatch { ok: true, root: Some(key), error: None }
+}
+
+pub async fn unwatch(manager: &WatchManager, root: Option<&str>, workspace_root: Option<&str>) -> HopResponse {
+    let root = match resolve_root(root, workspace_root) {
+        Ok(r) => r,
+        Err(e) => return e.into(),
+    };
+    let removed = manager.watchers.lock().ok().and_then(|mut w| w.remove(&path_string(&root)));
+    match removed {
+        Some(_) => HopResponse::WorkspaceUnwatch { ok: true, error: None },
+        None => HopResponse::WorkspaceUnwatch { ok: false, error: Some("Not watching that root".into()) },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, DataChange, RemoveKind};
+
+    fn event(kind: EventKind, paths: &[&str]) -> Event {
+        paths.iter().fold(Event::new(kind), |e, p| e.add_path(PathBuf::from(p)))
+    }
+
+    #[test]
+    fn test_coalesces_batch() {
+        let mut batch = Batch::default();
+        batch.add(event(EventKind::Create(CreateKind::File), &["/w/a"]));
+        batch.add(event(EventKind::Modify(ModifyKind::Data(DataChange::Any)), &["/w/a"]));
+        batch.add(event(EventKind::Create(CreateKind::File), &["/w/tmp"]));
+        batch.add(event(EventKind::Remove(RemoveKind::File), &["/w/tmp"]));
+        batch.add(event(EventKind::Modify(ModifyKind::Name(RenameMode::From)), &["/w/old"]));
+        batch.add(event(EventKind::Modify(ModifyKind::Name(RenameMode::To)), &["/w/new"]));
+        batch.add(event(EventKind::Modify(ModifyKind::Name(RenameMode::Both)), &["/w/old", "/w/new"]));
+
+        assert_eq!(batch.changes.into_iter().collect::<Vec<_>>(), vec![(PathBuf::from("/w/a"), Change::Created)]);
+        assert_eq!(batch.renames, vec![(PathBuf::from("/w/old"), PathBuf::from("/w/new"))]);
+    }
+
+    /// Records the paths it is asked to watch.
+    struct Recorder(Vec<PathBuf>);
+
+    impl Watcher for Recorder {
+        fn new<F: notify::EventHandler>(_: F, _: notify::Config) -> notify::Result<Self> {
+            Ok(Self(Vec::new()))
+        }
+
+        fn watch(&mut self, path: &Path, _: RecursiveMode) -> notify::Result<()> {
+            self.0.push(path.to_path_buf());
+            Ok(())
+        }
+
+        fn unwatch(&mut self, _: &Path) -> notify::Result<()> {
+            Ok(())
+        }
+
+        fn kind() -> notify::WatcherKind {
+            notify::WatcherKind::NullWatcher
+        }
+    }
+
+    #[test]
+    fn test_skips_ignored_trees() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for sub in ["src/bin", "node_modules/left-pad", "web/dist", ".git/objects"] {
+            std::fs::create_dir_all(root.join(sub)).unwrap();
+        }
+        std::fs::write(root.join(".gitignore"), "node_modules/\n").unwrap();
+        std::fs::write(root.join("web").join(".gitignore"), "dist/\n").unwrap();
+
+        let mut watcher = Recorder(Vec::new());
+        watch_tree(&mut watcher, root).unwrap();
+        if PER_DIRECTORY {
+            let mut watched: Vec<_> = watcher.0.iter().map(|p| p.strip_prefix(root).unwrap().to_path_buf()).collect();
+            watched.sort();
+            assert_eq!(watched, ["", "src", "src/bin", "web"].map(PathBuf::from));
+        }
+
+        let mut filter = Filter::new(root);
+        assert!(filter.is_ignored(&root.join("web/dist/app.js")));
+        assert!(filter.is_ignored(&root.join("node_modules/left-pad")));
+        assert!(!filter.is_ignored(&root.join("src/bin")));
+    }
+}
@@ -1,62 +1,492 @@
-use crate::ipc::{HopResponse, WorkspaceEntry};
-use std::path::Path;
+use crate::ipc::{HopResponse, WorkspaceEntry, WorkspaceInfo};
+use crate::sandbox::{self, Sandbox};
+use crate::walker::{self, CancelToken, IgnoreStack, WalkOptions};
+use crate::workspace_store::WorkspaceStore;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ignore::DirEntry;
+use serde_json::{Map, Value};
+use std::collections::BinaryHeap;
+use std::fs::Metadata;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::UNIX_EPOCH;
-use tokio::fs;
-
-pub async fn open(root: &str) -> HopResponse {
-    let p = Path::new(root);
-    match fs::metadata(p).await {
-        Ok(meta) if meta.is_dir() => HopResponse::WorkspaceOpen {
-            ok: true,
-            workspace_root: Some(p.to_string_lossy().to_string()),
-            error: None,
+use tauri::async_runtime;
+
+/// Number of workspaces `workspace.recent` returns by default.
+const DEFAULT_RECENT: usize = 20;
+
+/// The open workspace, if any. Its first root is the primary one: requests
+/// that don't name a root (terminals, searches) default to it, and paths
+/// are sandboxed to whichever root contains them.
+#[derive(Default)]
+pub struct WorkspaceState {
+    current: RwLock<Option<WorkspaceInfo>>,
+}
+
+impl WorkspaceState {
+    pub fn current(&self) -> Option<WorkspaceInfo> {
+        self.current.read().ok().and_then(|c| c.clone())
+    }
+
+    /// The primary root.
+    pub fn root(&self) -> Option<String> {
+        self.roots().into_iter().next()
+    }
+
+    pub fn roots(&self) -> Vec<String> {
+        self.current().map(|c| c.roots).unwrap_or_default()
+    }
+
+    /// The root an absolute `path` lies in (the innermost one if roots
+    /// nest), falling back to the primary root for relative or foreign
+    /// paths so the sandbox reports them as usual.
+    pub fn root_for(&self, path: &str) -> Option<String> {
+        let roots = self.roots();
+        roots
+            .iter()
+            .filter(|root| Path::new(path).starts_with(root))
+            .max_by_key(|root| root.len())
+            .or_else(|| roots.first())
+            .cloned()
+    }
+
+    fn set(&self, workspace: Option<WorkspaceInfo>) {
+        if let Ok(mut c) = self.current.write() {
+            *c = workspace;
+        }
+    }
+}
+
+fn workspace_error(error: String) -> HopResponse {
+    HopResponse::WorkspaceOpen { ok: false, workspace_root: None, workspace: None, error: Some(error) }
+}
+
+/// Canonicalizes and de-duplicates roots, keeping their order.
+fn canonical_roots(roots: Vec<String>) -> Result<Vec<String>, String> {
+    let mut canonical: Vec<String> = Vec::new();
+    for root in roots {
+        let root = Sandbox::new(&root).map_err(|_| format!("Invalid workspace root: {root}"))?;
+        let root = root.root().to_string_lossy().to_string();
+        if !canonical.contains(&root) {
+            canonical.push(root);
+        }
+    }
+    if canonical.is_empty() {
+        return Err("No workspace roots given".into());
+    }
+    Ok(canonical)
+}
+
+/// Opens a workspace from `roots`, or reopens a recent one by `id`, makes it
+/// the active workspace and records it as recently opened. Roots are stored
+/// and echoed back in canonical form, so later sandbox checks compare like
+/// with like.
+pub async fn open(state: &WorkspaceState, store: &WorkspaceStore, roots: Vec<String>, id: Option<String>, name: Option<String>) -> HopResponse {
+    let roots = match (id, roots.is_empty()) {
+        (Some(id), true) => match store.get(&id) {
+            Ok(Some(workspace)) => workspace.roots,
+            Ok(None) => return workspace_error(format!("Unknown workspace: {id}")),
+            Err(e) => return workspace_error(e.to_string()),
         },
-        _ => HopResponse::WorkspaceOpen { ok: false, workspace_root: None, error: Some("Invalid workspace".into()) },
-    }
-}
-
-pub async fn list(root: &str) -> HopResponse {
-    let mut entries = Vec::new();
-    match fs::read_dir(root).await {
-        Ok(mut dir) => {
-            loop {
-                match dir.next_entry().await {
-                    Ok(Some(entry)) => {
-                        let meta_ok = entry.metadata().await.ok();
-                        let file_type = meta_ok.as_ref().map(|m| m.file_type());
-                        let kind = if let Some(ft) = file_type {
-                            if ft.is_dir() {
-                                "dir"
-                            } else if ft.is_symlink() {
-                                "symlink"
-                            } else {
-                                "file"
-                            }
-                        } else {
-                            "file"
-                        };
-                        let size = meta_ok
-                            .as_ref()
-                            .and_then(|m| if m.is_file() { Some(m.len()) } else { None });
-                        let modified_ms = meta_ok
-                            .and_then(|m| m.modified().ok())
-                            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
-                            .map(|dur| dur.as_millis() as i64);
-                        entries.push(WorkspaceEntry {
-                            path: entry.path().to_string_lossy().to_string(),
-                            kind: kind.to_string(),
-                            size,
-                            modified_ms,
-                        });
-                    }
-                    Ok(None) => break,
-                    Err(e) => {
-                        return HopResponse::WorkspaceList { ok: false, entries: None, error: Some(e.to_string()) }
+        (None, false) => roots,
+        _ => return workspace_error("Pass either roots or the id of a recent workspace".into()),
+    };
+    let roots = match canonical_roots(roots) {
+        Ok(r) => r,
+        Err(e) => return workspace_error(e),
+    };
+    match store.touch(&roots, name.as_deref()) {
+        Ok(workspace) => {
+            state.set(Some(workspace.clone()));
+            HopResponse::WorkspaceOpen { ok: true, workspace_root: workspace.roots.first().cloned(), workspace: Some(workspace), error: None }
+        }
+        Err(e) => workspace_error(e.to_string()),
+    }
+}
+
+pub async fn close(state: &WorkspaceState) -> HopResponse {
+    state.set(None);
+    HopResponse::WorkspaceClose { ok: true, error: None }
+}
+
+/// Adds and removes roots of the open workspace. The primary root can't be
+/// removed; close the workspace instead.
+pub async fn update_roots(state: &WorkspaceState, store: &WorkspaceStore, add: Vec<String>, remove: Vec<String>) -> HopResponse {
+    let roots_error = |error: String| HopResponse::WorkspaceUpdateRoots { ok: false, workspace: None, error: Some(error) };
+    let mut workspace = match state.current() {
+        Some(w) => w,
+        None => return roots_error("No workspace open".into()),
+    };
+    for root in remove {
+        let root = Sandbox::new(&root).map(|s| s.root().to_string_lossy().to_string()).unwrap_or(root);
+        if workspace.roots.first() == Some(&root) {
+            return roots_error("Cannot remove the primary root".into());
+        }
+        workspace.roots.retain(|r| *r != root);
+    }
+    if !add.is_empty() {
+        match canonical_roots(add) {
+            Ok(added) => workspace.roots.extend(added.into_iter().filter(|r| !workspace.roots.contains(r)).collect::<Vec<_>>()),
+            Err(e) => return roots_error(e),
+        }
+    }
+    if let Err(e) = store.set_roots(&workspace.id, &workspace.roots) {
+        return roots_error(e.to_string());
+    }
+    state.set(Some(workspace.clone()));
+    HopResponse::WorkspaceUpdateRoots { ok: true, workspace: Some(workspace), error: None }
+}
+
+pub async fn recent(store: &WorkspaceStore, limit: Option<usize>) -> HopResponse {
+    match store.recent(limit.unwrap_or(DEFAULT_RECENT)) {
+        Ok(workspaces) => HopResponse::WorkspaceRecent { ok: true, workspaces: Some(workspaces), error: None },
+        Err(e) => HopResponse::WorkspaceRecent { ok: false, workspaces: None, error: Some(e.to_string()) },
+    }
+}
+
+pub async fn forget(store: &WorkspaceStore, id: &str) -> HopResponse {
+    match store.remove(id) {
+        Ok(_) => HopResponse::WorkspaceForget { ok: true, error: None },
+        Err(e) => HopResponse::WorkspaceForget { ok: false, error: Some(e.to_string()) },
+    }
+}
+
+/// Reads the settings of workspace `id` (default: the open one), first
+/// merging in `update` if given.
+pub async fn settings(state: &WorkspaceState, store: &WorkspaceStore, id: Option<String>, update: Option<Map<String, Value>>) -> HopResponse {
+    let settings_error = |error: String| HopResponse::WorkspaceSettings { ok: false, settings: None, error: Some(error) };
+    let id = match id.or_else(|| state.current().map(|w| w.id)) {
+        Some(id) => id,
+        None => return settings_error("No workspace open".into()),
+    };
+    let result = match update {
+        Some(update) => store.update_settings(&id, update),
+        None => store.get(&id).map(|w| w.map(|w| w.settings)),
+    };
+    match result {
+        Ok(Some(settings)) => {
+            if let Ok(mut current) = state.current.write() {
+                if let Some(workspace) = current.as_mut().filter(|w| w.id == id) {
+                    workspace.settings = settings.clone();
+                }
+            }
+            HopResponse::WorkspaceSettings { ok: true, settings: Some(settings), error: None }
+        }
+        Ok(None) => settings_error(format!("Unknown workspace: {id}")),
+        Err(e) => settings_error(e.to_string()),
+    }
+}
+
+pub struct ListOptions {
+    /// Levels to descend below `dir`; 1 lists direct children only.
+    pub depth: Option<usize>,
+    /// Opaque `nextCursor` from the previous page.
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+    /// `"dirsFirst"` (default) or `"name"`.
+    pub sort: Option<String>,
+    /// Also report entries matched by ignore files, flagged as `ignored`.
+    pub include_ignored: bool,
+}
+
+const DEFAULT_LIMIT: usize = 1000;
+/// Upper bound on entries a single listing walks, so a deep `depth` over a
+/// huge tree can't hang it.
+const MAX_ENTRIES: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortOrder {
+    DirsFirst,
+    Name,
+}
+
+impl SortOrder {
+    fn parse(sort: Option<&str>) -> Result<Self, String> {
+        match sort {
+            None | Some("dirsFirst") => Ok(SortOrder::DirsFirst),
+            Some("name") => Ok(SortOrder::Name),
+            Some(other) => Err(format!("Unsupported sort order: {other}")),
+        }
+    }
+}
+
+/// One `(rank, folded name, name)` triple per path component. Comparing keys
+/// lexicographically yields a depth-first listing in which every directory
+/// is followed by its own children, so pages of a recursive listing still
+/// read as a tree. Only the last component can be a non-directory.
+type SortKey = Vec<(u8, String, String)>;
+
+fn sort_key(rel: &Path, is_dir: bool, order: SortOrder) -> SortKey {
+    let count = rel.components().count();
+    rel.components()
+        .enumerate()
+        .map(|(i, c)| {
+            let name = c.as_os_str().to_string_lossy().to_string();
+            let rank = if order == SortOrder::DirsFirst && i + 1 == count && !is_dir { 1 } else { 0 };
+            (rank, name.to_lowercase(), name)
+        })
+        .collect()
+}
+
+/// Cursors name the last entry returned, so a page boundary stays put when
+/// entries before it are added or removed between requests.
+fn encode_cursor(rel: &Path, is_dir: bool) -> String {
+    let rel = rel.to_string_lossy().replace('\\', "/");
+    BASE64.encode(format!("{}{rel}", if is_dir { 'd' } else { 'f' }))
+}
+
+fn decode_cursor(cursor: &str) -> Result<(PathBuf, bool), String> {
+    let raw = BASE64.decode(cursor).ok().and_then(|b| String::from_utf8(b).ok()).ok_or("Invalid cursor")?;
+    match raw.split_at(raw.len().min(1)) {
+        ("d", rel) => Ok((PathBuf::from(rel), true)),
+        ("f", rel) => Ok((PathBuf::from(rel), false)),
+        _ => Err("Invalid cursor".into()),
+    }
+}
+
+/// The first `limit` entries after the cursor, kept in a max-heap so a large
+/// listing never holds more than one page.
+struct Page {
+    after: Option<SortKey>,
+    limit: usize,
+    order: SortOrder,
+    heap: BinaryHeap<(SortKey, PathBuf, bool, bool)>,
+    /// Entries past the page were seen.
+    more: bool,
+}
+
+impl Page {
+    fn offer(&mut self, rel: PathBuf, is_dir: bool, ignored: bool) {
+        let key = sort_key(&rel, is_dir, self.order);
+        if self.after.as_ref().map(|after| &key <= after).unwrap_or(false) {
+            return;
+        }
+        self.heap.push((key, rel, is_dir, ignored));
+        if self.heap.len() > self.limit {
+            self.heap.pop();
+            self.more = true;
+        }
+    }
+}
+
+/// Lists `dir`, which must lie inside `workspace_root` when one is open.
+/// Entries come back in a stable tree order and are paged with
+/// `cursor`/`limit`. Ignored entries are left out unless `include_ignored`
+/// is set; ignored directories are then reported but not descended into.
+/// The walk stops after `MAX_ENTRIES`, which the response reports as
+/// `truncated`.
+pub async fn list(dir: &str, workspace_root: Option<&str>, options: ListOptions) -> HopResponse {
+    let dir = match sandbox::resolve(dir, workspace_root) {
+        Ok(d) => d,
+        Err(e) => return e.into(),
+    };
+    if !dir.is_dir() {
+        return list_error(format!("Not a directory: {}", dir.display()));
+    }
+    let order = match SortOrder::parse(options.sort.as_deref()) {
+        Ok(o) => o,
+        Err(e) => return list_error(e),
+    };
+    let after = match options.cursor.as_deref().map(decode_cursor).transpose() {
+        Ok(c) => c.map(|(rel, is_dir)| sort_key(&rel, is_dir, order)),
+        Err(e) => return list_error(e),
+    };
+    let limit = options.limit.unwrap_or(DEFAULT_LIMIT).max(1);
+    let depth = options.depth.unwrap_or(1).max(1);
+    let include_ignored = options.include_ignored;
+
+    // Ignore files above `dir` still apply to it, so match from the
+    // workspace root when `dir` lies inside it.
+    let ignore_root = workspace_root.map(PathBuf::from).filter(|r| dir.starts_with(r)).unwrap_or_else(|| dir.clone());
+
+    let walk = async_runtime::spawn_blocking(move || {
+        let mut options = WalkOptions { max_depth: Some(depth), max_entries: Some(MAX_ENTRIES), ..WalkOptions::default() };
+        // With `include_ignored` the ignore rules are applied here instead of
+        // by the walker, so ignored entries can be set aside, and reported,
+        // rather than silently skipped; either way they are not descended into.
+        let pruned = Arc::new(Mutex::new(Vec::new()));
+        if include_ignored {
+            let stack = Mutex::new(IgnoreStack::new(&ignore_root));
+            let pruned = pruned.clone();
+            options.respect_ignore = false;
+            options.filter = Some(Arc::new(move |entry: &DirEntry| {
+                let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+                let ignored = stack.lock().map(|mut s| s.is_ignored(entry.path(), is_dir)).unwrap_or(false);
+                if ignored {
+                    if let Ok(mut pruned) = pruned.lock() {
+                        pruned.push((entry.path().to_path_buf(), is_dir));
                     }
                 }
+                !ignored
+            }));
+        }
+
+        let mut page = Page { after, limit, order, heap: BinaryHeap::new(), more: false };
+        let outcome = walker::walk(&dir, &options, &CancelToken::default(), |entry| {
+            let path = entry.path();
+            let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            page.offer(path.strip_prefix(&dir).unwrap_or(path).to_path_buf(), is_dir, false);
+            ControlFlow::Continue(())
+        });
+        let pruned = pruned.lock().map(|mut p| std::mem::take(&mut *p)).unwrap_or_default();
+        for (path, is_dir) in pruned {
+            page.offer(path.strip_prefix(&dir).unwrap_or(&path).to_path_buf(), is_dir, true);
+        }
+
+        let entries = page.heap.into_sorted_vec();
+        let next_cursor = if page.more { entries.last().map(|(_, rel, is_dir, _)| encode_cursor(rel, *is_dir)) } else { None };
+        let entries: Vec<WorkspaceEntry> = entries
+            .into_iter()
+            .map(|(_, rel, _, ignored)| {
+                let path = dir.join(rel);
+                // The walker doesn't follow links, so describe the entry itself.
+                describe(&path, path.symlink_metadata().ok(), ignored)
+            })
+            .collect();
+        (entries, next_cursor, outcome.truncated)
+    })
+    .await;
+
+    match walk {
+        Ok((entries, next_cursor, truncated)) => {
+            HopResponse::WorkspaceList { ok: true, entries: Some(entries), next_cursor, truncated: Some(truncated), error: None }
+        }
+        Err(e) => list_error(e.to_string()),
+    }
+}
+
+fn list_error(error: String) -> HopResponse {
+    HopResponse::WorkspaceList { ok: false, entries: None, next_cursor: None, truncated: None, error: Some(error) }
+}
+
+#[cfg(unix)]
+fn is_executable(_path: &Path, meta: &Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    meta.is_file() && meta.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(windows)]
+fn is_executable(path: &Path, meta: &Metadata) -> bool {
+    const EXTENSIONS: &[&str] = &["exe", "com", "bat", "cmd", "ps1"];
+    meta.is_file()
+        && path
+            .extension()
+            .map(|ext| EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+            .unwrap_or(false)
+}
+
+#[cfg(unix)]
+fn is_hidden(name: &str, _meta: Option<&Metadata>) -> bool {
+    name.starts_with('.')
+}
+
+#[cfg(windows)]
+fn is_hidden(name: &str, meta: Option<&Metadata>) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    name.starts_with('.') || meta.map(|m| m.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0).unwrap_or(false)
+}
+
+/// Builds the entry for `path` from its `symlink_metadata`; also used by
+/// `fs.stat`.
+pub fn describe(path: &Path, meta: Option<Metadata>, ignored: bool) -> WorkspaceEntry {
+    let is_symlink = meta.as_ref().map(|m| m.file_type().is_symlink()).unwrap_or(false);
+    let kind = match &meta {
+        Some(m) if m.is_dir() => "dir",
+        _ if is_symlink => "symlink",
+        _ => "file",
+    };
+    let size = meta.as_ref().and_then(|m| if m.is_file() { Some(m.len()) } else { None });
+    let modified_ms = meta
+        .as_ref()
+        .and_then(|m| m.modified().ok())
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|dur| dur.as_millis() as i64);
+    let target = if is_symlink { std::fs::read_link(path).ok().map(|t| t.to_string_lossy().to_string()) } else { None };
+    // A link is broken when following it fails, whatever the reason.
+    let broken = is_symlink && std::fs::metadata(path).is_err();
+    // Permission bits live on the target, not on the link.
+    let target_meta = if is_symlink { std::fs::metadata(path).ok() } else { meta.clone() };
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+
+    WorkspaceEntry {
+        path: path.to_string_lossy().to_string(),
+        kind: kind.to_string(),
+        size,
+        modified_ms,
+        target,
+        broken,
+        hidden: is_hidden(&name, meta.as_ref()),
+        ignored,
+        executable: target_meta.as_ref().map(|m| is_executable(path, m)).unwrap_or(false),
+        readonly: target_meta.map(|m| m.permissions().readonly()).unwrap_or(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_root_for_picks_containing_root() {
+        let state = WorkspaceState::default();
+        assert_eq!(state.root_for("/work/app/src"), None);
+        let roots = vec!["/work/app".to_string(), "/work/lib".to_string(), "/work/app/vendor".to_string()];
+        state.set(Some(WorkspaceInfo { id: "w".into(), name: "app".into(), roots, settings: Value::Null, last_opened_ms: 0 }));
+        assert_eq!(state.root_for("/work/lib/a.rs").as_deref(), Some("/work/lib"));
+        assert_eq!(state.root_for("/work/app/vendor/x").as_deref(), Some("/work/app/vendor"));
+        assert_eq!(state.root_for("src/main.rs").as_deref(), Some("/work/app"));
+        assert_eq!(state.root_for("/elsewhere").as_deref(), Some("/work/app"));
+    }
+
+    #[test]
+    fn test_sorts_as_tree_with_dirs_first() {
+        let mut paths = [("b.txt", false), ("src", true), ("src/main.rs", false), ("src/bin", true), ("A.md", false), ("docs", true)];
+        paths.sort_by_key(|(p, is_dir)| sort_key(Path::new(p), *is_dir, SortOrder::DirsFirst));
+        let order: Vec<&str> = paths.iter().map(|(p, _)| *p).collect();
+        assert_eq!(order, vec!["docs", "src", "src/bin", "src/main.rs", "A.md", "b.txt"]);
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = encode_cursor(Path::new("src/main.rs"), false);
+        assert_eq!(decode_cursor(&cursor).unwrap(), (PathBuf::from("src/main.rs"), false));
+        assert!(decode_cursor("not base64!").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_list_pages_and_prunes_ignored_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        for sub in ["src", "node_modules/left-pad"] {
+            std::fs::create_dir_all(root.join(sub)).unwrap();
+        }
+        std::fs::write(root.join(".gitignore"), "node_modules/\n").unwrap();
+        std::fs::write(root.join("src").join("main.rs"), "").unwrap();
+        std::fs::write(root.join("node_modules").join("left-pad").join("index.js"), "").unwrap();
+
+        let root_str = root.to_string_lossy().to_string();
+        let mut names = Vec::new();
+        let mut cursor = None;
+        loop {
+            let options = ListOptions { depth: Some(3), cursor, limit: Some(2), sort: None, include_ignored: true };
+            let (entries, next_cursor) = match list(&root_str, Some(&root_str), options).await {
+                HopResponse::WorkspaceList { ok: true, entries, next_cursor, truncated: Some(false), .. } => (entries.unwrap(), next_cursor),
+                other => panic!("{other:?}"),
+            };
+            assert!(entries.len() <= 2);
+            for entry in entries {
+                let rel = Path::new(&entry.path).strip_prefix(&root).unwrap().to_string_lossy().to_string();
+                names.push((rel, entry.ignored));
+            }
+            cursor = next_cursor;
+            if cursor.is_none() {
+                break;
             }
-            HopResponse::WorkspaceList { ok: true, entries: Some(entries), error: None }
         }
-        Err(e) => HopResponse::WorkspaceList { ok: false, entries: None, error: Some(e.to_string()) },
+        let expected = [("node_modules", true), ("src", false), ("src/main.rs", false), (".gitignore", false)];
+        assert_eq!(names, expected.map(|(p, ignored)| (p.to_string(), ignored)));
     }
 }
@@ -0,0 +1,190 @@
+use crate::ipc::WorkspaceInfo;
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde_json::{Map, Value};
+use std::path::Path;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Recently opened workspaces and their settings. A workspace is a set of
+/// roots; opening the same roots again reuses its row, settings included.
+pub struct WorkspaceStore {
+    conn: Mutex<Connection>,
+}
+
+const COLUMNS: &str = "id, name, roots_json, settings_json, last_opened_at";
+
+fn roots_key(roots: &[String]) -> String {
+    roots.join("\n")
+}
+
+/// A row whose roots can't be parsed is an error rather than a workspace
+/// with no roots, which nothing could open or match again.
+fn from_row(row: &Row) -> rusqlite::Result<WorkspaceInfo> {
+    let roots: String = row.get(2)?;
+    let settings: String = row.get(3)?;
+    Ok(WorkspaceInfo {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        roots: serde_json::from_str(&roots).map_err(|e| rusqlite::Error::FromSqlConversionFailure(2, Type::Text, Box::new(e)))?,
+        settings: serde_json::from_str(&settings).unwrap_or_else(|_| Value::Object(Map::new())),
+        last_opened_ms: row.get(4)?,
+    })
+}
+
+/// Default name: the primary root's folder name.
+fn default_name(roots: &[String]) -> String {
+    roots
+        .first()
+        .map(|root| Path::new(root).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| root.clone()))
+        .unwrap_or_default()
+}
+
+impl WorkspaceStore {
+    pub fn new(db_path: &str) -> rusqlite::Result<Self> {
+        let conn = Connection::open(db_path)?;
+        conn.execute_batch(
+            r#"
+            CREATE TABLE IF NOT EXISTS workspaces (
+              id TEXT PRIMARY KEY,
+              name TEXT NOT NULL,
+              roots_key TEXT NOT NULL,
+              roots_json TEXT NOT NULL,
+              settings_json TEXT NOT NULL DEFAULT '{}',
+              created_at INTEGER NOT NULL,
+              last_opened_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_workspaces_roots_key
+              ON workspaces (roots_key);
+            CREATE INDEX IF NOT EXISTS idx_workspaces_last_opened_at
+              ON workspaces (last_opened_at);
+            "#,
+        )?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records that `roots` were opened, creating the workspace the first
+    /// time. A given `name` replaces the stored one.
+    pub fn touch(&self, roots: &[String], name: Option<&str>) -> rusqlite::Result<WorkspaceInfo> {
+        let conn = self.conn();
+        let now = chrono::Utc::now().timestamp_millis();
+        let key = roots_key(roots);
+        let existing: Option<String> = conn
+            .query_row(
+                "SELECT id FROM workspaces WHERE roots_key = ?1 ORDER BY last_opened_at DESC LIMIT 1",
+                [&key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let id = match existing {
+            Some(id) => {
+                conn.execute(
+                    "UPDATE workspaces SET last_opened_at = ?2, name = COALESCE(?3, name) WHERE id = ?1",
+                    params![id, now, name],
+                )?;
+                id
+            }
+            None => {
+                let id = Uuid::new_v4().to_string();
+                let roots_json = serde_json::to_string(roots).unwrap_or_default();
+                conn.execute(
+                    r#"
+                    INSERT INTO workspaces (id, name, roots_key, roots_json, created_at, last_opened_at)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+                    "#,
+                    params![id, name.map(str::to_string).unwrap_or_else(|| default_name(roots)), key, roots_json, now],
+                )?;
+                id
+            }
+        };
+        conn.query_row(&format!("SELECT {COLUMNS} FROM workspaces WHERE id = ?1"), [&id], from_row)
+    }
+
+    pub fn get(&self, id: &str) -> rusqlite::Result<Option<WorkspaceInfo>> {
+        self.conn()
+            .query_row(&format!("SELECT {COLUMNS} FROM workspaces WHERE id = ?1"), [id], from_row)
+            .optional()
+    }
+
+    pub fn set_roots(&self, id: &str, roots: &[String]) -> rusqlite::Result<()> {
+        let roots_json = serde_json::to_string(roots).unwrap_or_default();
+        self.conn().execute(
+            "UPDATE workspaces SET roots_key = ?2, roots_json = ?3 WHERE id = ?1",
+            params![id, roots_key(roots), roots_json],
+        )?;
+        Ok(())
+    }
+
+    /// Most recently opened first.
+    pub fn recent(&self, limit: usize) -> rusqlite::Result<Vec<WorkspaceInfo>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!("SELECT {COLUMNS} FROM workspaces ORDER BY last_opened_at DESC LIMIT ?1"))?;
+        let rows = stmt.query_map([limit as i64], from_row)?;
+        rows.collect()
+    }
+
+    pub fn remove(&self, id: &str) -> rusqlite::Result<bool> {
+        Ok(self.conn().execute("DELETE FROM workspaces WHERE id = ?1", [id])? > 0)
+    }
+
+    /// Shallow-merges `update` into the workspace's settings; `null` values
+    /// remove keys. Returns the merged settings, or `None` for an unknown id.
+    pub fn update_settings(&self, id: &str, update: Map<String, Value>) -> rusqlite::Result<Option<Value>> {
+        let conn = self.conn();
+        let current: Option<String> = conn
+            .query_row("SELECT settings_json FROM workspaces WHERE id = ?1", [id], |row| row.get(0))
+            .optional()?;
+        let mut settings = match current {
+            Some(json) => serde_json::from_str::<Map<String, Value>>(&json).unwrap_or_default(),
+            None => return Ok(None),
+        };
+        for (key, value) in update {
+            if value.is_null() {
+                settings.remove(&key);
+            } else {
+                settings.insert(key, value);
+            }
+        }
+        let settings = Value::Object(settings);
+        conn.execute(
+            "UPDATE workspaces SET settings_json = ?2 WHERE id = ?1",
+            params![id, settings.to_string()],
+        )?;
+        Ok(Some(settings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reuses_workspace_for_same_roots() {
+        let store = WorkspaceStore::new(":memory:").unwrap();
+        let roots = vec!["/work/app".to_string(), "/work/lib".to_string()];
+        let first = store.touch(&roots, None).unwrap();
+        assert_eq!(first.name, "app");
+        store.touch(&["/work/other".to_string()], None).unwrap();
+
+        let mut update = Map::new();
+        update.insert("tabSize".into(), 2.into());
+        store.update_settings(&first.id, update).unwrap();
+        let again = store.touch(&roots, Some("App")).unwrap();
+        assert_eq!((again.id.as_str(), again.name.as_str()), (first.id.as_str(), "App"));
+        assert_eq!(again.settings["tabSize"], 2);
+        assert_eq!(store.recent(10).unwrap().len(), 2);
+
+        let mut update = Map::new();
+        update.insert("tabSize".into(), Value::Null);
+        assert_eq!(store.update_settings(&first.id, update).unwrap(), Some(Value::Object(Map::new())));
+        assert_eq!(store.update_settings("missing", Map::new()).unwrap(), None);
+
+        store.conn().execute("UPDATE workspaces SET roots_json = 'oops' WHERE id = ?1", [&first.id]).unwrap();
+        assert!(store.recent(10).is_err());
+        assert!(store.get(&first.id).is_err());
+    }
+}
@@ -12,7 +12,7 @@ import { MenuBar, MenuItem } from './components/MenuBar';
 import { toolRegistry } from './ai/ToolRegistry';
 import { aiOrchestrator } from './ai/Orchestrator';
 import { ipc } from './lib/ipc';
-import { hopMemoryLoadProject, hopMemorySaveProject } from './lib/hopMemory';
+import { hopMemoryLoadProject, hopMemorySaveProject, hopMemorySearch } from './lib/hopMemory';
 import { useHopWorkspace } from './hooks/useHopWorkspace';
 import { useEditor } from './hooks/useEditor';
 import { useTerminal } from './hooks/useTerminal';
@@ -27,6 +27,8 @@ function App() {
     isWorkspaceOpen,
     projectNotes,
     openWorkspace,
+    openRecentWorkspace,
+    recentWorkspaces,
     handleNotesChange,
     handleNotesBlur,
     listDir,
@@ -109,6 +111,27 @@ function App() {
         return { ok: true, items };
       }
     });
+
+    toolRegistry.register({
+      name: 'hop.memory.search',
+      description: 'Find the project memory items most relevant to a query, best first',
+      parameters: {
+        type: 'object',
+        required: ['query'],
+        properties: {
+          query: { type: 'string' },
+          limit: { type: 'number' },
+          mode: { type: 'string', enum: ['text', 'semantic'] }
+        }
+      },
+      execute: async ({ query, limit, mode }: { query: string; limit?: number; mode?: 'text' | 'semantic' }) => {
+        if (!workspaceRoot) {
+          throw new Error('No workspace is open.');
+        }
+        const items = await hopMemorySearch({ kind: 'project', projectId: workspaceRoot }, query, { limit, mode });
+        return { ok: true, items };
+      }
+    });
   }, [workspaceRoot, activeTerminalId]);
 
   // Auto-spawn first terminal
@@ -281,6 +304,23 @@ function App() {
               Open
             </button>
           </div>
+
+          {recentWorkspaces.length > 0 && (
+            <div className="flex flex-col gap-1 mt-4">
+              <span className="text-gold-dim text-xs uppercase tracking-wide">Recent</span>
+              {recentWorkspaces.map(workspace => (
+                <button
+                  key={workspace.id}
+                  className="flex justify-between gap-4 text-left px-3 py-1 rounded hover:bg-surface text-gold-light"
+                  title={workspace.roots.join('\n')}
+                  onClick={() => openRecentWorkspace(workspace.id)}
+                >
+                  <span className="truncate">{workspace.name}</span>
+                  <span className="truncate text-gold-dim text-xs self-center">{workspace.roots[0]}</span>
+                </button>
+              ))}
+            </div>
+          )}
         </div>
         <p className="mt-8 text-gold-dim text-sm opacity-60">HopCoder AI Native IDE</p>
       </div>
@@ -1,5 +1,7 @@
 import type {
+  HopFsApplyEditsResponse,
   HopFsReadResponse,
+  HopFsStatResponse,
   HopFsWriteResponse,
   HopWorkspaceEntry,
   HopWorkspaceListResponse,
@@ -73,25 +75,6 @@ function byteLength(text: string): number {
   return utf8Encoder.encode(text).length;
 }
 
-function truncateUtf8(text: string, maxBytes: number): { text: string; truncated: boolean } {
-  if (maxBytes <= 0) {
-    return { text: '', truncated: text.length > 0 };
-  }
-  let bytes = 0;
-  let output = '';
-  let truncated = false;
-  for (const char of text) {
-    const charBytes = utf8Encoder.encode(char).length;
-    if (bytes + charBytes > maxBytes) {
-      truncated = true;
-      break;
-    }
-    output += char;
-    bytes += charBytes;
-  }
-  return { text: output, truncated };
-}
-
 function isHiddenPath(relPath: string): boolean {
   const normalized = normalizeSlashes(relPath);
   if (normalized === '.' || !normalized.length) {
@@ -113,14 +96,21 @@ function getBaseName(relPath: string): string {
 }
 
 async function listDirectoryEntries(path: string): Promise<HopWorkspaceEntry[]> {
-  const resp = await ipc.send<HopWorkspaceListResponse>({
-    type: 'workspace.list',
-    root: path,
-  });
-  if (!resp.ok || !resp.entries) {
-    throw new Error(resp.error || 'Failed to list directory.');
-  }
-  return resp.entries;
+  const entries: HopWorkspaceEntry[] = [];
+  let cursor: string | undefined;
+  do {
+    const resp = await ipc.send<HopWorkspaceListResponse>({
+      type: 'workspace.list',
+      root: path,
+      cursor,
+    });
+    if (!resp.ok || !resp.entries) {
+      throw new Error(resp.error || 'Failed to list directory.');
+    }
+    entries.push(...resp.entries);
+    cursor = resp.nextCursor;
+  } while (cursor);
+  return entries;
 }
 
 function escapeRegExp(value: string): string {
@@ -180,18 +170,21 @@ function registerFsReadTool() {
     name: 'fs.read',
     description: spec?.description ?? 'Read a UTF-8 file from the workspace.',
     parameters: spec?.input_schema ?? {},
-    async execute({ path, max_bytes }: { path: string; max_bytes?: number }) {
+    async execute({ path, max_bytes, offset }: { path: string; max_bytes?: number; offset?: number }) {
       try {
         const absPath = resolveWorkspacePath(path);
-        const resp = await ipc.send<HopFsReadResponse>({ type: 'fs.read', path: absPath });
+        const resp = await ipc.send<HopFsReadResponse>({ type: 'fs.read', path: absPath, maxBytes: max_bytes, offset });
         if (!resp.ok || typeof resp.content !== 'string') {
           return { ok: false, error: resp.error || 'Unable to read file.' };
         }
-        if (typeof max_bytes === 'number' && max_bytes > 0) {
-          const { text, truncated } = truncateUtf8(resp.content, max_bytes);
-          return { ok: true, content: text, truncated };
+        if (resp.encoding === 'base64') {
+          return { ok: false, error: 'File appears to be binary.' };
         }
-        return { ok: true, content: resp.content, truncated: false };
+        const truncated = resp.truncated ?? false;
+        if (truncated && resp.offset !== undefined && resp.length !== undefined) {
+          return { ok: true, content: resp.content, truncated, next_offset: resp.offset + resp.length };
+        }
+        return { ok: true, content: resp.content, truncated, hash: resp.hash };
       } catch (err: any) {
         return { ok: false, error: err?.message || String(err) };
       }
@@ -199,15 +192,6 @@ function registerFsReadTool() {
   });
 }
 
-async function tryReadFileContent(absPath: string) {
-  try {
-    const resp = await ipc.send<HopFsReadResponse>({ type: 'fs.read', path: absPath });
-    return resp;
-  } catch (err: any) {
-    return { ok: false, error: err?.message || String(err) };
-  }
-}
-
 function registerFsWriteTool() {
   const spec = specsByName.get('fs.write');
   toolRegistry.register({
@@ -220,17 +204,22 @@ function registerFsWriteTool() {
       create_if_missing = true,
       append = false,
       overwrite = true,
+      expected_hash,
     }: {
       path: string;
       content: string;
       create_if_missing?: boolean;
       append?: boolean;
       overwrite?: boolean;
+      expected_hash?: string;
     }) {
       try {
         const absPath = resolveWorkspacePath(path);
-        const existing = await tryReadFileContent(absPath);
-        const exists = existing.ok && typeof existing.content === 'string';
+        const stat = await ipc.send<HopFsStatResponse>({ type: 'fs.stat', path: absPath });
+        if (!stat.ok) {
+          return { ok: false, error: stat.error || 'Unable to check file.' };
+        }
+        const exists = stat.exists === true;
 
         if (!exists && !create_if_missing) {
           return { ok: false, error: 'File does not exist and create_if_missing is false.' };
@@ -239,26 +228,55 @@ function registerFsWriteTool() {
           return { ok: false, error: 'File exists and overwrite is false.' };
         }
 
-        let finalContent = content;
-        if (append) {
-          const base = exists ? existing.content || '' : '';
-          if (!exists && !create_if_missing) {
-            return { ok: false, error: 'Cannot append because the file does not exist.' };
-          }
-          finalContent = `${base}${content}`;
-        }
-
+        // Appending happens in the backend, which keeps the file's encoding
+        // and refuses binary files.
         const resp = await ipc.send<HopFsWriteResponse>({
           type: 'fs.write',
           path: absPath,
-          content: finalContent,
+          content,
+          createIfMissing: create_if_missing,
+          append,
+          expectedHash: expected_hash,
         });
 
         if (!resp.ok) {
           return { ok: false, error: resp.error || 'Failed to write file.' };
         }
 
-        return { ok: true, bytes_written: byteLength(finalContent) };
+        return { ok: true, bytes_written: resp.bytesWritten ?? byteLength(content) };
+      } catch (err: any) {
+        return { ok: false, error: err?.message || String(err) };
+      }
+    },
+  });
+}
+
+function registerFsApplyPatchTool() {
+  const spec = specsByName.get('fs.applyPatch');
+  toolRegistry.register({
+    name: 'fs.applyPatch',
+    description: spec?.description ?? 'Apply a unified diff to workspace files.',
+    parameters: spec?.input_schema ?? {},
+    async execute({ patch, dry_run = false }: { patch: string; dry_run?: boolean }) {
+      try {
+        const resp = await ipc.send<HopFsApplyEditsResponse>({
+          type: 'fs.applyEdits',
+          root: ensureWorkspaceRoot(),
+          patch,
+          dryRun: dry_run,
+        });
+
+        const files = (resp.files ?? []).map((file) => ({
+          path: file.path,
+          ok: file.ok,
+          hash: file.hash,
+          failed_hunks: file.hunks.filter((hunk) => !hunk.ok).map((hunk) => hunk.index),
+          error: file.error,
+        }));
+        if (!resp.ok) {
+          return { ok: false, files, error: resp.error || 'Failed to apply patch.' };
+        }
+        return { ok: true, files };
       } catch (err: any) {
         return { ok: false, error: err?.message || String(err) };
       }
@@ -452,5 +470,6 @@ function registerFsSearchTool() {
 
 registerFsReadTool();
 registerFsWriteTool();
+registerFsApplyPatchTool();
 registerFsListTool();
 registerFsSearchTool();
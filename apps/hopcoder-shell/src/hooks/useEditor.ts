@@ -1,5 +1,5 @@
 import { useState, useCallback } from 'react';
-import { HopFsReadResponse } from '@proto/ipc';
+import { HopFsEncoding, HopFsReadResponse, HopFsWriteResponse, HopGenericErrorResponse } from '@proto/ipc';
 import { ipc } from '../lib/ipc';
 
 export interface EditorTab {
@@ -7,6 +7,10 @@ export interface EditorTab {
   content: string;
   isDirty: boolean;
   language: string;
+  /** Encoding the file was read in, so saving writes it back the same way */
+  encoding?: HopFsEncoding;
+  /** Hash of the content on disk when last read or saved */
+  hash?: string;
 }
 
 export function useEditor(rootPath?: string) {
@@ -38,7 +42,9 @@ export function useEditor(rootPath?: string) {
         path,
         content: resp.content,
         isDirty: false,
-        language: getLanguageFromPath(path)
+        language: getLanguageFromPath(path),
+        encoding: resp.encoding,
+        hash: resp.hash
       };
       setTabs(prev => [...prev, newTab]);
       setActiveFilePath(path);
@@ -79,10 +85,28 @@ export function useEditor(rootPath?: string) {
 
     const tab = tabs.find(t => t.path === targetPath);
     if (tab) {
-      await ipc.send({ type: 'fs.write', path: targetPath, content: tab.content, root: rootPath });
+      const write = (expectedHash?: string) =>
+        ipc.send<HopFsWriteResponse | HopGenericErrorResponse>({
+          type: 'fs.write',
+          path: targetPath,
+          content: tab.content,
+          root: rootPath,
+          encoding: tab.encoding,
+          expectedHash,
+        });
+      let resp = await write(tab.hash);
+      if (resp.type === 'error' && resp.code === 'conflict') {
+        if (!window.confirm(`${targetPath} changed on disk since it was opened. Overwrite it?`)) return;
+        resp = await write();
+      }
+      if (!resp.ok) {
+        console.error('Failed to save file', resp.error);
+        return;
+      }
+      const hash = resp.type === 'fs.write' ? resp.hash : undefined;
       setTabs(prev => prev.map(t => {
         if (t.path === targetPath) {
-          return { ...t, isDirty: false };
+          return { ...t, isDirty: false, hash };
         }
         return t;
       }));
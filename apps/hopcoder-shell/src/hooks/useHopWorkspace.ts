@@ -1,7 +1,14 @@
-import { useState, useCallback } from 'react';
+import { useState, useCallback, useEffect } from 'react';
 import { open } from '@tauri-apps/api/dialog';
-import { HopWorkspaceListResponse, HopWorkspaceEntry } from '@proto/ipc';
-import { hopMemoryLoadProject, hopMemorySaveProject } from '../lib/hopMemory';
+import {
+  HopEvent,
+  HopWorkspaceEntry,
+  HopWorkspaceInfo,
+  HopWorkspaceListResponse,
+  HopWorkspaceOpenResponse,
+  HopWorkspaceRecentResponse,
+} from '@proto/ipc';
+import { hopMemoryGet, hopMemoryUpsert } from '../lib/hopMemory';
 import { ipc } from '../lib/ipc';
 import { setFsToolsWorkspaceRoot } from '../ai/registerFsTools';
 
@@ -20,38 +27,38 @@ export function useHopWorkspace() {
   const [entries, setEntries] = useState<HopWorkspaceEntry[]>([]);
   const [isWorkspaceOpen, setIsWorkspaceOpen] = useState(false);
   const [projectNotes, setProjectNotes] = useState('');
+  const [recentWorkspaces, setRecentWorkspaces] = useState<HopWorkspaceInfo[]>([]);
 
   // Debounced save function
   const debouncedSaveNotes = useCallback(
     debounce((root: string, notes: string) => {
-      hopMemorySaveProject(root, 'project_notes', notes).catch(console.error);
+      hopMemoryUpsert({ kind: 'project', projectId: root }, 'project_notes', notes).catch(console.error);
     }, 500),
     []
   );
 
   const loadWorkspaceFolder = async (root: string): Promise<HopWorkspaceEntry[]> => {
-    const resp = await ipc.send<HopWorkspaceListResponse>({ type: 'workspace.list', root });
-    if (resp.ok && resp.entries) {
-      return resp.entries;
-    }
-    return [];
+    const entries: HopWorkspaceEntry[] = [];
+    let cursor: string | undefined;
+    do {
+      const resp = await ipc.send<HopWorkspaceListResponse>({ type: 'workspace.list', root, cursor });
+      if (!resp.ok || !resp.entries) break;
+      entries.push(...resp.entries);
+      cursor = resp.nextCursor;
+    } while (cursor);
+    return entries;
   };
 
-  const openWorkspace = async (root: string) => {
-    // Check if it's a .code-workspace file
-    if (root.endsWith('.code-workspace')) {
-      // TODO: Parse .code-workspace file
-      // For now, just treat the parent dir as root
-      // const content = await ipc.send({ type: 'fs.read', path: root });
-    }
-
+  // Shows a workspace the backend has just opened: the primary root's
+  // entries, followed by any extra roots as top-level folders.
+  const showWorkspace = async (workspace: HopWorkspaceInfo) => {
+    const [root, ...extraRoots] = workspace.roots;
     const folderEntries = await loadWorkspaceFolder(root);
     
     // Load project notes FIRST to avoid overwriting with empty state
     let loadedNotes = '';
     try {
-      const items = await hopMemoryLoadProject(root);
-      const noteItem = items.find(i => i.key === 'project_notes');
+      const noteItem = await hopMemoryGet({ kind: 'project', projectId: root }, 'project_notes');
       if (noteItem) {
         loadedNotes = JSON.parse(noteItem.valueJson);
       }
@@ -62,12 +69,70 @@ export function useHopWorkspace() {
     setProjectNotes(loadedNotes);
     setWorkspaceRoot(root);
     setFsToolsWorkspaceRoot(root);
-    setWorkspaceFolders([root]);
-    setEntries(folderEntries);
+    setWorkspaceFolders(workspace.roots);
+    setEntries([...folderEntries, ...extraRoots.map((path): HopWorkspaceEntry => ({ path, kind: 'dir' }))]);
     setIsWorkspaceOpen(true);
-    ipc.send({ type: 'workspace.open', root });
+    for (const watchRoot of workspace.roots) {
+      ipc.send({ type: 'workspace.watch', root: watchRoot }).catch(console.error);
+    }
   };
 
+  const openWorkspace = async (root: string) => {
+    // Check if it's a .code-workspace file
+    if (root.endsWith('.code-workspace')) {
+      // TODO: Parse .code-workspace file
+      // For now, just treat the parent dir as root
+      // const content = await ipc.send({ type: 'fs.read', path: root });
+    }
+
+    const resp = await ipc.send<HopWorkspaceOpenResponse>({ type: 'workspace.open', root });
+    if (!resp.ok || !resp.workspace) {
+      console.error('Failed to open workspace', resp.error);
+      return;
+    }
+    await showWorkspace(resp.workspace);
+  };
+
+  const openRecentWorkspace = async (id: string) => {
+    const resp = await ipc.send<HopWorkspaceOpenResponse>({ type: 'workspace.open', id });
+    if (!resp.ok || !resp.workspace) {
+      console.error('Failed to open workspace', resp.error);
+      return;
+    }
+    await showWorkspace(resp.workspace);
+  };
+
+  useEffect(() => {
+    if (isWorkspaceOpen) return;
+    ipc
+      .send<HopWorkspaceRecentResponse>({ type: 'workspace.recent' })
+      .then(resp => setRecentWorkspaces(resp.workspaces ?? []))
+      .catch(console.error);
+  }, [isWorkspaceOpen]);
+
+  // Reload the top-level entries when something directly under the watched
+  // root appears, disappears or is renamed.
+  useEffect(() => {
+    if (!workspaceRoot) return;
+    const parentOf = (path: string) => path.replace(/[\\/][^\\/]*$/, '');
+    const unlisten = ipc.onEvent((evt: HopEvent) => {
+      let paths: string[];
+      if (evt.type === 'fs.created' || evt.type === 'fs.deleted') {
+        paths = evt.paths;
+      } else if (evt.type === 'fs.renamed') {
+        paths = evt.renames.flatMap(r => [r.from, r.to]);
+      } else {
+        return;
+      }
+      if (paths.some(p => parentOf(p) === evt.root)) {
+        loadWorkspaceFolder(workspaceRoot).then(setEntries).catch(console.error);
+      }
+    });
+    return () => {
+      unlisten.then(fn => fn());
+    };
+  }, [workspaceRoot]);
+
   const browseForWorkspace = async () => {
     try {
       const selected = await open({
@@ -86,17 +151,16 @@ export function useHopWorkspace() {
 
   const addWorkspaceFolder = async (path: string) => {
     if (workspaceFolders.includes(path)) return;
-    
-    const newEntries = await loadWorkspaceFolder(path);
-    // If we already have multiple folders, we might need a better structure
-    // For now, let's just append to entries, but this is tricky for the tree view
-    // Ideally, we should switch to a multi-root structure in the Sidebar
-    
+
+    const resp = await ipc.send({ type: 'workspace.updateRoots', add: [path] });
+    if (!resp.ok) {
+      console.error('Failed to add workspace folder', resp.error);
+      return;
+    }
+    ipc.send({ type: 'workspace.watch', root: path }).catch(console.error);
+
+    // Extra roots show up as top-level folders in the Sidebar.
     setWorkspaceFolders(prev => [...prev, path]);
-    // Re-fetching all might be needed or just appending
-    // But Sidebar expects a flat list of top-level items
-    // If we have multiple roots, we should probably wrap them in "Folder" entries
-    
     const rootEntry: HopWorkspaceEntry = {
       path: path,
       kind: 'dir'
@@ -114,19 +178,16 @@ export function useHopWorkspace() {
 
   const handleNotesBlur = () => {
     if (workspaceRoot) {
-      hopMemorySaveProject(workspaceRoot, 'project_notes', projectNotes).catch(console.error);
+      hopMemoryUpsert({ kind: 'project', projectId: workspaceRoot }, 'project_notes', projectNotes).catch(console.error);
     }
   };
 
-  const listDir = async (path: string) => {
-    const resp = await ipc.send<HopWorkspaceListResponse>({ type: 'workspace.list', root: path });
-    if (resp.ok && resp.entries) {
-      return resp.entries;
-    }
-    return [];
-  };
+  const listDir = (path: string) => loadWorkspaceFolder(path);
 
   const closeWorkspace = () => {
+    if (workspaceRoot) {
+      ipc.send({ type: 'workspace.close' }).catch(console.error);
+    }
     setIsWorkspaceOpen(false);
     setEntries([]);
     setWorkspaceFolders([]);
@@ -143,6 +204,8 @@ export function useHopWorkspace() {
     setIsWorkspaceOpen,
     projectNotes,
     openWorkspace,
+    openRecentWorkspace,
+    recentWorkspaces,
     handleNotesChange,
     handleNotesBlur,
     listDir,
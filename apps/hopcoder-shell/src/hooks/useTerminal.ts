@@ -1,5 +1,5 @@
-import { useState, useEffect, useCallback } from 'react';
-import { HopEvent } from '@proto/ipc';
+import { useState, useEffect, useCallback, useRef } from 'react';
+import { HopEvent, HopTerminalAttachResponse, HopTerminalListResponse } from '@proto/ipc';
 import { ipc } from '../lib/ipc';
 
 export interface TerminalInstance {
@@ -8,23 +8,60 @@ export interface TerminalInstance {
   output: string;
 }
 
+function decodeBase64(data: string): Uint8Array {
+  const binary = atob(data);
+  const bytes = new Uint8Array(binary.length);
+  for (let i = 0; i < binary.length; i++) {
+    bytes[i] = binary.charCodeAt(i);
+  }
+  return bytes;
+}
+
 export function useTerminal() {
   const [terminals, setTerminals] = useState<TerminalInstance[]>([]);
   const [activeTerminalId, setActiveTerminalId] = useState<string | null>(null);
+  // Per-terminal streaming decoders keep multi-byte characters intact across chunks.
+  const decoders = useRef(new Map<string, TextDecoder>());
+  // Stream offset up to which output has already been rendered (e.g. via terminal.attach).
+  const offsets = useRef(new Map<string, number>());
+
+  const appendOutput = useCallback((id: string, bytes: Uint8Array) => {
+    let decoder = decoders.current.get(id);
+    if (!decoder) {
+      decoder = new TextDecoder();
+      decoders.current.set(id, decoder);
+    }
+    const text = decoder.decode(bytes, { stream: true });
+    setTerminals(prev => prev.map(t => (t.id === id ? { ...t, output: t.output + text } : t)));
+  }, []);
 
   useEffect(() => {
     const subscribe = async () =>
       ipc.onEvent((evt: HopEvent) => {
         if (evt.type === 'terminal.data') {
-          setTerminals(prev => prev.map(t => {
-            if (t.id === evt.id) {
-              return { ...t, output: t.output + evt.data };
-            }
-            return t;
-          }));
+          const seen = offsets.current.get(evt.id) ?? 0;
+          const bytes = decodeBase64(evt.data);
+          if (evt.offset + bytes.length <= seen) return;
+          offsets.current.set(evt.id, evt.offset + bytes.length);
+          appendOutput(evt.id, bytes.subarray(Math.max(0, seen - evt.offset)));
         }
       });
     subscribe();
+  }, [appendOutput]);
+
+  // Re-attach to terminals that survived a frontend reload.
+  useEffect(() => {
+    const restore = async () => {
+      const resp = await ipc.send<HopTerminalListResponse>({ type: 'terminal.list' });
+      const running = (resp.terminals ?? []).filter(t => t.state === 'running');
+      for (const [i, t] of running.entries()) {
+        await attachTerminal(t.id, `Terminal ${i + 1}`);
+      }
+      if (running.length > 0) {
+        setActiveTerminalId(running[running.length - 1].id);
+      }
+    };
+    restore();
   }, []);
 
   const createTerminal = async () => {
@@ -35,6 +72,17 @@ export function useTerminal() {
     await ipc.send({ type: 'terminal.spawn', id });
   };
 
+  const attachTerminal = async (id: string, title: string) => {
+    const resp = await ipc.send<HopTerminalAttachResponse>({ type: 'terminal.attach', id });
+    if (!resp.ok || resp.data === undefined || resp.offset === undefined) return;
+    const bytes = decodeBase64(resp.data);
+    decoders.current.set(id, new TextDecoder());
+    offsets.current.set(id, resp.offset + bytes.length);
+    // Scrollback replaces whatever was rendered before the reload.
+    setTerminals(prev => [...prev.filter(t => t.id !== id), { id, title, output: '' }]);
+    appendOutput(id, bytes);
+  };
+
   const closeTerminal = async (id: string) => {
     await ipc.send({ type: 'terminal.kill', id, signal: 'SIGKILL' });
     setTerminals(prev => {
@@ -57,6 +105,7 @@ export function useTerminal() {
     activeTerminalId,
     setActiveTerminalId,
     createTerminal,
+    attachTerminal,
     closeTerminal,
     writeToTerminal
   };
@@ -36,3 +36,125 @@ export async function hopMemoryLoadProject(projectId: string): Promise<HopMemory
   });
   return items;
 }
+
+export async function hopMemoryLoadSession(sessionId: string): Promise<HopMemoryItem[]> {
+  return invoke<HopMemoryItem[]>('hop_memory_load_session', {
+    args: { sessionId },
+  });
+}
+
+/** Whose memory a call addresses */
+export type HopMemoryScope =
+  | { kind: 'project'; projectId: string }
+  | { kind: 'session'; sessionId: string };
+
+/** Narrows hopMemoryQuery / hopMemoryListKeys; omitted fields match everything */
+export interface HopMemoryFilter {
+  prefix?: string;
+  /** SQLite JSON path into the value, e.g. `$.tags[0]`; items without it are left out */
+  path?: string;
+  /** Value the item must have at `path` */
+  equals?: unknown;
+}
+
+/** The item stored under `key`, or null */
+export async function hopMemoryGet(scope: HopMemoryScope, key: string): Promise<HopMemoryItem | null> {
+  return invoke<HopMemoryItem | null>('hop_memory_get', {
+    args: { ...scope, key },
+  });
+}
+
+/** Stores `value` under `key`, replacing the previous value. Returns the item id. */
+export async function hopMemoryUpsert(
+  scope: HopMemoryScope,
+  key: string,
+  value: unknown,
+  ttlSeconds?: number,
+): Promise<string> {
+  const result = await invoke<{ id: string }>('hop_memory_upsert', {
+    args: { ...scope, key, value, ttlSeconds: ttlSeconds ?? null },
+  });
+  return result.id;
+}
+
+/** Deletes everything stored under `key`. Returns how many items were removed. */
+export async function hopMemoryDelete(scope: HopMemoryScope, key: string): Promise<number> {
+  const result = await invoke<{ deleted: number }>('hop_memory_delete', {
+    args: { ...scope, key },
+  });
+  return result.deleted;
+}
+
+export async function hopMemoryQuery(scope: HopMemoryScope, filter: HopMemoryFilter = {}): Promise<HopMemoryItem[]> {
+  return invoke<HopMemoryItem[]>('hop_memory_query', {
+    args: { ...scope, ...filter },
+  });
+}
+
+export async function hopMemoryListKeys(scope: HopMemoryScope, filter: HopMemoryFilter = {}): Promise<string[]> {
+  return invoke<string[]>('hop_memory_list_keys', {
+    args: { ...scope, ...filter },
+  });
+}
+
+/** Project ids that have memory stored */
+export async function hopMemoryListProjects(): Promise<string[]> {
+  return invoke<string[]>('hop_memory_list_projects');
+}
+
+/** A search result; higher `score` is more relevant, within one search */
+export interface HopMemorySearchHit extends HopMemoryItem {
+  score: number;
+}
+
+export interface HopMemorySearchOptions {
+  /** Defaults to 20 */
+  limit?: number;
+  /** `text` (default) ranks by full-text match on keys and values, `semantic` by embedding similarity */
+  mode?: 'text' | 'semantic';
+}
+
+/** The live items most relevant to `query`, best first */
+export async function hopMemorySearch(
+  scope: HopMemoryScope,
+  query: string,
+  options: HopMemorySearchOptions = {},
+): Promise<HopMemorySearchHit[]> {
+  return invoke<HopMemorySearchHit[]>('hop_memory_search', {
+    args: { ...scope, query, ...options },
+  });
+}
+
+export interface HopMemoryQuota {
+  maxRows: number;
+  /** Key plus JSON value, in bytes */
+  maxBytes: number;
+}
+
+export interface HopMemoryScopeUsage {
+  id: string;
+  rows: number;
+  bytes: number;
+}
+
+/**
+ * Memory usage. Each project and session is held to its quota by evicting
+ * the least recently used items; expired items are swept periodically.
+ */
+export interface HopMemoryStats {
+  rows: number;
+  bytes: number;
+  /** Expired items the next sweep will delete */
+  expiredRows: number;
+  fileBytes: number;
+  freeBytes: number;
+  walBytes: number;
+  /** Largest first */
+  projects: HopMemoryScopeUsage[];
+  sessions: HopMemoryScopeUsage[];
+  quotas: { project: HopMemoryQuota; session: HopMemoryQuota };
+}
+
+export async function hopMemoryStats(): Promise<HopMemoryStats> {
+  return invoke<HopMemoryStats>('hop_memory_stats');
+}
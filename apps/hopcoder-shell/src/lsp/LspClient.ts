@@ -1,5 +1,5 @@
 import { HopIpcClient } from '@proto/ipc-client';
-import type { HopEvent } from '@proto/ipc';
+import type { HopEvent, HopLspResponse } from '@proto/ipc';
 import * as monaco from 'monaco-editor';
 
 export class LspClient {
@@ -7,8 +7,10 @@ export class LspClient {
   private serverId: string;
   private languageId: string;
   private isInitialized = false;
-  private pendingRequests = new Map<number, (result: any) => void>();
   private nextId = 1;
+  /** Models we sent didOpen for; re-opened when the server comes back */
+  private openModels = new Set<monaco.editor.ITextModel>();
+  private wasDown = false;
 
   constructor(ipc: HopIpcClient, serverId: string, languageId: string) {
     this.ipc = ipc;
@@ -18,6 +20,10 @@ export class LspClient {
     this.ipc.onEvent((evt: HopEvent) => {
       if (evt.type === 'lsp.message' && evt.server === this.serverId) {
         this.handleMessage(evt.message);
+      } else if (evt.type === 'lsp.serverRequest' && evt.server === this.serverId) {
+        this.handleServerRequest(evt.id, evt.method, evt.params);
+      } else if (evt.type === 'lsp.status' && evt.server === this.serverId) {
+        this.handleStatus(evt.status);
       }
     });
   }
@@ -43,23 +49,15 @@ export class LspClient {
   }
 
   async sendRequest(method: string, params: any): Promise<any> {
+    // The shell matches the response to the request and replaces the id
     const id = this.nextId++;
     const payload = { jsonrpc: '2.0', id, method, params };
-    
-    return new Promise((resolve, reject) => {
-      this.pendingRequests.set(id, resolve);
-      this.ipc.send({ type: 'lsp.request', server: this.serverId, payload })
-        .then((resp) => {
-          if (!resp.ok) {
-            this.pendingRequests.delete(id);
-            reject(resp.error);
-          }
-        })
-        .catch((err) => {
-          this.pendingRequests.delete(id);
-          reject(err);
-        });
-    });
+    const resp = await this.ipc.send<HopLspResponse>({ type: 'lsp.request', server: this.serverId, payload });
+    if (!resp.ok) {
+      console.error(`[LSP:${this.serverId}] Error:`, resp.error);
+      throw new Error(resp.error);
+    }
+    return resp.result;
   }
 
   sendNotification(method: string, params: any) {
@@ -68,21 +66,39 @@ export class LspClient {
   }
 
   private handleMessage(msg: any) {
-    if (msg.id && this.pendingRequests.has(msg.id)) {
-      // Response to our request
-      const resolve = this.pendingRequests.get(msg.id);
-      this.pendingRequests.delete(msg.id);
-      if (msg.error) {
-        console.error(`[LSP:${this.serverId}] Error:`, msg.error);
-      } else {
-        resolve?.(msg.result);
-      }
-    } else if (msg.method) {
+    if (msg.method) {
       // Notification from server
       this.handleNotification(msg.method, msg.params);
     }
   }
 
+  // The shell replays `initialize` when it restarts a server, but the
+  // documents we had open are gone.
+  private handleStatus(status: string) {
+    if (status === 'running' && this.wasDown) {
+      this.wasDown = false;
+      this.isInitialized = true;
+      for (const model of this.openModels) {
+        if (model.isDisposed()) {
+          this.openModels.delete(model);
+        } else {
+          this.notifyOpen(model);
+        }
+      }
+    } else if (status !== 'running') {
+      this.wasDown = true;
+      this.isInitialized = false;
+    }
+  }
+
+  // We don't provide configuration or dynamic registration yet; answer so
+  // the server doesn't wait on us.
+  private handleServerRequest(id: number | string, method: string, params: any) {
+    const result = method === 'workspace/configuration' ? (params?.items ?? []).map(() => null) : null;
+    const payload = { jsonrpc: '2.0', id, result };
+    this.ipc.send({ type: 'lsp.request', server: this.serverId, payload }).catch(console.error);
+  }
+
   private handleNotification(method: string, params: any) {
     if (method === 'textDocument/publishDiagnostics') {
       const { uri, diagnostics } = params;
@@ -202,6 +218,7 @@ export class LspClient {
   // Document Sync
   
   notifyOpen(model: monaco.editor.ITextModel) {
+    this.openModels.add(model);
     this.sendNotification('textDocument/didOpen', {
       textDocument: {
         uri: model.uri.toString(),
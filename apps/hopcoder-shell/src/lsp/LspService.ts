@@ -1,4 +1,5 @@
 import { HopIpcClient } from '@proto/ipc-client';
+import type { HopLspServersResponse } from '@proto/ipc';
 import { LspClient } from './LspClient';
 import * as monaco from 'monaco-editor';
 
@@ -15,11 +16,12 @@ class LspService {
       return this.clients.get(languageId)!;
     }
 
-    // Map language ID to server ID (as defined in Rust lsp.rs)
-    const serverId = languageId === 'rust' ? 'rust' : languageId === 'typescript' ? 'typescript' : null;
+    // Pick the first configured server for this language that is installed
+    const resp = await this.ipc.send<HopLspServersResponse>({ type: 'lsp.servers', language: languageId });
+    const serverId = resp.servers?.find(s => s.available)?.id;
     
     if (!serverId) {
-      throw new Error(`No LSP server configured for ${languageId}`);
+      throw new Error(resp.error ?? `No LSP server available for ${languageId}`);
     }
 
     const client = new LspClient(this.ipc, serverId, languageId);
@@ -46,48 +46,323 @@ export type HopRequest =
   | HopFsReadRequest
   | HopFsWriteRequest
   | HopFsDeleteRequest
+  | HopFsRestoreRequest
+  | HopFsTrashListRequest
+  | HopFsRenameRequest
+  | HopFsCopyRequest
+  | HopFsMkdirRequest
+  | HopFsStatRequest
+  | HopFsApplyEditsRequest
   | HopFsSearchRequest
+  | HopFsGrepRequest
+  | HopFsGrepCancelRequest
   | HopWorkspaceOpenRequest
+  | HopWorkspaceCloseRequest
+  | HopWorkspaceUpdateRootsRequest
+  | HopWorkspaceRecentRequest
+  | HopWorkspaceForgetRequest
+  | HopWorkspaceSettingsRequest
   | HopWorkspaceListRequest
+  | HopWorkspaceWatchRequest
+  | HopWorkspaceUnwatchRequest
   | HopTerminalSpawnRequest
   | HopTerminalWriteRequest
   | HopTerminalResizeRequest
   | HopTerminalKillRequest
-  | HopLspRequest;
+  | HopTerminalAttachRequest
+  | HopTerminalListRequest
+  | HopTerminalInfoRequest
+  | HopLspRequest
+  | HopLspStopRequest
+  | HopLspRestartRequest
+  | HopLspServersRequest
+  | HopLspDiagnosticsRequest
+  | HopLspDefinitionRequest
+  | HopLspReferencesRequest
+  | HopLspHoverRequest
+  | HopLspSymbolsRequest;
+
+/** How file content is carried in `content`; binary data travels as base64 */
+export type HopFsEncoding = 'utf-8' | 'utf-16le' | 'utf-16be' | 'latin1' | 'base64';
 
 export interface HopFsReadRequest {
   type: 'fs.read';
+  /** Absolute, or relative to the workspace root */
   path: string;
+  /** Sandbox root; defaults to the open workspace */
   root?: string;
+  /** Byte offset to start reading at */
+  offset?: number;
+  /** Maximum number of bytes to read */
+  length?: number;
+  /** Soft limit as in the fs.read tool; the smaller of length/maxBytes wins */
+  maxBytes?: number;
+  /** Skip detection and decode as this encoding */
+  encoding?: HopFsEncoding;
 }
 
+/** Writes are atomic: content goes to a temp file that replaces the target, keeping its permissions. */
 export interface HopFsWriteRequest {
   type: 'fs.write';
   path: string;
   content: string;
   root?: string;
+  /** Encoding of `content`; defaults to 'utf-8'. Pass back what fs.read reported to round-trip a file. */
+  encoding?: HopFsEncoding;
+  /** Defaults to true; when false a missing file fails with code 'not_found' */
+  createIfMissing?: boolean;
+  /**
+   * Add `content` to the end of the file, in the file's own encoding. Binary
+   * files only take base64 content with encoding 'base64'. Unless
+   * expectedHash/expectedMtime is given, the append fails with 'conflict' if
+   * the file changes while it is being written.
+   */
+  append?: boolean;
+  /**
+   * `hash` from the fs.read this edit is based on. If the file changed since,
+   * the write fails with an `error` response whose code is 'conflict'.
+   */
+  expectedHash?: string;
+  /** Same as expectedHash, but compares `modifiedMs` */
+  expectedMtime?: number;
 }
 
+/**
+ * Moves the entry into HopCoder's trash (under the app data dir) rather than
+ * deleting it; see fs.restore. Trashed items are purged after 30 days, or
+ * oldest first once the trash exceeds 2 GiB.
+ */
 export interface HopFsDeleteRequest {
   type: 'fs.delete';
   path: string;
   root?: string;
+  /** Required for non-empty directories, which otherwise fail with code 'not_empty' */
+  recursive?: boolean;
+}
+
+/** Moves a trashed entry back; fails with code 'conflict' if its path is taken */
+export interface HopFsRestoreRequest {
+  type: 'fs.restore';
+  /** `trashId` from fs.delete or `id` from fs.trash.list */
+  id: string;
+  root?: string;
+}
+
+/** Lists trashed entries that were deleted from under `root` */
+export interface HopFsTrashListRequest {
+  type: 'fs.trash.list';
+  root?: string;
+}
+
+/**
+ * Moves or renames a file, directory or symlink (the link itself, never its
+ * target), creating missing parent directories.
+ */
+export interface HopFsRenameRequest {
+  type: 'fs.rename';
+  from: string;
+  to: string;
+  root?: string;
+  /**
+   * Replace an existing entry of the same kind at `to`; it is moved to the
+   * trash. Otherwise an existing `to` fails with code 'conflict'.
+   */
+  overwrite?: boolean;
+}
+
+/** Copies a file or directory tree; symlinks are copied as links */
+export interface HopFsCopyRequest {
+  type: 'fs.copy';
+  from: string;
+  to: string;
+  root?: string;
+  /**
+   * What to do when `to` exists. 'fail' (default) refuses before writing
+   * anything; 'skip' and 'overwrite' merge into existing directories and
+   * keep or replace existing files. Entries of a different kind are never
+   * replaced.
+   */
+  onConflict?: 'fail' | 'skip' | 'overwrite';
+}
+
+/**
+ * Creates a directory and any missing parents. Failures come back as an
+ * `error` response, with code 'conflict' if a file is in the way.
+ */
+export interface HopFsMkdirRequest {
+  type: 'fs.mkdir';
+  path: string;
+  root?: string;
+}
+
+/**
+ * Describes one entry. A missing path succeeds with `exists: false`; other
+ * failures come back as an `error` response with code 'io'.
+ */
+export interface HopFsStatRequest {
+  type: 'fs.stat';
+  path: string;
+  root?: string;
+}
+
+/** 0-based; `character` counts Unicode code points, like HopGrepMatch.column */
+export interface HopEditPosition {
+  line: number;
+  character: number;
+}
+
+export interface HopTextEdit {
+  range: { start: HopEditPosition; end: HopEditPosition };
+  newText: string;
+}
+
+/** Either range `edits` (all against the current content) or a single-file unified `diff` */
+export interface HopFileEdits {
+  path: string;
+  /** `hash` from fs.read; the whole request fails with code 'conflict' if the file changed */
+  expectedHash?: string;
+  edits?: HopTextEdit[];
+  diff?: string;
+}
+
+/**
+ * Applies edits to one or more files as a unit: if any hunk of any file
+ * fails, nothing is written. Diff hunks whose context moved are located
+ * near their stated line.
+ */
+export interface HopFsApplyEditsRequest {
+  type: 'fs.applyEdits';
+  root?: string;
+  files?: HopFileEdits[];
+  /** Multi-file unified diff (e.g. `git diff` output); `/dev/null` as the old file creates it */
+  patch?: string;
+  /** Check that everything applies without writing */
+  dryRun?: boolean;
 }
 
+/**
+ * File name search. Like fs.grep and workspace.list it skips `.git` and
+ * anything matched by `.gitignore`, `.ignore` or `.hopcoderignore`.
+ */
 export interface HopFsSearchRequest {
   type: 'fs.search';
   query: string;
   root?: string;
 }
 
+/**
+ * Content search. The response only acknowledges the search; matches stream
+ * in as `fs.grep.matches` events followed by one `fs.grep.done`, all tagged
+ * with the request message id as `searchId`.
+ */
+export interface HopFsGrepRequest {
+  type: 'fs.grep';
+  query: string;
+  root?: string;
+  /** Directory or file to search in, relative to the root; defaults to the root */
+  path?: string;
+  /** Treat `query` as a regular expression instead of plain text */
+  regex?: boolean;
+  caseSensitive?: boolean;
+  /** Only match where `query` is not part of a longer word */
+  wholeWord?: boolean;
+  /** Root-relative globs, e.g. '**\/*.ts' */
+  includeGlobs?: string[];
+  excludeGlobs?: string[];
+  /** Defaults to 1000 */
+  maxResults?: number;
+  /** Lines of context before and after each match (max 10) */
+  contextLines?: number;
+}
+
+export interface HopFsGrepCancelRequest {
+  type: 'fs.grep.cancel';
+  searchId: string;
+}
+
+/**
+ * Opens a workspace of one or more roots; the first root is the primary one.
+ * Paths in later requests default to the root that contains them. The
+ * workspace is remembered in the recent list together with its settings.
+ */
 export interface HopWorkspaceOpenRequest {
   type: 'workspace.open';
-  root: string;
+  /** Single-root shorthand for `roots: [root]` */
+  root?: string;
+  roots?: string[];
+  /** Reopen a workspace from workspace.recent instead of giving roots */
+  id?: string;
+  name?: string;
+}
+
+/** Closes the open workspace and stops its watchers */
+export interface HopWorkspaceCloseRequest {
+  type: 'workspace.close';
+}
+
+/** Adds or removes roots of the open workspace; the primary root stays */
+export interface HopWorkspaceUpdateRootsRequest {
+  type: 'workspace.updateRoots';
+  add?: string[];
+  remove?: string[];
+}
+
+/** Recently opened workspaces, most recent first */
+export interface HopWorkspaceRecentRequest {
+  type: 'workspace.recent';
+  /** Defaults to 20 */
+  limit?: number;
 }
 
+/** Drops a workspace from the recent list, settings included */
+export interface HopWorkspaceForgetRequest {
+  type: 'workspace.forget';
+  id: string;
+}
+
+/**
+ * Reads a workspace's settings (the open workspace by default). `update` is
+ * shallow-merged first; `null` values remove keys.
+ */
+export interface HopWorkspaceSettingsRequest {
+  type: 'workspace.settings';
+  id?: string;
+  update?: Record<string, unknown>;
+}
+
+/**
+ * Lists a directory in tree order (each directory followed by its children).
+ * Large listings are paged: pass the previous `nextCursor` back as `cursor`.
+ */
 export interface HopWorkspaceListRequest {
   type: 'workspace.list';
+  /** Directory to list */
   root: string;
+  /** Levels to descend; defaults to 1 (direct children only) */
+  depth?: number;
+  cursor?: string;
+  /** Page size; defaults to 1000 */
+  limit?: number;
+  /** Defaults to 'dirsFirst' */
+  sort?: 'dirsFirst' | 'name';
+  /** Also return entries hidden by ignore files, flagged `ignored` (not descended into) */
+  includeIgnored?: boolean;
+}
+
+/**
+ * Watches a directory inside the open workspace (the workspace root itself
+ * by default). Changes are debounced, filtered through the ignore files and
+ * delivered as fs.created / fs.modified / fs.deleted / fs.renamed events.
+ * Opening a different workspace stops watchers outside it.
+ */
+export interface HopWorkspaceWatchRequest {
+  type: 'workspace.watch';
+  root?: string;
+}
+
+export interface HopWorkspaceUnwatchRequest {
+  type: 'workspace.unwatch';
+  root?: string;
 }
 
 export interface HopTerminalSpawnRequest {
@@ -96,6 +371,18 @@ export interface HopTerminalSpawnRequest {
   id: string;
   /** Optional shell binary (e.g. /bin/bash, powershell.exe) */
   shell?: string;
+  /** Extra arguments passed to the shell */
+  args?: string[];
+  /** Working directory; relative paths resolve against the open workspace, which is also the default */
+  cwd?: string;
+  /** Environment overrides applied on top of the inherited environment */
+  env?: Record<string, string>;
+  /** Start from the environment a login shell would set up (PATH from profiles, etc.) */
+  loginShell?: boolean;
+  /** Initial PTY width in columns (defaults to 80) */
+  cols?: number;
+  /** Initial PTY height in rows (defaults to 24) */
+  rows?: number;
 }
 
 export interface HopTerminalWriteRequest {
@@ -114,15 +401,136 @@ export interface HopTerminalResizeRequest {
 export interface HopTerminalKillRequest {
   type: 'terminal.kill';
   id: string;
-  signal?: string;
+  /**
+   * SIGINT interrupts the foreground job; SIGTERM, SIGHUP and SIGKILL end the
+   * shell and every process it started. Defaults to SIGHUP.
+   */
+  signal?: 'SIGINT' | 'SIGTERM' | 'SIGHUP' | 'SIGKILL';
+}
+
+export interface HopTerminalAttachRequest {
+  type: 'terminal.attach';
+  /** Terminal to replay scrollback for, e.g. after a frontend reload */
+  id: string;
+}
+
+export interface HopTerminalListRequest {
+  type: 'terminal.list';
 }
 
+export interface HopTerminalInfoRequest {
+  type: 'terminal.info';
+  id: string;
+}
+
+/**
+ * Sends a JSON-RPC message to a language server, starting it on
+ * `initialize`. A request (a payload with `method` and `id`) is answered with
+ * the server's response: `result`, or `ok: false` with the JSON-RPC error
+ * object as `result`. The id is replaced with one unique per server. After
+ * `timeoutMs` the request is cancelled with `$/cancelRequest` and fails with
+ * code 'timeout'. Notifications, and responses to lsp.serverRequest events,
+ * are forwarded without waiting.
+ */
 export interface HopLspRequest {
   type: 'lsp.request';
-  /** LSP server identifier, e.g. "tsserver", "pyright" */
+  /** Server id from lsp.servers, e.g. "rust", "typescript" */
   server: string;
   /** Raw LSP payload (JSON-RPC) */
   payload: unknown;
+  /** Defaults to 30000 */
+  timeoutMs?: number;
+}
+
+/** Shuts a server down (`shutdown` then `exit`, killed if it hangs) */
+export interface HopLspStopRequest {
+  type: 'lsp.stop';
+  server: string;
+}
+
+/**
+ * Stops a server if running and starts it again with its current config,
+ * replaying its `initialize` handshake. Crashed servers are restarted
+ * automatically with backoff; this also revives one left down after
+ * crashing repeatedly.
+ */
+export interface HopLspRestartRequest {
+  type: 'lsp.restart';
+  server: string;
+}
+
+/**
+ * Lists the configured language servers: built-in defaults overlaid with
+ * `lsp.json` in the app config dir, then `.hopcoder/lsp.json` in the
+ * workspace root. Each file looks like
+ * `{ "servers": { "<id>": { command, args?, env?, languages?, globs?, initializationOptions?, rootMarkers? } } }`;
+ * a `null` entry removes a server.
+ */
+export interface HopLspServersRequest {
+  type: 'lsp.servers';
+  /** Only servers handling this editor language id */
+  language?: string;
+  /** Only servers handling this file; also resolves each server's `root` */
+  path?: string;
+}
+
+/**
+ * Fields shared by the high-level LSP requests below. The file is opened in
+ * its server (started and initialized if needed) or, if it changed on disk
+ * since, synced; files the editor has open are left to the editor.
+ * Positions are converted from the server's encoding, so they count code
+ * points like everywhere else. A missing server or a binary file fails
+ * with code 'unsupported', a missing file with 'not_found', a slow server
+ * with 'timeout'.
+ */
+export interface HopLspFileTarget {
+  path: string;
+  root?: string;
+  /** Server id; defaults to the first available server handling the file */
+  server?: string;
+}
+
+/**
+ * Latest `publishDiagnostics` per file. With a `path` the file is opened or
+ * synced first and fresh diagnostics are waited for (up to `timeoutMs`,
+ * default 5000); without one every file with cached diagnostics is listed.
+ */
+export interface HopLspDiagnosticsRequest {
+  type: 'lsp.diagnostics';
+  path?: string;
+  root?: string;
+  server?: string;
+  timeoutMs?: number;
+}
+
+export interface HopLspDefinitionRequest extends HopLspFileTarget {
+  type: 'lsp.definition';
+  position: HopEditPosition;
+}
+
+export interface HopLspReferencesRequest extends HopLspFileTarget {
+  type: 'lsp.references';
+  position: HopEditPosition;
+  /** Defaults to true */
+  includeDeclaration?: boolean;
+}
+
+export interface HopLspHoverRequest extends HopLspFileTarget {
+  type: 'lsp.hover';
+  position: HopEditPosition;
+}
+
+/**
+ * Symbols declared in `path`, flattened with `containerName` naming the
+ * parent. Without a `path`, workspace symbols matching `query` from
+ * `server` or every running server.
+ */
+export interface HopLspSymbolsRequest {
+  type: 'lsp.symbols';
+  path?: string;
+  query?: string;
+  root?: string;
+  server?: string;
 }
 
 /* ------------------------------------------------------------------ */
@@ -137,14 +545,41 @@ export type HopResponse =
   | HopFsReadResponse
   | HopFsWriteResponse
   | HopFsDeleteResponse
+  | HopFsRestoreResponse
+  | HopFsTrashListResponse
+  | HopFsRenameResponse
+  | HopFsCopyResponse
+  | HopFsMkdirResponse
+  | HopFsStatResponse
+  | HopFsApplyEditsResponse
   | HopFsSearchResponse
+  | HopFsGrepResponse
+  | HopFsGrepCancelResponse
   | HopWorkspaceOpenResponse
+  | HopWorkspaceCloseResponse
+  | HopWorkspaceUpdateRootsResponse
+  | HopWorkspaceRecentResponse
+  | HopWorkspaceForgetResponse
+  | HopWorkspaceSettingsResponse
   | HopWorkspaceListResponse
+  | HopWorkspaceWatchResponse
+  | HopWorkspaceUnwatchResponse
   | HopTerminalSpawnResponse
   | HopTerminalWriteResponse
   | HopTerminalResizeResponse
   | HopTerminalKillResponse
+  | HopTerminalAttachResponse
+  | HopTerminalListResponse
+  | HopTerminalInfoResponse
   | HopLspResponse
+  | HopLspStopResponse
+  | HopLspRestartResponse
+  | HopLspServersResponse
+  | HopLspDiagnosticsResponse
+  | HopLspDefinitionResponse
+  | HopLspReferencesResponse
+  | HopLspHoverResponse
+  | HopLspSymbolsResponse
   | HopGenericErrorResponse;
 
 /** Base success/failure discriminant */
@@ -158,6 +593,18 @@ export interface HopFsReadResponse extends HopBaseResponse {
   ok: boolean;
   /** File content on success */
   content?: string;
+  /** Detected (or requested) encoding of `content` */
+  encoding?: HopFsEncoding;
+  /** Byte range actually returned; ranged reads are trimmed to character boundaries */
+  offset?: number;
+  length?: number;
+  /** Total file size in bytes */
+  size?: number;
+  /** True if the file continues past offset + length */
+  truncated?: boolean;
+  /** SHA-256 (hex) of the file; only set when the whole file was read */
+  hash?: string;
+  modifiedMs?: number;
   /** Error message if ok === false */
   error?: string;
 }
@@ -165,12 +612,110 @@ export interface HopFsReadResponse extends HopBaseResponse {
 export interface HopFsWriteResponse extends HopBaseResponse {
   type: 'fs.write';
   ok: boolean;
+  /** For an append, only the bytes added */
+  bytesWritten?: number;
+  /** SHA-256 (hex) and mtime of the new content, for the next expectedHash/expectedMtime */
+  hash?: string;
+  modifiedMs?: number;
   error?: string;
 }
 
 export interface HopFsDeleteResponse extends HopBaseResponse {
   type: 'fs.delete';
   ok: boolean;
+  /** Pass to fs.restore to undo */
+  trashId?: string;
+  error?: string;
+}
+
+export interface HopFsRestoreResponse extends HopBaseResponse {
+  type: 'fs.restore';
+  ok: boolean;
+  /** Absolute path the entry was restored to */
+  path?: string;
+  error?: string;
+}
+
+export interface HopTrashEntry {
+  id: string;
+  /** Absolute path the entry was deleted from */
+  path: string;
+  kind: 'file' | 'dir' | 'symlink';
+  /** Total bytes of the files it contains */
+  size: number;
+  deletedAtMs: number;
+}
+
+export interface HopFsRenameResponse extends HopBaseResponse {
+  type: 'fs.rename';
+  ok: boolean;
+  /** Absolute destination path */
+  path?: string;
+  /** Set when an existing destination was replaced; pass to fs.restore */
+  trashId?: string;
+  error?: string;
+}
+
+export interface HopFsCopyResponse extends HopBaseResponse {
+  type: 'fs.copy';
+  ok: boolean;
+  /** Files and symlinks copied, and existing files left alone under 'skip' */
+  copied?: number;
+  skipped?: number;
+  error?: string;
+}
+
+export interface HopFsMkdirResponse extends HopBaseResponse {
+  type: 'fs.mkdir';
+  ok: boolean;
+  /** False if the directory already existed */
+  created?: boolean;
+  error?: string;
+}
+
+export interface HopFsStatResponse extends HopBaseResponse {
+  type: 'fs.stat';
+  ok: boolean;
+  /** A missing path is not an error: ok is true and exists is false */
+  exists?: boolean;
+  /** Describes a symlink itself rather than its target, as in workspace.list */
+  entry?: HopWorkspaceEntry;
+  error?: string;
+}
+
+export interface HopFsTrashListResponse extends HopBaseResponse {
+  type: 'fs.trash.list';
+  ok: boolean;
+  /** Newest first */
+  entries?: HopTrashEntry[];
+  error?: string;
+}
+
+export interface HopHunkResult {
+  /** Position of the edit or hunk in the request */
+  index: number;
+  ok: boolean;
+  /** 0-based line the hunk was applied at */
+  line?: number;
+  error?: string;
+}
+
+export interface HopFileEditResult {
+  path: string;
+  ok: boolean;
+  /** SHA-256 (hex) of the new content */
+  hash?: string;
+  hunks: HopHunkResult[];
+  /** 'conflict', 'not_found' or 'io' */
+  code?: string;
+  error?: string;
+}
+
+export interface HopFsApplyEditsResponse extends HopBaseResponse {
+  type: 'fs.applyEdits';
+  ok: boolean;
+  /** Per-file results, in request order (files first, then patch) */
+  files?: HopFileEditResult[];
   error?: string;
 }
 
@@ -181,18 +726,92 @@ export interface HopFsSearchResponse extends HopBaseResponse {
   error?: string;
 }
 
+export interface HopFsGrepResponse extends HopBaseResponse {
+  type: 'fs.grep';
+  ok: boolean;
+  searchId?: string;
+  error?: string;
+}
+
+export interface HopFsGrepCancelResponse extends HopBaseResponse {
+  type: 'fs.grep.cancel';
+  ok: boolean;
+  error?: string;
+}
+
 export interface HopWorkspaceOpenResponse extends HopBaseResponse {
   type: 'workspace.open';
   ok: boolean;
   /** Normalized root path, project metadata, etc. */
   workspaceRoot?: string;
+  workspace?: HopWorkspaceInfo;
+  error?: string;
+}
+
+export interface HopWorkspaceCloseResponse extends HopBaseResponse {
+  type: 'workspace.close';
+  ok: boolean;
+  error?: string;
+}
+
+export interface HopWorkspaceUpdateRootsResponse extends HopBaseResponse {
+  type: 'workspace.updateRoots';
+  ok: boolean;
+  workspace?: HopWorkspaceInfo;
+  error?: string;
+}
+
+export interface HopWorkspaceRecentResponse extends HopBaseResponse {
+  type: 'workspace.recent';
+  ok: boolean;
+  workspaces?: HopWorkspaceInfo[];
+  error?: string;
+}
+
+export interface HopWorkspaceForgetResponse extends HopBaseResponse {
+  type: 'workspace.forget';
+  ok: boolean;
+  error?: string;
+}
+
+export interface HopWorkspaceSettingsResponse extends HopBaseResponse {
+  type: 'workspace.settings';
+  ok: boolean;
+  settings?: Record<string, unknown>;
   error?: string;
 }
 
+export interface HopWorkspaceInfo {
+  id: string;
+  name: string;
+  /** Canonical roots; the first is the primary root */
+  roots: string[];
+  settings: Record<string, unknown>;
+  lastOpenedMs: number;
+}
+
 export interface HopWorkspaceListResponse extends HopBaseResponse {
   type: 'workspace.list';
   ok: boolean;
   entries?: HopWorkspaceEntry[];
+  /** Set when more entries follow */
+  nextCursor?: string;
+  /** True if the walk stopped at its entry cap; entries past it are missing from every page */
+  truncated?: boolean;
+  error?: string;
+}
+
+export interface HopWorkspaceWatchResponse extends HopBaseResponse {
+  type: 'workspace.watch';
+  ok: boolean;
+  /** Canonical path of the watched directory; events carry it as `root` */
+  root?: string;
+  error?: string;
+}
+
+export interface HopWorkspaceUnwatchResponse extends HopBaseResponse {
+  type: 'workspace.unwatch';
+  ok: boolean;
   error?: string;
 }
 
@@ -201,6 +820,14 @@ export interface HopWorkspaceEntry {
   kind: 'file' | 'dir' | 'symlink';
   size?: number;
   modified_ms?: number;
+  /** Symlink target as stored in the link */
+  target?: string;
+  /** Symlink whose target does not exist */
+  broken?: boolean;
+  hidden?: boolean;
+  ignored?: boolean;
+  executable?: boolean;
+  readonly?: boolean;
 }
 
 export interface HopTerminalSpawnResponse extends HopBaseResponse {
@@ -229,11 +856,158 @@ export interface HopTerminalKillResponse extends HopBaseResponse {
   error?: string;
 }
 
+export interface HopTerminalAttachResponse extends HopBaseResponse {
+  type: 'terminal.attach';
+  ok: boolean;
+  /** Retained scrollback, base64-encoded raw bytes */
+  data?: string;
+  /** Stream offset of the first replayed byte */
+  offset?: number;
+  error?: string;
+}
+
+export interface HopTerminalListResponse extends HopBaseResponse {
+  type: 'terminal.list';
+  ok: boolean;
+  /** Running terminals plus recently exited ones, oldest first */
+  terminals?: HopTerminalInfo[];
+  error?: string;
+}
+
+export interface HopTerminalInfoResponse extends HopBaseResponse {
+  type: 'terminal.info';
+  ok: boolean;
+  terminal?: HopTerminalInfo;
+  error?: string;
+}
+
+export interface HopTerminalInfo {
+  id: string;
+  pid?: number | null;
+  shell: string;
+  cwd?: string | null;
+  startedAtMs: number;
+  state: 'running' | 'exited';
+  exitCode?: number | null;
+  signal?: string | null;
+}
+
 export interface HopLspResponse extends HopBaseResponse {
   type: 'lsp.request';
   ok: boolean;
   /** Raw LSP JSON-RPC result or error object */
   result?: unknown;
+  /** 'timeout' when the server did not answer within timeoutMs */
+  code?: 'timeout';
+  error?: string;
+}
+
+export interface HopLspStopResponse extends HopBaseResponse {
+  type: 'lsp.stop';
+  ok: boolean;
+  error?: string;
+}
+
+export interface HopLspRestartResponse extends HopBaseResponse {
+  type: 'lsp.restart';
+  ok: boolean;
+  error?: string;
+}
+
+export interface HopLspServersResponse extends HopBaseResponse {
+  type: 'lsp.servers';
+  ok: boolean;
+  servers?: HopLspServerInfo[];
+  error?: string;
+}
+
+export interface HopLspServerInfo {
+  id: string;
+  command: string;
+  args: string[];
+  languages: string[];
+  globs: string[];
+  rootMarkers: string[];
+  /** The command was found on PATH */
+  available: boolean;
+  /** Resolved path of the command */
+  binary?: string;
+  running: boolean;
+  /** Project root found from `rootMarkers`, when listing for a `path` */
+  root?: string;
+}
+
+export interface HopLspRange {
+  start: HopEditPosition;
+  end: HopEditPosition;
+}
+
+export interface HopLspLocation {
+  /** Root-relative with `/` separators; absolute for files outside the root */
+  path: string;
+  range: HopLspRange;
+}
+
+export interface HopLspDiagnostic {
+  range: HopLspRange;
+  severity?: 'error' | 'warning' | 'information' | 'hint';
+  message: string;
+  source?: string;
+  code?: string;
+  /** Id of the server that reported it */
+  server: string;
+}
+
+export interface HopLspFileDiagnostics {
+  path: string;
+  diagnostics: HopLspDiagnostic[];
+}
+
+export interface HopLspSymbol {
+  name: string;
+  /** Lowercase LSP symbol kind, e.g. "function", "struct", "enum_member" */
+  kind: string;
+  detail?: string;
+  containerName?: string;
+  path: string;
+  /** Missing for workspace symbols the server resolves lazily */
+  range?: HopLspRange;
+}
+
+export interface HopLspDiagnosticsResponse extends HopBaseResponse {
+  type: 'lsp.diagnostics';
+  ok: boolean;
+  files?: HopLspFileDiagnostics[];
+  error?: string;
+}
+
+export interface HopLspDefinitionResponse extends HopBaseResponse {
+  type: 'lsp.definition';
+  ok: boolean;
+  locations?: HopLspLocation[];
+  error?: string;
+}
+
+export interface HopLspReferencesResponse extends HopBaseResponse {
+  type: 'lsp.references';
+  ok: boolean;
+  locations?: HopLspLocation[];
+  error?: string;
+}
+
+export interface HopLspHoverResponse extends HopBaseResponse {
+  type: 'lsp.hover';
+  ok: boolean;
+  /** Markdown; absent when there is nothing to show */
+  contents?: string;
+  range?: HopLspRange;
+  error?: string;
+}
+
+export interface HopLspSymbolsResponse extends HopBaseResponse {
+  type: 'lsp.symbols';
+  ok: boolean;
+  symbols?: HopLspSymbol[];
   error?: string;
 }
 
@@ -241,7 +1015,13 @@ export interface HopLspResponse extends HopBaseResponse {
 export interface HopGenericErrorResponse extends HopBaseResponse {
   type: 'error';
   ok: false;
-  /** Machine-readable code (optional but recommended) */
+  /**
+   * Machine-readable code (optional but recommended). Path sandbox failures
+   * use 'invalid_root', 'invalid_path' or 'outside_root'; writes use
+   * 'conflict', 'not_found' or 'io'; file operations add 'not_empty' and
+   * 'unsupported'; LSP requests use 'timeout', and the high-level ones
+   * 'unsupported' and 'not_found'.
+   */
   code?: string;
   /** Human-readable description */
   error: string;
@@ -254,28 +1034,121 @@ export interface HopGenericErrorResponse extends HopBaseResponse {
 export type HopEvent =
   | HopTerminalDataEvent
   | HopTerminalExitEvent
+  | HopFsGrepMatchesEvent
+  | HopFsGrepDoneEvent
+  | HopFsCreatedEvent
+  | HopFsModifiedEvent
+  | HopFsDeletedEvent
+  | HopFsRenamedEvent
   | HopLspMessageEvent
+  | HopLspServerRequestEvent
+  | HopLspStatusEvent
   | HopLogEvent;
 
 export interface HopTerminalDataEvent {
   type: 'terminal.data';
   id: string; // terminal id
-  data: string; // chunk of output
+  data: string; // base64-encoded raw PTY output
+  /** Stream offset of the first byte in `data`; lets clients drop chunks already replayed by terminal.attach */
+  offset: number;
 }
 
 export interface HopTerminalExitEvent {
   type: 'terminal.exit';
   id: string;
   code: number | null;
+  /** Signal delivered via terminal.kill that ended the shell, if any */
   signal?: string | null;
 }
 
+export interface HopGrepMatch {
+  /** Root-relative path with '/' separators */
+  path: string;
+  /** 0-based line index */
+  line: number;
+  /** 0-based character index within the line */
+  column: number;
+  /** Match length in characters */
+  length: number;
+  preview: string;
+  before: string[];
+  after: string[];
+}
+
+export interface HopFsGrepMatchesEvent {
+  type: 'fs.grep.matches';
+  searchId: string;
+  matches: HopGrepMatch[];
+}
+
+export interface HopFsGrepDoneEvent {
+  type: 'fs.grep.done';
+  searchId: string;
+  total: number;
+  /** True if the search stopped at maxResults */
+  truncated: boolean;
+  /** True if the search was stopped by fs.grep.cancel */
+  cancelled: boolean;
+}
+
+/** Absolute paths created since the last batch */
+export interface HopFsCreatedEvent {
+  type: 'fs.created';
+  root: string;
+  paths: string[];
+}
+
+export interface HopFsModifiedEvent {
+  type: 'fs.modified';
+  root: string;
+  paths: string[];
+}
+
+export interface HopFsDeletedEvent {
+  type: 'fs.deleted';
+  root: string;
+  paths: string[];
+}
+
+export interface HopFsRenamedEvent {
+  type: 'fs.renamed';
+  root: string;
+  renames: { from: string; to: string }[];
+}
+
+/** A notification from a language server */
 export interface HopLspMessageEvent {
   type: 'lsp.message';
   server: string;
   message: any; // JSON-RPC message
 }
 
+/**
+ * A request from a language server, e.g. `workspace/configuration`. Reply
+ * with an lsp.request whose payload is `{ jsonrpc: '2.0', id, result }`.
+ */
+export interface HopLspServerRequestEvent {
+  type: 'lsp.serverRequest';
+  server: string;
+  id: number | string;
+  method: string;
+  params: any;
+}
+
+/**
+ * Server lifecycle. 'crashed' is followed by an automatic restart (and
+ * 'running' once `initialize` has been replayed); 'failed' means the server
+ * was left down.
+ */
+export interface HopLspStatusEvent {
+  type: 'lsp.status';
+  server: string;
+  status: 'running' | 'crashed' | 'failed' | 'stopped';
+  /** Exit code, when the process exited */
+  code?: number | null;
+  error?: string | null;
+}
+
 export interface HopLogEvent {
   type: 'log';
   level: 'debug' | 'info' | 'warn' | 'error';